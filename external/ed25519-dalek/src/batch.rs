@@ -35,7 +35,7 @@ use rand::thread_rng;
 #[cfg(all(not(feature = "batch"), feature = "batch_deterministic"))]
 use rand_core;
 
-use sha2::Sha512;
+use blake2::Blake2b;
 
 use crate::errors::InternalError;
 use crate::errors::SignatureError;
@@ -241,9 +241,10 @@ pub fn verify_batch(
         .map(InternalSignature::try_from)
         .collect::<Result<Vec<_>, _>>()?;
 
-    // Compute H(R || A || M) for each (signature, public_key, message) triplet
+    // Compute H(R || A || M) for each (signature, public_key, message) triplet, using the same
+    // blake2b hash as `PublicKey::verify`.
     let hrams: Vec<Scalar> = (0..signatures.len()).map(|i| {
-        let mut h: Sha512 = Sha512::default();
+        let mut h: Blake2b = Blake2b::default();
         h.update(signatures[i].R.as_bytes());
         h.update(public_keys[i].as_bytes());
         h.update(&messages[i]);
@@ -1,6 +1,6 @@
 use crate::hexify;
 
-#[derive(Clone, PartialEq, Eq, Hash)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct BlockHash([u8; BlockHash::LEN]);
 
 hexify!(BlockHash, "block hash");
@@ -1,11 +1,79 @@
+#[cfg(feature = "node")]
+use crate::node::Header;
+
+#[cfg(feature = "node")]
+use crate::node::Wire;
+
+#[cfg(feature = "node")]
+use crate::blocks::{read_signature_and_work, write_signature_and_work};
+
+#[cfg(feature = "node")]
+use crate::bytes::Bytes;
+
+#[cfg(feature = "node")]
+use std::convert::TryFrom;
+
 use crate::blocks::BlockHash;
+use crate::keys::public::{from_address, to_address};
 use crate::{Public, Signature, Work};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ChangeBlock {
-    previous: BlockHash,
-    representative: Public,
+    /// The hash of the previous block in this account.
+    pub previous: BlockHash,
+
+    #[serde(serialize_with = "to_address", deserialize_with = "from_address")]
+    pub representative: Public,
+
     pub work: Option<Work>,
     pub signature: Option<Signature>,
 }
+
+impl ChangeBlock {
+    pub const LEN: usize = 136;
+
+    pub fn new(previous: BlockHash, representative: Public) -> Self {
+        Self {
+            previous,
+            representative,
+            work: None,
+            signature: None,
+        }
+    }
+}
+
+#[cfg(feature = "node")]
+impl Wire for ChangeBlock {
+    fn serialize(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(Self::LEN);
+        v.extend_from_slice(self.previous.as_bytes());
+        v.extend_from_slice(self.representative.as_bytes());
+        write_signature_and_work(&mut v, &self.signature, &self.work);
+        v
+    }
+
+    fn deserialize(_: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut data = Bytes::new(data);
+        let previous = BlockHash::try_from(data.slice(BlockHash::LEN)?)?;
+        let representative = Public::try_from(data.slice(Public::LEN)?)?;
+        let (signature, work) = read_signature_and_work(&mut data)?;
+
+        Ok(Self {
+            previous,
+            representative,
+            work: Some(work),
+            signature: Some(signature),
+        })
+    }
+
+    fn len(_: Option<&Header>) -> anyhow::Result<usize>
+    where
+        Self: Sized,
+    {
+        Ok(Self::LEN)
+    }
+}
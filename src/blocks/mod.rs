@@ -18,7 +18,7 @@ pub(crate) use state_block::deserialize_to_unsure_link;
 use crate::encoding::blake2b;
 use crate::keys::public::to_address;
 use crate::network::Network;
-use crate::{Private, Public, Raw, Signature, Work};
+use crate::{Private, Public, Raw, Signature, Subject, Work};
 use anyhow::{anyhow, Context};
 pub use block_hash::BlockHash;
 pub use change_block::ChangeBlock;
@@ -27,7 +27,7 @@ pub use receive_block::ReceiveBlock;
 pub use send_block::SendBlock;
 use serde;
 use serde::{Deserialize, Serialize};
-pub use state_block::{Link, StateBlock, Subtype};
+pub use state_block::{Epoch, Link, StateBlock, Subtype, UnsureLink};
 use std::convert::TryFrom;
 use std::str::FromStr;
 use strum_macros::EnumString;
@@ -88,10 +88,90 @@ pub enum BlockHolder {
     State(StateBlock),
 }
 
+impl BlockHolder {
+    /// The block hash, which only needs the fields that are in every block type's wire format.
+    pub fn hash(&self) -> BlockHash {
+        match self {
+            BlockHolder::Send(b) => hash_block(&[
+                b.previous.as_bytes(),
+                b.destination.as_bytes(),
+                b.balance.to_vec().as_slice(),
+            ]),
+            BlockHolder::Receive(b) => hash_block(&[b.previous.as_bytes(), b.source.as_bytes()]),
+            BlockHolder::Open(b) => hash_block(&[
+                b.source.as_bytes(),
+                b.representative.as_bytes(),
+                b.account.as_bytes(),
+            ]),
+            BlockHolder::Change(b) => {
+                hash_block(&[b.previous.as_bytes(), b.representative.as_bytes()])
+            }
+            BlockHolder::State(b) => Block::from_state_block(b)
+                .hash()
+                .expect("State blocks are always hashable")
+                .to_owned(),
+        }
+    }
+}
+
+#[cfg(feature = "node")]
+impl BlockHolder {
+    pub fn block_type(&self) -> BlockType {
+        match self {
+            BlockHolder::Send(_) => BlockType::Send,
+            BlockHolder::Receive(_) => BlockType::Receive,
+            BlockHolder::Open(_) => BlockType::Open,
+            BlockHolder::Change(_) => BlockType::Change,
+            BlockHolder::State(_) => BlockType::State,
+        }
+    }
+
+    /// The serialized length of a block of this type, for when the type isn't in a header, e.g.
+    /// in a bulk pull response.
+    pub fn len_for_type(block_type: &BlockType) -> anyhow::Result<usize> {
+        Ok(match block_type {
+            BlockType::State => StateBlock::LEN,
+            BlockType::Send => SendBlock::LEN,
+            BlockType::Receive => ReceiveBlock::LEN,
+            BlockType::Open => OpenBlock::LEN,
+            BlockType::Change => ChangeBlock::LEN,
+            t => return Err(anyhow!("Unsupported block type: {:?}", t)),
+        })
+    }
+
+    pub fn deserialize_typed(block_type: &BlockType, data: &[u8]) -> anyhow::Result<Self> {
+        let context = || format!("Deserialize {:?} BlockHolder", block_type);
+        Ok(match block_type {
+            BlockType::State => {
+                BlockHolder::State(Wire::deserialize(None, data).with_context(context)?)
+            }
+            BlockType::Send => {
+                BlockHolder::Send(Wire::deserialize(None, data).with_context(context)?)
+            }
+            BlockType::Receive => {
+                BlockHolder::Receive(Wire::deserialize(None, data).with_context(context)?)
+            }
+            BlockType::Open => {
+                BlockHolder::Open(Wire::deserialize(None, data).with_context(context)?)
+            }
+            BlockType::Change => {
+                BlockHolder::Change(Wire::deserialize(None, data).with_context(context)?)
+            }
+            t => return Err(anyhow!("Unsupported block type: {:?}", t)),
+        })
+    }
+}
+
 #[cfg(feature = "node")]
 impl Wire for BlockHolder {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        match self {
+            BlockHolder::Send(b) => Wire::serialize(b),
+            BlockHolder::Receive(b) => Wire::serialize(b),
+            BlockHolder::Open(b) => Wire::serialize(b),
+            BlockHolder::Change(b) => Wire::serialize(b),
+            BlockHolder::State(b) => Wire::serialize(b),
+        }
     }
 
     fn deserialize(header: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
@@ -99,22 +179,13 @@ impl Wire for BlockHolder {
         Self: Sized,
     {
         debug_assert!(header.is_some());
-        let context = "Deserialize BlockHolder";
-
-        let holder = match header
+        let block_type = header
             .as_ref()
             .unwrap()
             .ext()
             .block_type()
-            .context(context)?
-        {
-            BlockType::State => {
-                BlockHolder::State(Wire::deserialize(header, data).context(context)?)
-            }
-            BlockType::Send => BlockHolder::Send(Wire::deserialize(header, data).context(context)?),
-            _ => todo!(),
-        };
-        Ok(holder)
+            .context("Deserialize BlockHolder")?;
+        Self::deserialize_typed(&block_type, data)
     }
 
     fn len(header: Option<&Header>) -> anyhow::Result<usize>
@@ -122,14 +193,35 @@ impl Wire for BlockHolder {
         Self: Sized,
     {
         debug_assert!(header.is_some());
-        match header.as_ref().unwrap().ext().block_type()? {
-            BlockType::State => StateBlock::len(header),
-            BlockType::Send => SendBlock::len(header),
-            _ => todo!(),
-        }
+        Self::len_for_type(&header.as_ref().unwrap().ext().block_type()?)
     }
 }
 
+/// The signature and work at the end of every legacy block. Work is little endian in legacy
+/// blocks.
+#[cfg(feature = "node")]
+pub(crate) fn write_signature_and_work(
+    v: &mut Vec<u8>,
+    signature: &Option<Signature>,
+    work: &Option<Work>,
+) {
+    v.extend_from_slice(signature.as_ref().unwrap_or(&Signature::zero()).as_bytes());
+    let mut work = work.as_ref().unwrap_or(&Work::zero()).as_bytes().to_vec();
+    work.reverse();
+    v.extend_from_slice(&work);
+}
+
+#[cfg(feature = "node")]
+pub(crate) fn read_signature_and_work(
+    data: &mut crate::bytes::Bytes,
+) -> anyhow::Result<(Signature, Work)> {
+    let signature = Signature::try_from(data.slice(Signature::LEN)?)?;
+    let mut work = data.slice(Work::LEN)?.to_vec();
+    work.reverse();
+    let work = Work::try_from(work.as_slice())?;
+    Ok((signature, work))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Previous {
     Block(BlockHash),
@@ -279,6 +371,45 @@ impl Block {
         b
     }
 
+    /// The account, representative and balance aren't in a receive block, so they have to be
+    /// looked up from the previous block and the source.
+    pub fn from_receive_block(
+        receive_block: &ReceiveBlock,
+        account: &Public,
+        representative: &Public,
+        balance: &Raw,
+    ) -> Self {
+        let mut b = Self::new(
+            BlockType::Receive,
+            account.to_owned(),
+            Previous::Block(receive_block.previous.to_owned()),
+            representative.to_owned(),
+            balance.to_owned(),
+            Link::Source(receive_block.source.to_owned()),
+            ValidationState::Valid,
+        );
+        b.signature = receive_block.signature.to_owned();
+        b.work = receive_block.work.to_owned();
+        b
+    }
+
+    /// The account and balance aren't in a change block, so they have to be looked up from the
+    /// previous block.
+    pub fn from_change_block(change_block: &ChangeBlock, account: &Public, balance: &Raw) -> Self {
+        let mut b = Self::new(
+            BlockType::Change,
+            account.to_owned(),
+            Previous::Block(change_block.previous.to_owned()),
+            change_block.representative.to_owned(),
+            balance.to_owned(),
+            Link::Nothing,
+            ValidationState::Valid,
+        );
+        b.signature = change_block.signature.to_owned();
+        b.work = change_block.work.to_owned();
+        b
+    }
+
     pub fn from_state_block(state_block: &StateBlock) -> Self {
         let mut b = Self::new(
             BlockType::State,
@@ -398,13 +529,96 @@ impl Block {
         &self.balance
     }
 
+    /// What the work of this block is generated for: the previous block, or the account for the
+    /// first block.
+    pub fn work_subject(&self) -> Subject {
+        match &self.previous {
+            Previous::Open => Subject::Public(self.account.to_owned()),
+            Previous::Block(hash) => Subject::Hash(hash.to_owned()),
+        }
+    }
+
     pub fn previous(&self) -> &Previous {
         &self.previous
     }
 
+    pub fn link(&self) -> &Link {
+        &self.link
+    }
+
+    pub fn validation_state(&self) -> &ValidationState {
+        &self.state
+    }
+
+    /// The previous block hash, for legacy block types that always have one.
+    fn previous_hash(&self) -> anyhow::Result<&BlockHash> {
+        match &self.previous {
+            Previous::Block(hash) => Ok(hash),
+            Previous::Open => Err(anyhow!(
+                "{:?} block without a previous block",
+                self.block_type
+            )),
+        }
+    }
+
+    /// The block in the form it's sent over the network.
+    pub fn to_holder(&self) -> anyhow::Result<BlockHolder> {
+        Ok(match &self.block_type {
+            BlockType::Send => {
+                let mut send = SendBlock::new(
+                    self.previous_hash()?.to_owned(),
+                    self.destination()?.to_owned(),
+                    self.balance.to_owned(),
+                );
+                send.signature = self.signature.to_owned();
+                send.work = self.work.to_owned();
+                BlockHolder::Send(send)
+            }
+            BlockType::Receive => {
+                let mut receive =
+                    ReceiveBlock::new(self.previous_hash()?.to_owned(), self.source()?.to_owned());
+                receive.signature = self.signature.to_owned();
+                receive.work = self.work.to_owned();
+                BlockHolder::Receive(receive)
+            }
+            BlockType::Open => {
+                let mut open = OpenBlock::new(
+                    self.source()?.to_owned(),
+                    self.representative.to_owned(),
+                    self.account.to_owned(),
+                );
+                open.signature = self.signature.to_owned();
+                open.work = self.work.to_owned();
+                BlockHolder::Open(open)
+            }
+            BlockType::Change => {
+                let mut change = ChangeBlock::new(
+                    self.previous_hash()?.to_owned(),
+                    self.representative.to_owned(),
+                );
+                change.signature = self.signature.to_owned();
+                change.work = self.work.to_owned();
+                BlockHolder::Change(change)
+            }
+            BlockType::State => {
+                let mut state = StateBlock::new(
+                    self.account.to_owned(),
+                    self.previous.to_owned(),
+                    self.representative.to_owned(),
+                    self.balance.to_owned(),
+                    self.link.to_owned(),
+                );
+                state.signature = self.signature.to_owned();
+                state.work = self.work.to_owned();
+                BlockHolder::State(state)
+            }
+            t => return Err(anyhow!("Converting {:?} blocks is not supported yet", t)),
+        })
+    }
+
     /// For an open or recv block, get the sender's block hash, otherwise Err.
     pub fn source(&self) -> anyhow::Result<&BlockHash> {
-        if self.block_type != BlockType::Open {
+        if self.block_type != BlockType::Open && self.block_type != BlockType::Receive {
             return Err(anyhow!(
                 "Source requested for a {:?} block",
                 self.block_type
@@ -453,7 +667,7 @@ pub fn hash_block(parts: &[&[u8]]) -> BlockHash {
 
 #[cfg(test)]
 mod tests {
-    use crate::blocks::{Block, BlockHash, Link, Previous, StateBlock};
+    use crate::blocks::{Block, BlockHash, BlockType, Link, Previous, StateBlock};
     use crate::network::Network;
     use crate::{Public, Raw};
     use std::str::FromStr;
@@ -500,4 +714,76 @@ mod tests {
         let block_1 = Block::from_state_block(&StateBlock::from(block_0.clone()));
         assert_eq!(block_0, block_1)
     }
+
+    #[cfg(feature = "node")]
+    #[test]
+    fn genesis_wire() {
+        use crate::blocks::BlockHolder;
+        use crate::encoding::to_hex;
+        use crate::node::Wire;
+
+        let genesis = Network::Live.genesis_block();
+        let holder = genesis.to_holder().unwrap();
+        let account = "E89208DD038FBB269987689621D52292AE9C35941A7484756ECCED92A65093BA";
+        let signature = "9F0C933C8ADE004D808EA1985FA746A7E95BA2A38F867640F53EC8F180BDFE9E2C1268DEAD7C2664F356E37ABA362BC58E46DBA03E523A7B5A19E4B6EB12BB02";
+        // Work is little endian on the wire.
+        let work = "91B63FDD1754F062";
+        let expected = format!("{}{}{}{}{}", account, account, account, signature, work);
+
+        let data = holder.serialize();
+        assert_eq!(to_hex(&data), expected);
+        assert_eq!(holder.hash(), Network::Live.genesis_hash());
+        assert_eq!(
+            BlockHolder::deserialize_typed(&BlockType::Open, &data).unwrap(),
+            holder
+        );
+    }
+
+    #[cfg(feature = "node")]
+    #[test]
+    fn wire_round_trip() {
+        use crate::blocks::{BlockHolder, ChangeBlock, ReceiveBlock, SendBlock};
+        use crate::node::Wire;
+        use crate::{Signature, Work};
+
+        let state = test_state_block();
+        let hash = Block::from_state_block(&state).hash().unwrap().to_owned();
+        let rep = state.representative.to_owned();
+        let holders = vec![
+            BlockHolder::Send(SendBlock::new(hash.clone(), rep.clone(), Raw(100))),
+            BlockHolder::Receive(ReceiveBlock::new(hash.clone(), hash.clone())),
+            BlockHolder::Change(ChangeBlock::new(hash.clone(), rep)),
+            BlockHolder::State(state),
+        ];
+        for mut holder in holders {
+            let signature = Some(Signature::from_str("721C6CAFD61C2D7ED27643C556F77AE900308BD5AAF458E74310E42773BB45494A138EE0291B6868C360EB983AB5CE8FF2EFF6A66044CBA2B128047ACDBD4402").unwrap());
+            let work = Some(Work::from_str("62F05417DD3FB691").unwrap());
+            match &mut holder {
+                BlockHolder::Send(b) => {
+                    b.signature = signature;
+                    b.work = work;
+                }
+                BlockHolder::Receive(b) => {
+                    b.signature = signature;
+                    b.work = work;
+                }
+                BlockHolder::Change(b) => {
+                    b.signature = signature;
+                    b.work = work;
+                }
+                BlockHolder::State(b) => {
+                    b.signature = signature;
+                    b.work = work;
+                }
+                BlockHolder::Open(_) => unreachable!(),
+            }
+
+            let block_type = holder.block_type();
+            let data = holder.serialize();
+            assert_eq!(data.len(), BlockHolder::len_for_type(&block_type).unwrap());
+            let decoded = BlockHolder::deserialize_typed(&block_type, &data).unwrap();
+            assert_eq!(decoded.hash(), holder.hash());
+            assert_eq!(decoded.serialize(), data);
+        }
+    }
 }
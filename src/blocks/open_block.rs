@@ -1,3 +1,18 @@
+#[cfg(feature = "node")]
+use crate::node::Header;
+
+#[cfg(feature = "node")]
+use crate::node::Wire;
+
+#[cfg(feature = "node")]
+use crate::blocks::{read_signature_and_work, write_signature_and_work};
+
+#[cfg(feature = "node")]
+use crate::bytes::Bytes;
+
+#[cfg(feature = "node")]
+use std::convert::TryFrom;
+
 use crate::blocks::BlockHash;
 use crate::keys::public::{from_address, to_address};
 use crate::{Public, Signature, Work};
@@ -18,6 +33,8 @@ pub struct OpenBlock {
 }
 
 impl OpenBlock {
+    pub const LEN: usize = 168;
+
     pub fn new(source: BlockHash, representative: Public, account: Public) -> Self {
         Self {
             source,
@@ -28,3 +45,41 @@ impl OpenBlock {
         }
     }
 }
+
+#[cfg(feature = "node")]
+impl Wire for OpenBlock {
+    fn serialize(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(Self::LEN);
+        v.extend_from_slice(self.source.as_bytes());
+        v.extend_from_slice(self.representative.as_bytes());
+        v.extend_from_slice(self.account.as_bytes());
+        write_signature_and_work(&mut v, &self.signature, &self.work);
+        v
+    }
+
+    fn deserialize(_: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut data = Bytes::new(data);
+        let source = BlockHash::try_from(data.slice(BlockHash::LEN)?)?;
+        let representative = Public::try_from(data.slice(Public::LEN)?)?;
+        let account = Public::try_from(data.slice(Public::LEN)?)?;
+        let (signature, work) = read_signature_and_work(&mut data)?;
+
+        Ok(Self {
+            source,
+            representative,
+            account,
+            work: Some(work),
+            signature: Some(signature),
+        })
+    }
+
+    fn len(_: Option<&Header>) -> anyhow::Result<usize>
+    where
+        Self: Sized,
+    {
+        Ok(Self::LEN)
+    }
+}
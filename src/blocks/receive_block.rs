@@ -1,11 +1,78 @@
+#[cfg(feature = "node")]
+use crate::node::Header;
+
+#[cfg(feature = "node")]
+use crate::node::Wire;
+
+#[cfg(feature = "node")]
+use crate::blocks::{read_signature_and_work, write_signature_and_work};
+
+#[cfg(feature = "node")]
+use crate::bytes::Bytes;
+
+#[cfg(feature = "node")]
+use std::convert::TryFrom;
+
 use crate::blocks::BlockHash;
-use crate::{Public, Signature, Work};
+use crate::{Signature, Work};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ReceiveBlock {
-    previous: BlockHash,
-    source: Public,
+    /// The hash of the previous block in this account.
+    pub previous: BlockHash,
+
+    /// The send block being received.
+    pub source: BlockHash,
+
     pub work: Option<Work>,
     pub signature: Option<Signature>,
 }
+
+impl ReceiveBlock {
+    pub const LEN: usize = 136;
+
+    pub fn new(previous: BlockHash, source: BlockHash) -> Self {
+        Self {
+            previous,
+            source,
+            work: None,
+            signature: None,
+        }
+    }
+}
+
+#[cfg(feature = "node")]
+impl Wire for ReceiveBlock {
+    fn serialize(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(Self::LEN);
+        v.extend_from_slice(self.previous.as_bytes());
+        v.extend_from_slice(self.source.as_bytes());
+        write_signature_and_work(&mut v, &self.signature, &self.work);
+        v
+    }
+
+    fn deserialize(_: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut data = Bytes::new(data);
+        let previous = BlockHash::try_from(data.slice(BlockHash::LEN)?)?;
+        let source = BlockHash::try_from(data.slice(BlockHash::LEN)?)?;
+        let (signature, work) = read_signature_and_work(&mut data)?;
+
+        Ok(Self {
+            previous,
+            source,
+            work: Some(work),
+            signature: Some(signature),
+        })
+    }
+
+    fn len(_: Option<&Header>) -> anyhow::Result<usize>
+    where
+        Self: Sized,
+    {
+        Ok(Self::LEN)
+    }
+}
@@ -5,7 +5,7 @@ use crate::node::Header;
 use crate::node::Wire;
 
 #[cfg(feature = "node")]
-use crate::blocks::BlockType;
+use crate::blocks::{read_signature_and_work, write_signature_and_work, BlockType};
 
 #[cfg(feature = "node")]
 use crate::bytes::Bytes;
@@ -54,7 +54,12 @@ impl SendBlock {
 #[cfg(feature = "node")]
 impl Wire for SendBlock {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        let mut v = Vec::with_capacity(Self::LEN);
+        v.extend_from_slice(self.previous.as_bytes());
+        v.extend_from_slice(self.destination.as_bytes());
+        v.extend_from_slice(&self.balance.to_vec());
+        write_signature_and_work(&mut v, &self.signature, &self.work);
+        v
     }
 
     fn deserialize(_: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
@@ -65,15 +70,14 @@ impl Wire for SendBlock {
         let previous = BlockHash::try_from(data.slice(BlockHash::LEN)?)?;
         let destination = Public::try_from(data.slice(Public::LEN)?)?;
         let balance = Raw::try_from(data.slice(Raw::LEN)?)?;
-        let work = Some(Work::try_from(data.slice(Work::LEN)?)?);
-        let signature = Some(Signature::try_from(data.slice(Signature::LEN)?)?);
+        let (signature, work) = read_signature_and_work(&mut data)?;
 
         Ok(Self {
             previous,
             destination,
             balance,
-            work,
-            signature,
+            work: Some(work),
+            signature: Some(signature),
         })
     }
 
@@ -41,6 +41,43 @@ pub enum Subtype {
     Epoch,
 }
 
+/// The version of an account chain. An epoch block upgrades an account to the next epoch,
+/// which changes the work thresholds of the account's later blocks.
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Epoch {
+    Epoch0 = 0,
+    Epoch1 = 1,
+    Epoch2 = 2,
+}
+
+impl Epoch {
+    /// The epoch an epoch block upgrades this one to.
+    pub fn next(&self) -> Option<Epoch> {
+        match self {
+            Epoch::Epoch0 => Some(Epoch::Epoch1),
+            Epoch::Epoch1 => Some(Epoch::Epoch2),
+            Epoch::Epoch2 => None,
+        }
+    }
+}
+
+impl TryFrom<u8> for Epoch {
+    type Error = anyhow::Error;
+
+    fn try_from(v: u8) -> anyhow::Result<Self> {
+        Ok(match v {
+            0 => Epoch::Epoch0,
+            1 => Epoch::Epoch1,
+            2 => Epoch::Epoch2,
+            v => return Err(anyhow!("Unknown epoch: {}", v)),
+        })
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct StateBlock {
     #[serde(serialize_with = "to_address", deserialize_with = "from_address")]
@@ -150,7 +187,20 @@ impl StateBlock {
 #[cfg(feature = "node")]
 impl Wire for StateBlock {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        let mut v = Vec::with_capacity(Self::LEN);
+        v.extend_from_slice(self.account.as_bytes());
+        v.extend_from_slice(&self.previous.to_bytes());
+        v.extend_from_slice(self.representative.as_bytes());
+        v.extend_from_slice(&self.balance.to_vec());
+        v.extend_from_slice(self.link.as_bytes());
+        v.extend_from_slice(
+            self.signature
+                .as_ref()
+                .unwrap_or(&Signature::zero())
+                .as_bytes(),
+        );
+        v.extend_from_slice(self.work.as_ref().unwrap_or(&Work::zero()).as_bytes());
+        v
     }
 
     fn deserialize(_header: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
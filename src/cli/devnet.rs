@@ -0,0 +1,45 @@
+use crate::cli::StringOrStdin;
+use crate::{DevNetwork, Difficulty};
+use clap::Clap;
+
+#[derive(Clap)]
+pub struct DevnetOpts {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+impl DevnetOpts {
+    pub fn handle(&self) -> anyhow::Result<()> {
+        match &self.command {
+            Command::Genesis(o) => {
+                let key = o.seed.to_owned().resolve()?.derive(o.index);
+                let dev = DevNetwork::generate(&key, o.magic, o.work_threshold.to_owned())?;
+                println!("{}", serde_json::to_string_pretty(&dev)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clap)]
+pub enum Command {
+    /// Print the config of a new dev network, with the seed's account as the genesis account.
+    /// Start each node with it using `feeless node --devnet <PATH>`.
+    Genesis(GenesisOpts),
+}
+
+#[derive(Clap)]
+pub struct GenesisOpts {
+    seed: StringOrStdin<crate::Seed>,
+
+    #[clap(short, long, default_value = "0")]
+    index: u32,
+
+    /// Byte identifying the network in message headers. 65 to 67 are taken by the public networks.
+    #[clap(short, long, default_value = "68")]
+    magic: u8,
+
+    /// Work threshold for every block, as hex.
+    #[clap(short, long, default_value = "fe00000000000000")]
+    work_threshold: Difficulty,
+}
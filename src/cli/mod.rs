@@ -2,6 +2,7 @@
 mod pcap;
 
 mod address;
+mod devnet;
 mod phrase;
 mod private;
 mod public;
@@ -19,21 +20,29 @@ use crate::rpc::client::RPCClientOpts;
 use crate::cli::pcap::PcapDumpOpts;
 
 #[cfg(feature = "node")]
-use crate::node::Node;
+use crate::node::{node_id, ElectionConfig, Node};
+
+#[cfg(feature = "node")]
+use crate::wallet::WalletManager;
 
 use crate::cli::unit::UnitOpts;
 use crate::cli::vanity::VanityOpts;
 use crate::cli::verify::VerifyOpts;
 use crate::cli::wallet::WalletOpts;
 use crate::cli::work::WorkOpts;
+use crate::paths::PathsOpts;
+use crate::units::Mnano;
+use crate::wallet::WalletId;
 use address::AddressOpts;
 use anyhow::anyhow;
 use clap::Clap;
+use devnet::DevnetOpts;
 use phrase::PhraseOpts;
 use private::PrivateOpts;
 use public::PublicOpts;
 use seed::SeedOpts;
 use std::io::Read;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::{env, io};
@@ -94,6 +103,9 @@ enum Command {
     /// Find a secret that can generate a custom vanity address.
     Vanity(VanityOpts),
 
+    /// Set up a private dev network.
+    Devnet(DevnetOpts),
+
     #[cfg(feature = "rpc_client")]
     /// RPC client that can call a function against a Nano RPC server.
     Call(RPCClientOpts),
@@ -111,9 +123,101 @@ enum Command {
 
 #[derive(Clap)]
 struct NodeOpts {
+    #[clap(flatten)]
+    paths_opts: PathsOpts,
+
     /// Comma separated list of IP:PORT pairs. Overrides default initial nodes.
     #[clap(short, long)]
     override_peers: Option<Vec<String>>,
+
+    /// Address and port to listen on for incoming peer connections. Defaults to the network's
+    /// port on all interfaces.
+    #[clap(short, long)]
+    bind: Option<SocketAddr>,
+
+    /// Port for the RPC server. Defaults to the network's RPC port.
+    #[clap(long)]
+    rpc_port: Option<u16>,
+
+    /// Print our node ID and exit. It is created if it doesn't exist yet.
+    #[clap(long)]
+    show_node_id: bool,
+
+    /// Replace our node ID with a new one, print it and exit.
+    #[clap(long, conflicts_with = "show-node-id")]
+    rotate_node_id: bool,
+
+    /// Vote as a representative, with a key from this wallet.
+    #[clap(long)]
+    representative_wallet: Option<WalletId>,
+
+    /// Index of the representative's key in `--representative-wallet`.
+    #[clap(long, default_value = "0")]
+    representative_index: u32,
+
+    /// Percentage of the online voting weight needed to confirm a block. Defaults to the live
+    /// network's 67.
+    #[clap(long)]
+    quorum_percent: Option<u8>,
+
+    /// The least online voting weight, in Mnano, the quorum is calculated from. Defaults to the
+    /// live network's 60000000. Lower it for a dev network with little weight.
+    #[clap(long)]
+    online_weight_minimum: Option<Mnano>,
+}
+
+#[cfg(feature = "node")]
+impl NodeOpts {
+    async fn handle(self) -> anyhow::Result<()> {
+        let network = self.paths_opts.network()?;
+        let paths = self.paths_opts.paths(network);
+        let node_key = if self.show_node_id {
+            node_id::load_or_create(&paths)?
+        } else if self.rotate_node_id {
+            node_id::rotate(&paths)?
+        } else {
+            let representative = match &self.representative_wallet {
+                Some(wallet_id) => Some(
+                    WalletManager::new(paths.wallet_path())
+                        .wallet(wallet_id)
+                        .await?
+                        .private(self.representative_index)?,
+                ),
+                None => None,
+            };
+            let bind = self.bind.unwrap_or_else(|| {
+                SocketAddr::from((std::net::Ipv6Addr::UNSPECIFIED, network.default_port()))
+            });
+            let rpc_port = self.rpc_port.unwrap_or_else(|| network.default_rpc_port());
+            let election_config = self.election_config()?;
+            return Node::start(
+                network,
+                &paths,
+                self.override_peers,
+                bind,
+                rpc_port,
+                representative,
+                election_config,
+            )
+            .await;
+        };
+        println!("{}", node_id::node_id_string(&node_key.to_public()?));
+        Ok(())
+    }
+
+    fn election_config(&self) -> anyhow::Result<ElectionConfig> {
+        let mut config = ElectionConfig::default();
+        if let Some(percent) = self.quorum_percent {
+            if percent == 0 || percent > 100 {
+                return Err(anyhow!("Quorum must be 1 to 100 percent, not {}", percent));
+            }
+            config.quorum_percent = percent;
+        }
+        if let Some(minimum) = &self.online_weight_minimum {
+            config.online_weight_minimum = minimum.to_raw()?;
+        }
+        Ok(config)
+    }
 }
 
 #[derive(Clap)]
@@ -139,7 +243,7 @@ pub async fn run() -> anyhow::Result<()> {
 
     match opts.command {
         #[cfg(feature = "node")]
-        Command::Node(o) => Node::start(o.override_peers).await,
+        Command::Node(o) => o.handle().await,
         #[cfg(not(feature = "node"))]
         Command::Node => panic!("Compile with the `node` feature to enable this."),
 
@@ -162,6 +266,7 @@ pub async fn run() -> anyhow::Result<()> {
         Command::Unit(unit) => unit.handle(),
         Command::Work(work) => work.handle(),
         Command::Vanity(vanity) => vanity.handle().await,
+        Command::Devnet(devnet) => devnet.handle(),
         Command::Verify(verify) => verify.handle(),
     }
 }
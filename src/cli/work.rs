@@ -1,4 +1,4 @@
-use crate::blocks::BlockHash;
+use crate::blocks::{BlockHash, Epoch};
 use crate::pow::{Subject, Work};
 use crate::Difficulty;
 use clap::Clap;
@@ -17,6 +17,10 @@ pub struct WorkOpts {
     #[clap(short, long, group = "base")]
     receive: bool,
 
+    /// The epoch of the account, which decides the base difficulty of normal and receive blocks.
+    #[clap(short, long, default_value = "epoch2")]
+    epoch: Epoch,
+
     /// The base difficulty in hex.
     #[clap(short, long, group = "base")]
     difficulty: Option<Difficulty>,
@@ -31,9 +35,9 @@ impl WorkOpts {
         let difficulty = if let Some(d) = &self.difficulty {
             d.to_owned()
         } else if self.receive {
-            Difficulty::receive()
+            Difficulty::receive(&self.epoch)
         } else {
-            Difficulty::normal()
+            Difficulty::normal(&self.epoch)
         };
         info!("Finding work for {:?} at {:?}", &subject, &difficulty);
         let result = Work::generate(&subject, &difficulty)?;
@@ -14,7 +14,7 @@ use std::iter::FromIterator;
 use std::str::FromStr;
 
 /// 256 bit public key which can be converted into an [Address](crate::Address) or verify a [Signature](crate::Signature).
-#[derive(Clone, Eq, PartialEq, Hash)]
+#[derive(Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub struct Public([u8; Public::LEN]);
 
 hexify!(Public, "public key");
@@ -23,6 +23,10 @@ impl Public {
     pub const LEN: usize = 32;
     const ADDRESS_CHECKSUM_LEN: usize = 5;
 
+    pub fn zero() -> Self {
+        Self([0u8; Public::LEN])
+    }
+
     fn dalek_key(&self) -> Result<ed25519_dalek::PublicKey, Error> {
         Ok(
             ed25519_dalek::PublicKey::from_bytes(&self.0).map_err(|e| Error::SignatureError {
@@ -63,6 +67,26 @@ impl Public {
             _ => Err(Error::BadPublicKey),
         }
     }
+
+    /// Verify many signatures at once, which is a lot faster than verifying them one by one.
+    /// Fails if any of them is invalid, without saying which.
+    pub fn verify_batch(
+        messages: &[&[u8]],
+        signatures: &[Signature],
+        publics: &[Public],
+    ) -> Result<(), Error> {
+        let keys = publics
+            .iter()
+            .map(|public| public.dalek_key())
+            .collect::<Result<Vec<_>, _>>()?;
+        let signatures: Vec<_> = signatures.iter().map(|s| s.internal()).collect();
+        ed25519_dalek::verify_batch(messages, &signatures, &keys).map_err(|e| {
+            Error::SignatureError {
+                msg: format!("Batch verification of {} signatures failed", messages.len()),
+                source: e,
+            }
+        })
+    }
 }
 
 impl From<ed25519_dalek::PublicKey> for Public {
@@ -74,14 +98,15 @@ impl From<ed25519_dalek::PublicKey> for Public {
 #[cfg(feature = "node")]
 impl Wire for Public {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        self.as_bytes().to_vec()
     }
 
-    fn deserialize(_header: Option<&Header>, _data: &[u8]) -> anyhow::Result<Self>
+    fn deserialize(_header: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
     where
         Self: Sized,
     {
-        unimplemented!()
+        use std::convert::TryFrom;
+        Ok(Public::try_from(data)?)
     }
 
     fn len(_header: Option<&Header>) -> anyhow::Result<usize>
@@ -135,6 +160,22 @@ mod tests {
         )
     }
 
+    #[test]
+    fn verify_batch() {
+        let privates: Vec<Private> = (0..4).map(|_| Private::random()).collect();
+        let publics: Vec<Public> = privates.iter().map(|p| p.to_public().unwrap()).collect();
+        let messages: Vec<&[u8]> = vec![b"a", b"b", b"c", b"d"];
+        let mut signatures: Vec<_> = privates
+            .iter()
+            .zip(&messages)
+            .map(|(p, m)| p.sign(m).unwrap())
+            .collect();
+        assert!(Public::verify_batch(&messages, &signatures, &publics).is_ok());
+
+        signatures.swap(0, 1);
+        assert!(Public::verify_batch(&messages, &signatures, &publics).is_err());
+    }
+
     #[test]
     fn hex() {
         let s = "19D3D919475DEED4696B5D13018151D1AF88B2BD3BCFF048B45031C1F36D1858";
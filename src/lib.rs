@@ -71,7 +71,7 @@ pub use keys::private::Private;
 pub use keys::public::Public;
 pub use keys::seed::Seed;
 pub use keys::signature::Signature;
-pub use network::{Network, DEFAULT_PORT};
+pub use network::{DevNetwork, Network, DEFAULT_PORT};
 pub use pow::{Difficulty, Subject, Work};
 pub use units::raw::Raw;
 pub use version::Version;
@@ -0,0 +1,149 @@
+//! A private network for testing without any outside services.
+//!
+//! Every node on a dev network needs the same config, which is made once from a key with
+//! [DevNetwork::generate] and shared as JSON. The genesis account holds the whole supply, so the
+//! key can fund any other accounts the test needs.
+use crate::blocks::{
+    Block, BlockHash, BlockHolder, BlockType, Link, OpenBlock, Previous, ValidationState,
+};
+use crate::network::Network;
+use crate::{Difficulty, Private, Raw, Work};
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DevNetwork {
+    /// Identifies the network in message headers, instead of the byte of a public network.
+    pub magic: u8,
+
+    /// The work threshold for every block. Keep it low so work is quick to generate.
+    pub work_threshold: Difficulty,
+
+    /// Opens the genesis account.
+    pub genesis: OpenBlock,
+}
+
+impl DevNetwork {
+    /// "D" for dev, next to the bytes of the test, beta and live networks.
+    pub const DEFAULT_MAGIC: u8 = 0x44;
+
+    /// The same as the test network's threshold for legacy blocks.
+    pub const DEFAULT_WORK_THRESHOLD: u64 = 0xfe00000000000000;
+
+    /// A network with `key`'s account as the genesis account.
+    pub fn generate(key: &Private, magic: u8, work_threshold: Difficulty) -> anyhow::Result<Self> {
+        let account = key.to_public()?;
+        // Like the live genesis block, the source is the account itself.
+        let mut block = Block::new(
+            BlockType::Open,
+            account.to_owned(),
+            Previous::Open,
+            account.to_owned(),
+            Raw::max(),
+            Link::Source(BlockHash::try_from(account.as_bytes())?),
+            ValidationState::Valid,
+        );
+        block.sign(key.to_owned())?;
+        block.set_work(Work::generate(&block.work_subject(), &work_threshold)?);
+        let genesis = match block.to_holder()? {
+            BlockHolder::Open(open) => open,
+            holder => return Err(anyhow!("Genesis block is not an open block: {:?}", holder)),
+        };
+
+        let dev = Self {
+            magic,
+            work_threshold,
+            genesis,
+        };
+        dev.validate()?;
+        Ok(dev)
+    }
+
+    /// Read a config written by `feeless devnet`.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading dev network config {:?}", path))?;
+        let dev: Self = serde_json::from_str(&json)
+            .with_context(|| format!("Parsing dev network config {:?}", path))?;
+        dev.validate()?;
+        Ok(dev)
+    }
+
+    /// Check that the network can't be mistaken for a public one, and that its genesis block
+    /// is signed by the genesis account with enough work.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if Network::try_from(self.magic).is_ok() {
+            return Err(anyhow!(
+                "Magic byte {:#04X} belongs to a public network",
+                self.magic
+            ));
+        }
+
+        let block = Block::from_open_block(&self.genesis, &Previous::Open, &Raw::max());
+        block
+            .verify_signature(&self.genesis.account)
+            .context("Genesis block signature")?;
+        let work = block.work().context("Genesis block has no work")?;
+        if work.difficulty(&block.work_subject())? < self.work_threshold {
+            return Err(anyhow!("Genesis block has insufficient work"));
+        }
+        Ok(())
+    }
+
+    /// Keep the config for the rest of the process, so it can be used as a [Network].
+    pub fn into_network(self) -> Network {
+        Network::Dev(Box::leak(Box::new(self)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dev_network(key: &Private) -> DevNetwork {
+        DevNetwork::generate(
+            key,
+            DevNetwork::DEFAULT_MAGIC,
+            Difficulty::new(DevNetwork::DEFAULT_WORK_THRESHOLD),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn generate_and_load() {
+        let key = Private::random();
+        let dev = dev_network(&key);
+        let json = serde_json::to_string(&dev).unwrap();
+        let loaded: DevNetwork = serde_json::from_str(&json).unwrap();
+        loaded.validate().unwrap();
+        assert_eq!(loaded, dev);
+
+        let network = loaded.into_network();
+        assert_eq!(network.magic(), DevNetwork::DEFAULT_MAGIC);
+        assert_eq!(network.to_string(), "dev");
+        let genesis = network.genesis_block();
+        assert_eq!(genesis.account(), &key.to_public().unwrap());
+        assert_eq!(genesis.balance(), &Raw::max());
+        assert_eq!(&network.genesis_hash(), genesis.hash().unwrap());
+    }
+
+    #[test]
+    fn reject_invalid() {
+        let key = Private::random();
+        let threshold = Difficulty::new(DevNetwork::DEFAULT_WORK_THRESHOLD);
+        let live = Network::Live.magic();
+        assert!(DevNetwork::generate(&key, live, threshold).is_err());
+
+        // Signed by someone other than the genesis account.
+        let mut dev = dev_network(&key);
+        dev.genesis.account = Private::random().to_public().unwrap();
+        assert!(dev.validate().is_err());
+
+        // The work was made for a lower threshold.
+        let mut dev = dev_network(&key);
+        dev.work_threshold = Difficulty::new(u64::MAX);
+        assert!(dev.validate().is_err());
+    }
+}
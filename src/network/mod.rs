@@ -0,0 +1,343 @@
+mod dev;
+
+use crate::blocks::{Block, BlockHash, Epoch, Link, OpenBlock, Previous, Subtype};
+use crate::{Difficulty, Public, Raw};
+use anyhow::anyhow;
+pub use dev::DevNetwork;
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use strum_macros::EnumString;
+
+/// The default TCP port that Nano nodes use on the live network.
+pub const DEFAULT_PORT: u16 = 7075;
+
+/// Network to use: Test, Beta, Live, or a private [DevNetwork].
+#[derive(Debug, Clone, Copy, PartialEq, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum Network {
+    Test,
+    Beta,
+    Live,
+
+    /// Comes from a config file, so it can't be parsed from its name.
+    #[strum(disabled)]
+    Dev(&'static DevNetwork),
+}
+
+fn live_genesis_block() -> OpenBlock {
+    serde_json::from_str(
+    r#"
+        {
+            "type": "open",
+            "source": "E89208DD038FBB269987689621D52292AE9C35941A7484756ECCED92A65093BA",
+            "representative": "nano_3t6k35gi95xu6tergt6p69ck76ogmitsa8mnijtpxm9fkcm736xtoncuohr3",
+            "account": "nano_3t6k35gi95xu6tergt6p69ck76ogmitsa8mnijtpxm9fkcm736xtoncuohr3",
+            "work": "62F05417DD3FB691",
+            "signature": "9F0C933C8ADE004D808EA1985FA746A7E95BA2A38F867640F53EC8F180BDFE9E2C1268DEAD7C2664F356E37ABA362BC58E46DBA03E523A7B5A19E4B6EB12BB02"
+        }
+        "#
+    ).unwrap()
+}
+
+fn beta_genesis_block() -> OpenBlock {
+    serde_json::from_str(
+    r#"
+        {
+            "type": "open",
+            "source": "259A43ABDB779E97452E188BA3EB951B41C961D3318CA6B925380F4D99F0577A",
+            "representative": "nano_1betagoxpxwykx4kw86dnhosc8t3s7ix8eeentwkcg1hbpez1outjrcyg4n1",
+            "account": "nano_1betagoxpxwykx4kw86dnhosc8t3s7ix8eeentwkcg1hbpez1outjrcyg4n1",
+            "work": "79D4E27DC873C6F2",
+            "signature": "4BD7F96F9ED2721BCEE5EAED400EA50AD00524C629AE55E9AFF11220D2C1B00C3D4B3BB770BF67D4F8658023B677F91110193B6C101C2666931F57046A6DB806"
+        }
+        "#
+    ).unwrap()
+}
+
+/// Signed by [TEST_GENESIS_KEY].
+fn test_genesis_block() -> OpenBlock {
+    serde_json::from_str(
+    r#"
+        {
+            "type": "open",
+            "source": "B0311EA55708D6A53C75CDBF88300259C6D018522FE3D4D0A242E431F9E8B6D0",
+            "representative": "nano_3e3j5tkog48pnny9dmfzj1r16pg8t1e76dz5tmac6iq689wyjfpiij4txtdo",
+            "account": "nano_3e3j5tkog48pnny9dmfzj1r16pg8t1e76dz5tmac6iq689wyjfpiij4txtdo",
+            "work": "7B42A00EE91D5810",
+            "signature": "ECDA914373A2F0CA1296475BAEE40500A7F0A7AD72A5A80C81D7FAB7F6C802B2CC7DB50F5DD0FB25B2EF11761FA7344A158DD5A700B21BD47DE5BD0F63153A02"
+        }
+        "#
+    ).unwrap()
+}
+
+/// The link of an epoch v1 block: "epoch v1 block" padded with zeros.
+const EPOCH_V1_LINK: &str = "65706F636820763120626C6F636B000000000000000000000000000000000000";
+
+/// The link of an epoch v2 block: "epoch v2 block" padded with zeros.
+const EPOCH_V2_LINK: &str = "65706F636820763220626C6F636B000000000000000000000000000000000000";
+
+/// The well known private key of the test network's genesis account, which also signs its epoch
+/// blocks.
+pub(crate) const TEST_GENESIS_KEY: &str =
+    "34F0A37AAD20F4A260F0A5B3CB3D7FB50673212263E58A380BC10474BB039CE4";
+
+/// The minimum work difficulty a block needs, which depends on the epoch of the account and the
+/// kind of block.
+#[derive(Debug, Clone)]
+pub struct WorkThresholds {
+    /// Legacy blocks and state blocks of epoch 1 accounts.
+    pub epoch_1: Difficulty,
+
+    /// Sends and changes of epoch 2 accounts.
+    pub epoch_2: Difficulty,
+
+    /// Receives and opens of epoch 2 accounts, and epoch 2 blocks.
+    pub epoch_2_receive: Difficulty,
+}
+
+impl WorkThresholds {
+    /// The threshold for a block of `subtype` in an account at `epoch`. For an epoch block,
+    /// `epoch` is the one it upgrades to.
+    pub fn threshold(&self, epoch: &Epoch, subtype: &Subtype) -> Difficulty {
+        match (epoch, subtype) {
+            (Epoch::Epoch2, Subtype::Receive)
+            | (Epoch::Epoch2, Subtype::Open)
+            | (Epoch::Epoch2, Subtype::Epoch) => self.epoch_2_receive.to_owned(),
+            (Epoch::Epoch2, _) => self.epoch_2.to_owned(),
+            _ => self.epoch_1.to_owned(),
+        }
+    }
+
+    /// The lowest threshold of any block, for checking work before we know which one applies.
+    pub fn lowest(&self) -> Difficulty {
+        [&self.epoch_2, &self.epoch_2_receive]
+            .iter()
+            .fold(
+                &self.epoch_1,
+                |lowest, &t| if t < lowest { t } else { lowest },
+            )
+            .to_owned()
+    }
+}
+
+impl Network {
+    /// The byte identifying the network in message headers.
+    pub fn magic(&self) -> u8 {
+        match self {
+            Self::Test => 0x41,
+            Self::Beta => 0x42,
+            Self::Live => 0x43,
+            Self::Dev(dev) => dev.magic,
+        }
+    }
+
+    pub fn work_thresholds(&self) -> WorkThresholds {
+        let (epoch_1, epoch_2, epoch_2_receive) = match self {
+            // Very low, so tests can generate work quickly.
+            Self::Test => (0xfe00000000000000, 0xffc0000000000000, 0xf000000000000000),
+            Self::Beta => (0xfc00000000000000, 0xfc00000000000000, 0xf000000000000000),
+            Self::Live => (0xffffffc000000000, 0xfffffff800000000, 0xfffffe0000000000),
+            Self::Dev(dev) => {
+                let threshold = dev.work_threshold.as_u64();
+                (threshold, threshold, threshold)
+            }
+        };
+        WorkThresholds {
+            epoch_1: Difficulty::new(epoch_1),
+            epoch_2: Difficulty::new(epoch_2),
+            epoch_2_receive: Difficulty::new(epoch_2_receive),
+        }
+    }
+
+    pub fn genesis_block(&self) -> Block {
+        let open_block = match self {
+            Self::Test => test_genesis_block(),
+            Self::Beta => beta_genesis_block(),
+            Self::Live => live_genesis_block(),
+            Self::Dev(dev) => dev.genesis.to_owned(),
+        };
+
+        // Give the genesis block the maximum u128 value.
+        let balance = Raw::max();
+
+        Block::from_open_block(&open_block, &Previous::Open, &balance)
+    }
+
+    pub fn genesis_hash(&self) -> BlockHash {
+        let hash = match self {
+            Self::Test => "04270D7F11C4B2B472F2854C5A59F2A7E84226CE9ED799DE75744BD7D85FC9D9",
+            Self::Beta => "01A92459E69440D5C1088D3B31F4CA678BE944BAB3776C2E6B7665E9BD99BD5A",
+            Self::Live => "991CF190094C00F0B68E2E5F75F6BEE95A2E0BD93CEAA4A6734DB9F19B728948",
+            Self::Dev(_) => return self.genesis_block().hash().unwrap().to_owned(),
+        };
+        BlockHash::from_str(hash).unwrap()
+    }
+
+    /// The link that marks a state block as an upgrade to `epoch`.
+    pub fn epoch_link(&self, epoch: &Epoch) -> Option<Link> {
+        let link = match epoch {
+            Epoch::Epoch0 => return None,
+            Epoch::Epoch1 => EPOCH_V1_LINK,
+            Epoch::Epoch2 => EPOCH_V2_LINK,
+        };
+        Some(Link::unsure_from_str(link).unwrap())
+    }
+
+    /// The epoch a state block with `link` upgrades to, if it's an epoch link.
+    pub fn epoch_for_link(&self, link: &Link) -> Option<Epoch> {
+        [Epoch::Epoch1, Epoch::Epoch2]
+            .iter()
+            .copied()
+            .find(|epoch| {
+                self.epoch_link(epoch)
+                    .is_some_and(|l| l.as_bytes() == link.as_bytes())
+            })
+    }
+
+    /// The account that signs epoch blocks upgrading to `epoch`, instead of the account owner.
+    pub fn epoch_signer(&self, epoch: &Epoch) -> Option<Public> {
+        let signer = match (self, epoch) {
+            (_, Epoch::Epoch0) => return None,
+            (Self::Live, Epoch::Epoch1) => {
+                "E89208DD038FBB269987689621D52292AE9C35941A7484756ECCED92A65093BA"
+            }
+            (Self::Live, Epoch::Epoch2) => {
+                "DD24A9200D4BF8247981E4AC63DBDE38FD2319386970A26D02ECC98C79975DB1"
+            }
+            (Self::Beta, _) => "259A43ABDB779E97452E188BA3EB951B41C961D3318CA6B925380F4D99F0577A",
+            (Self::Dev(dev), _) => return Some(dev.genesis.account.to_owned()),
+            (Self::Test, _) => {
+                return Some(
+                    crate::Private::from_str(TEST_GENESIS_KEY)
+                        .unwrap()
+                        .to_public()
+                        .unwrap(),
+                )
+            }
+        };
+        Some(Public::from_str(signer).unwrap())
+    }
+
+    /// A host name resolving to some of the network's nodes, to find peers with. The test and
+    /// dev networks only run locally, so they have none.
+    pub fn peering_host(&self) -> Option<&str> {
+        match self {
+            Self::Test | Self::Dev(_) => None,
+            Self::Beta => Some("peering-beta.nano.org:54000"),
+            Self::Live => Some("peering.nano.org:7075"),
+        }
+    }
+
+    /// The TCP port nodes listen on for peers by default.
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Self::Test | Self::Dev(_) => 44000,
+            Self::Beta => 54000,
+            Self::Live => DEFAULT_PORT,
+        }
+    }
+
+    /// The port the RPC server listens on by default.
+    pub fn default_rpc_port(&self) -> u16 {
+        match self {
+            Self::Test | Self::Dev(_) => 45000,
+            Self::Beta => 55000,
+            Self::Live => 7076,
+        }
+    }
+}
+
+/// Written by hand, since strum won't display a disabled variant.
+impl Display for Network {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Test => "test",
+            Self::Beta => "beta",
+            Self::Live => "live",
+            Self::Dev(_) => "dev",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One of the public networks, from its [Network::magic] byte.
+impl TryFrom<u8> for Network {
+    type Error = anyhow::Error;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        use Network::*;
+        Ok(match v {
+            0x41 => Test,
+            0x42 => Beta,
+            0x43 => Live,
+            v => return Err(anyhow!("Unknown network: {} ({:X})", v, v)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn genesis_blocks() {
+        for net in &[Network::Test, Network::Beta, Network::Live] {
+            let block = net.genesis_block();
+            assert_eq!(block.hash().unwrap(), &net.genesis_hash());
+            assert!(block.verify_signature(block.account()).is_ok());
+            let difficulty = block
+                .work()
+                .unwrap()
+                .difficulty(&block.work_subject())
+                .unwrap();
+            assert!(difficulty >= net.work_thresholds().epoch_1, "{:?}", net);
+        }
+
+        // As given to `--network`.
+        assert_eq!(Network::from_str("beta").unwrap(), Network::Beta);
+        assert_eq!(Network::Test.peering_host(), None);
+
+        let test_key = crate::Private::from_str(TEST_GENESIS_KEY).unwrap();
+        assert_eq!(
+            &test_key.to_public().unwrap(),
+            Network::Test.genesis_block().account()
+        );
+        assert_eq!(
+            Network::Beta.epoch_signer(&Epoch::Epoch1).as_ref(),
+            Some(Network::Beta.genesis_block().account())
+        );
+    }
+
+    #[test]
+    fn epochs() {
+        let net = Network::Live;
+        let link = net.epoch_link(&Epoch::Epoch2).unwrap();
+        assert_eq!(&link.as_bytes()[..14], b"epoch v2 block");
+        assert_eq!(net.epoch_for_link(&link), Some(Epoch::Epoch2));
+        assert_eq!(net.epoch_for_link(&Link::Nothing), None);
+        assert_eq!(Epoch::from_str("epoch2").unwrap(), Epoch::Epoch2);
+        assert_eq!(
+            net.epoch_signer(&Epoch::Epoch1).unwrap(),
+            *net.genesis_block().account()
+        );
+
+        let thresholds = net.work_thresholds();
+        assert_eq!(
+            thresholds.threshold(&Epoch::Epoch1, &Subtype::Receive),
+            thresholds.epoch_1
+        );
+        assert_eq!(
+            thresholds.threshold(&Epoch::Epoch2, &Subtype::Open),
+            thresholds.epoch_2_receive
+        );
+        assert_eq!(
+            thresholds.threshold(&Epoch::Epoch2, &Subtype::Epoch),
+            thresholds.epoch_2_receive
+        );
+        assert_eq!(
+            thresholds.threshold(&Epoch::Epoch2, &Subtype::Send),
+            thresholds.epoch_2
+        );
+    }
+}
@@ -1,13 +1,28 @@
-//! Channel commands for a node. Messages can be sent from the RPC server.
+//! Channel commands for a node. Messages can be sent from the RPC server or from peers.
+use crate::blocks::BlockHash;
+use std::net::SocketAddr;
 use tokio::sync::{mpsc, oneshot};
 
 pub type NodeCommandSender = mpsc::Sender<NodeCommand>;
 pub type NodeCommandReceiver = mpsc::Receiver<NodeCommand>;
 
 pub type PeerInfoResponseSender = oneshot::Sender<crate::rpc::calls::Peers>;
+pub type NodeIdResponseSender = oneshot::Sender<anyhow::Result<crate::Public>>;
+pub type BlockConfirmResponseSender = oneshot::Sender<anyhow::Result<bool>>;
 
 #[derive(Debug)]
 pub enum NodeCommand {
-    /// Request all currently connected peers.
-    PeerInfo(PeerInfoResponseSender),
+    /// Request all currently connected peers, with details such as node ID when `true`.
+    PeerInfo(bool, PeerInfoResponseSender),
+
+    /// Connect to these peers if we aren't already, e.g. after learning about them from a
+    /// keepalive.
+    ConnectPeers(Vec<SocketAddr>),
+
+    /// Request the public key of our node ID.
+    NodeId(NodeIdResponseSender),
+
+    /// Ask the network to confirm a block in our ledger. Responds with `false` if we don't
+    /// have the block, or the error if it couldn't be looked up.
+    BlockConfirm(BlockHash, BlockConfirmResponseSender),
 }
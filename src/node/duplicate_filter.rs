@@ -0,0 +1,97 @@
+//! Drops messages we've recently seen, before spending any time deserializing them or checking
+//! their signatures.
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+pub type ArcDuplicateFilter = Arc<Mutex<DuplicateFilter>>;
+
+/// The number of digests remembered by default.
+pub const DUPLICATE_FILTER_SIZE: usize = 256 * 1024;
+
+/// A fixed size table of payload digests. A digest is stored in the slot it hashes to, so an
+/// older digest is forgotten when a new one lands in the same slot.
+///
+/// The hasher is seeded randomly so peers can't craft payloads that evict each other.
+#[derive(Debug)]
+pub struct DuplicateFilter {
+    digests: Vec<u64>,
+    hasher: RandomState,
+}
+
+impl Default for DuplicateFilter {
+    fn default() -> Self {
+        Self::new(DUPLICATE_FILTER_SIZE)
+    }
+}
+
+impl DuplicateFilter {
+    pub fn new(size: usize) -> Self {
+        debug_assert!(size > 0);
+        Self {
+            digests: vec![0; size],
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Returns true if `payload` was seen recently. Otherwise it is remembered for next time.
+    pub fn apply(&mut self, payload: &[u8]) -> bool {
+        let digest = self.digest(payload);
+        let slot = self.slot(digest);
+        if self.digests[slot] == digest {
+            return true;
+        }
+        self.digests[slot] = digest;
+        false
+    }
+
+    /// Forget `payload`, so it's handled the next time it arrives. For payloads that failed to
+    /// be processed, which might succeed later or from another peer.
+    pub fn clear(&mut self, payload: &[u8]) {
+        let digest = self.digest(payload);
+        let slot = self.slot(digest);
+        if self.digests[slot] == digest {
+            self.digests[slot] = 0;
+        }
+    }
+
+    fn slot(&self, digest: u64) -> usize {
+        (digest % self.digests.len() as u64) as usize
+    }
+
+    fn digest(&self, payload: &[u8]) -> u64 {
+        // Zero marks an empty slot.
+        self.hasher.hash_one(payload).max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicates() {
+        let mut filter = DuplicateFilter::default();
+        assert!(!filter.apply(b"publish"));
+        assert!(filter.apply(b"publish"));
+        assert!(!filter.apply(b"confirm_ack"));
+        assert!(filter.apply(b"publish"));
+
+        filter.clear(b"publish");
+        assert!(!filter.apply(b"publish"));
+        assert!(filter.apply(b"confirm_ack"));
+
+        // Clearing a payload that isn't there leaves the one in its slot.
+        let mut filter = DuplicateFilter::new(1);
+        assert!(!filter.apply(b"a"));
+        filter.clear(b"b");
+        assert!(filter.apply(b"a"));
+
+        // Bounded: a filter with one slot only remembers the latest payload.
+        let mut filter = DuplicateFilter::new(1);
+        assert!(!filter.apply(b"a"));
+        assert!(!filter.apply(b"b"));
+        assert!(!filter.apply(b"a"));
+    }
+}
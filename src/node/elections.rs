@@ -0,0 +1,521 @@
+//! Open Representative Voting: deciding which block wins for each root.
+//!
+//! Every block we hear about that isn't in our ledger yet starts an election for its root, or
+//! joins the existing one as a fork. Representatives vote with the weight delegated to them, and
+//! a block is confirmed once its tally reaches the quorum, a percentage of the online weight.
+//! Elections that don't get there in time expire.
+use crate::blocks::{Block, BlockHash, Previous};
+use crate::{Public, Raw};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+pub type ArcElections = Arc<Mutex<Elections>>;
+
+/// Percentage of the online weight a block needs to be confirmed.
+pub const QUORUM_PERCENT: u8 = 67;
+
+/// A representative counts towards the online weight if it voted within this period.
+pub const ONLINE_WEIGHT_PERIOD: Duration = Duration::from_secs(5 * 60);
+
+/// How many confirmed roots we remember, to ignore late blocks and votes for them.
+pub const RECENTLY_CONFIRMED_SIZE: usize = 65_536;
+
+/// Elections that haven't reached quorum by this age are dropped, so they don't stay active
+/// forever when the network never votes on them.
+pub const ELECTION_EXPIRY: Duration = Duration::from_secs(5 * 60);
+
+/// How many elections can be active at once. Blocks for other roots aren't elected while this
+/// many are, though forks still join their election.
+pub const ACTIVE_ELECTIONS_SIZE: usize = 5_000;
+
+/// What competing blocks have in common. Only one block can be confirmed per root.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Root {
+    /// The first block of an account.
+    Account(Public),
+
+    /// Any other block, rooted on the block before it.
+    Previous(BlockHash),
+}
+
+impl Root {
+    pub fn of(block: &Block) -> Self {
+        match block.previous() {
+            Previous::Open => Root::Account(block.account().to_owned()),
+            Previous::Block(hash) => Root::Previous(hash.to_owned()),
+        }
+    }
+
+    /// The root as it's sent in confirm requests.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Root::Account(account) => account.as_bytes(),
+            Root::Previous(hash) => hash.as_bytes(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ElectionStatus {
+    /// Waiting for enough votes.
+    Active,
+
+    /// The block won its election.
+    Confirmed,
+}
+
+#[derive(Debug, Clone)]
+pub struct ElectionConfig {
+    pub quorum_percent: u8,
+
+    /// The quorum is never calculated from less than this, so a few representatives can't
+    /// confirm blocks while the rest of the network is out of sight.
+    pub online_weight_minimum: Raw,
+}
+
+impl Default for ElectionConfig {
+    /// The values used by the live network: 67% of at least 60M Nano.
+    fn default() -> Self {
+        Self {
+            quorum_percent: QUORUM_PERCENT,
+            online_weight_minimum: Raw::from(60_000_000 * 10u128.pow(30)),
+        }
+    }
+}
+
+/// The latest vote of a representative in an election.
+#[derive(Debug, Clone)]
+struct Vote {
+    hash: BlockHash,
+    timestamp: u64,
+    weight: u128,
+}
+
+/// The blocks competing for one root and the votes for them.
+#[derive(Debug)]
+pub struct Election {
+    root: Root,
+    blocks: HashMap<BlockHash, Block>,
+    votes: HashMap<Public, Vote>,
+    pub started: Instant,
+}
+
+impl Election {
+    fn new(root: Root, started: Instant) -> Self {
+        Self {
+            root,
+            blocks: HashMap::new(),
+            votes: HashMap::new(),
+            started,
+        }
+    }
+
+    pub fn root(&self) -> &Root {
+        &self.root
+    }
+
+    /// More than one block is competing for the root.
+    pub fn is_fork(&self) -> bool {
+        self.blocks.len() > 1
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    /// The total weight voting for each block.
+    pub fn tally(&self) -> HashMap<BlockHash, Raw> {
+        let mut tally: HashMap<BlockHash, u128> = HashMap::new();
+        for vote in self.votes.values() {
+            let total = tally.entry(vote.hash.to_owned()).or_default();
+            *total = total.saturating_add(vote.weight);
+        }
+        tally
+            .into_iter()
+            .map(|(hash, weight)| (hash, Raw::from(weight)))
+            .collect()
+    }
+
+    /// The block with the most weight, if any block has votes.
+    pub fn leader(&self) -> Option<(BlockHash, Raw)> {
+        self.tally()
+            .into_iter()
+            .filter(|(hash, _)| self.blocks.contains_key(hash))
+            .max_by_key(|(_, weight)| weight.to_u128())
+    }
+}
+
+/// All active elections, and the online weight they are decided against.
+#[derive(Debug)]
+pub struct Elections {
+    config: ElectionConfig,
+    active: HashMap<Root, Election>,
+
+    /// Which root each block in an active election belongs to.
+    roots: HashMap<BlockHash, Root>,
+
+    /// The weight of each representative and when it last voted.
+    online: HashMap<Public, (u128, Instant)>,
+
+    /// Winners of recently finished elections, oldest first.
+    recently_confirmed: VecDeque<(Root, BlockHash)>,
+    confirmed_roots: HashSet<Root>,
+    confirmed_hashes: HashSet<BlockHash>,
+}
+
+impl Default for Elections {
+    fn default() -> Self {
+        Self::new(ElectionConfig::default())
+    }
+}
+
+impl Elections {
+    pub fn new(config: ElectionConfig) -> Self {
+        Self {
+            config,
+            active: HashMap::new(),
+            roots: HashMap::new(),
+            online: HashMap::new(),
+            recently_confirmed: VecDeque::new(),
+            confirmed_roots: HashSet::new(),
+            confirmed_hashes: HashSet::new(),
+        }
+    }
+
+    /// Start an election for the root of `block`, or add it to the existing one as a fork.
+    /// Returns false if we already know about the block, its root has been confirmed, or there
+    /// are too many active elections to start another.
+    pub fn insert(&mut self, block: Block, now: Instant) -> anyhow::Result<bool> {
+        let hash = block.hash()?.to_owned();
+        self.expire(now);
+        if self.roots.contains_key(&hash) || self.confirmed_hashes.contains(&hash) {
+            return Ok(false);
+        }
+
+        let root = Root::of(&block);
+        if self.confirmed_roots.contains(&root) {
+            info!("Ignoring {:?} since {:?} is already confirmed", hash, root);
+            return Ok(false);
+        }
+        if !self.active.contains_key(&root) && self.active.len() >= ACTIVE_ELECTIONS_SIZE {
+            debug!(
+                "Not electing {:?} with {} elections active",
+                hash,
+                self.active.len()
+            );
+            return Ok(false);
+        }
+
+        let election = self
+            .active
+            .entry(root.clone())
+            .or_insert_with(|| Election::new(root.clone(), now));
+        election.blocks.insert(hash.clone(), block);
+        if election.is_fork() {
+            warn!(
+                "Fork for {:?}: {} competing blocks",
+                root,
+                election.blocks.len()
+            );
+        } else {
+            debug!("Started election for {:?} with {:?}", root, hash);
+        }
+        self.roots.insert(hash, root);
+        Ok(true)
+    }
+
+    pub fn election(&self, root: &Root) -> Option<&Election> {
+        self.active.get(root)
+    }
+
+    pub fn election_for_block(&self, hash: &BlockHash) -> Option<&Election> {
+        self.roots.get(hash).and_then(|root| self.active.get(root))
+    }
+
+    pub fn active_count(&self) -> usize {
+        self.active.len()
+    }
+
+    /// None if the block isn't in an election we know about.
+    pub fn status(&self, hash: &BlockHash) -> Option<ElectionStatus> {
+        if self.confirmed_hashes.contains(hash) {
+            Some(ElectionStatus::Confirmed)
+        } else if self.roots.contains_key(hash) {
+            Some(ElectionStatus::Active)
+        } else {
+            None
+        }
+    }
+
+    /// Count a vote with the representative's delegated `weight`. A representative only has one
+    /// vote per election, so a newer vote replaces its previous one, even for another block.
+    ///
+    /// Returns the winning block if this vote confirmed it.
+    pub fn vote(
+        &mut self,
+        representative: &Public,
+        weight: Raw,
+        hash: &BlockHash,
+        timestamp: u64,
+        now: Instant,
+    ) -> Option<Block> {
+        let weight = weight.to_u128();
+        self.online.insert(representative.to_owned(), (weight, now));
+
+        let root = self.roots.get(hash)?.to_owned();
+        let quorum = self.quorum(now).to_u128();
+        let election = self.active.get_mut(&root)?;
+
+        if let Some(previous) = election.votes.get(representative) {
+            if timestamp <= previous.timestamp {
+                return None;
+            }
+        }
+        election.votes.insert(
+            representative.to_owned(),
+            Vote {
+                hash: hash.to_owned(),
+                timestamp,
+                weight,
+            },
+        );
+
+        let (winner, tally) = election.leader()?;
+        if tally.to_u128() == 0 || tally.to_u128() < quorum {
+            return None;
+        }
+        self.confirm(&root, &winner)
+    }
+
+    /// Drop the elections that started more than [ELECTION_EXPIRY] before `now`. Their blocks
+    /// can be elected again if we hear about them later.
+    pub fn expire(&mut self, now: Instant) {
+        let roots = &mut self.roots;
+        self.active.retain(|root, election| {
+            if now.saturating_duration_since(election.started) < ELECTION_EXPIRY {
+                return true;
+            }
+            info!("Election for {:?} expired without quorum", root);
+            for hash in election.blocks.keys() {
+                roots.remove(hash);
+            }
+            false
+        });
+    }
+
+    /// End the election for `root` with `winner`, returning the winning block.
+    fn confirm(&mut self, root: &Root, winner: &BlockHash) -> Option<Block> {
+        let mut election = self.active.remove(root)?;
+        for hash in election.blocks.keys() {
+            self.roots.remove(hash);
+        }
+        info!(
+            "Confirmed {:?} for {:?} after {:?}",
+            winner,
+            root,
+            election.started.elapsed()
+        );
+
+        self.recently_confirmed
+            .push_back((root.to_owned(), winner.to_owned()));
+        self.confirmed_roots.insert(root.to_owned());
+        self.confirmed_hashes.insert(winner.to_owned());
+        if self.recently_confirmed.len() > RECENTLY_CONFIRMED_SIZE {
+            if let Some((root, hash)) = self.recently_confirmed.pop_front() {
+                self.confirmed_roots.remove(&root);
+                self.confirmed_hashes.remove(&hash);
+            }
+        }
+
+        election.blocks.remove(winner)
+    }
+
+    /// The weight of representatives that voted within [ONLINE_WEIGHT_PERIOD].
+    /// Also forgets representatives that haven't.
+    pub fn online_weight(&mut self, now: Instant) -> Raw {
+        self.online
+            .retain(|_, (_, seen)| now.saturating_duration_since(*seen) < ONLINE_WEIGHT_PERIOD);
+        Raw::from(
+            self.online
+                .values()
+                .fold(0u128, |sum, (weight, _)| sum.saturating_add(*weight)),
+        )
+    }
+
+    /// The weight a block needs to be confirmed.
+    pub fn quorum(&mut self, now: Instant) -> Raw {
+        let online = self
+            .online_weight(now)
+            .to_u128()
+            .max(self.config.online_weight_minimum.to_u128());
+        // Divide first so the multiplication can't overflow.
+        Raw::from(online / 100 * self.config.quorum_percent as u128)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{BlockType, Link, ValidationState};
+    use crate::Private;
+    use std::convert::TryFrom;
+
+    fn block(previous: Previous, balance: u128) -> Block {
+        Block::new(
+            BlockType::State,
+            Public::zero(),
+            previous,
+            Public::zero(),
+            Raw::from(balance),
+            Link::Nothing,
+            ValidationState::Valid,
+        )
+    }
+
+    fn rep() -> Public {
+        Private::random().to_public().unwrap()
+    }
+
+    fn elections() -> Elections {
+        Elections::new(ElectionConfig {
+            quorum_percent: QUORUM_PERCENT,
+            online_weight_minimum: Raw::from(100),
+        })
+    }
+
+    #[test]
+    fn confirm_with_quorum() {
+        let now = Instant::now();
+        let mut elections = elections();
+        let b = block(Previous::Open, 1);
+        let hash = b.hash().unwrap().to_owned();
+        assert!(elections.insert(b.clone(), now).unwrap());
+        assert!(!elections.insert(b, now).unwrap());
+        assert_eq!(elections.status(&hash), Some(ElectionStatus::Active));
+
+        // 67% of the minimum online weight of 100.
+        assert_eq!(elections.quorum(now), 67);
+        assert!(elections
+            .vote(&rep(), Raw::from(40), &hash, 1, now)
+            .is_none());
+        let winner = elections.vote(&rep(), Raw::from(30), &hash, 1, now);
+        assert_eq!(winner.unwrap().hash().unwrap(), &hash);
+        assert_eq!(elections.status(&hash), Some(ElectionStatus::Confirmed));
+        assert_eq!(elections.active_count(), 0);
+
+        // Late votes and blocks for the root are ignored.
+        assert!(elections
+            .vote(&rep(), Raw::from(100), &hash, 1, now)
+            .is_none());
+        assert!(!elections.insert(block(Previous::Open, 2), now).unwrap());
+    }
+
+    #[test]
+    fn fork_goes_to_heaviest() {
+        let now = Instant::now();
+        let mut elections = elections();
+        let previous = Previous::Block(BlockHash::zero());
+        let a = block(previous.clone(), 1);
+        let b = block(previous, 2);
+        let a_hash = a.hash().unwrap().to_owned();
+        let b_hash = b.hash().unwrap().to_owned();
+        elections.insert(a, now).unwrap();
+        elections.insert(b, now).unwrap();
+        assert!(elections.election_for_block(&a_hash).unwrap().is_fork());
+
+        let (r1, r2) = (rep(), rep());
+        assert!(elections
+            .vote(&r1, Raw::from(50), &a_hash, 1, now)
+            .is_none());
+        assert!(elections
+            .vote(&r2, Raw::from(60), &b_hash, 1, now)
+            .is_none());
+        let election = elections.election_for_block(&a_hash).unwrap();
+        assert_eq!(election.leader(), Some((b_hash.clone(), Raw::from(60))));
+
+        // An old vote doesn't replace a newer one.
+        assert!(elections
+            .vote(&r1, Raw::from(50), &b_hash, 0, now)
+            .is_none());
+
+        // r1 changes its mind, and b now has all the online weight.
+        let winner = elections.vote(&r1, Raw::from(50), &b_hash, 2, now);
+        assert_eq!(winner.unwrap().hash().unwrap(), &b_hash);
+        assert_eq!(elections.status(&a_hash), None);
+    }
+
+    #[test]
+    fn online_weight_expires() {
+        let now = Instant::now();
+        let mut elections = Elections::new(ElectionConfig {
+            quorum_percent: 50,
+            online_weight_minimum: Raw::zero(),
+        });
+        let hash = BlockHash::zero();
+        elections.vote(&rep(), Raw::from(1000), &hash, 1, now);
+        elections.vote(
+            &rep(),
+            Raw::from(200),
+            &hash,
+            1,
+            now + ONLINE_WEIGHT_PERIOD / 2,
+        );
+        assert_eq!(elections.online_weight(now), 1200);
+        assert_eq!(elections.quorum(now), 600);
+
+        let later = now + ONLINE_WEIGHT_PERIOD;
+        assert_eq!(elections.online_weight(later), 200);
+        assert_eq!(elections.quorum(later), 100);
+    }
+
+    #[test]
+    fn elections_expire() {
+        let now = Instant::now();
+        let mut elections = elections();
+        let b = block(Previous::Open, 1);
+        let hash = b.hash().unwrap().to_owned();
+        elections.insert(b.clone(), now).unwrap();
+        assert!(elections
+            .vote(&rep(), Raw::from(10), &hash, 1, now)
+            .is_none());
+
+        elections.expire(now + ELECTION_EXPIRY / 2);
+        assert_eq!(elections.status(&hash), Some(ElectionStatus::Active));
+
+        let later = now + ELECTION_EXPIRY;
+        elections.expire(later);
+        assert_eq!(elections.status(&hash), None);
+        assert_eq!(elections.active_count(), 0);
+
+        // The block can be elected again.
+        assert!(elections.insert(b, later).unwrap());
+        assert_eq!(elections.status(&hash), Some(ElectionStatus::Active));
+    }
+
+    #[test]
+    fn active_elections_are_capped() {
+        let now = Instant::now();
+        let mut elections = elections();
+        let on = |n: usize| {
+            let mut previous = [0u8; BlockHash::LEN];
+            previous[..8].copy_from_slice(&(n as u64).to_be_bytes());
+            Previous::Block(BlockHash::try_from(previous.as_ref()).unwrap())
+        };
+        for n in 0..ACTIVE_ELECTIONS_SIZE {
+            assert!(elections.insert(block(on(n), 1), now).unwrap());
+        }
+
+        // A new root waits for room, but a fork joins its election.
+        let new_root = block(on(ACTIVE_ELECTIONS_SIZE), 1);
+        assert!(!elections.insert(new_root.clone(), now).unwrap());
+        assert!(elections.insert(block(on(0), 2), now).unwrap());
+        assert_eq!(elections.active_count(), ACTIVE_ELECTIONS_SIZE);
+
+        // Expired elections make room.
+        assert!(elections.insert(new_root, now + ELECTION_EXPIRY).unwrap());
+        assert_eq!(elections.active_count(), 1);
+    }
+}
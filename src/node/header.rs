@@ -17,13 +17,17 @@ pub struct Header {
 
     /// Network: live (C 0x43), beta (B 0x42), test (A 0x41).
     /// https://github.com/nanocurrency/nano-node/blob/8c650ee8f537c3ded9a4a518f5f7df56c6a67904/nano/secure/common.cpp#L89
-    network: Network,
+    ///
+    /// Kept as a raw byte, since a dev network picks its own.
+    network: u8,
 
     /// Protocol version
     /// https://github.com/nanocurrency/nano-node/blob/8c650ee8f537c3ded9a4a518f5f7df56c6a67904/nano/secure/common.hpp#L350
-    version_max: Version,
-    version_using: Version,
-    version_min: Version,
+    ///
+    /// Kept as raw bytes, since peers may advertise versions newer than we know about.
+    version_max: u8,
+    version_using: u8,
+    version_min: u8,
 
     /// Type of data in the payload.
     /// https://github.com/nanocurrency/nano-node/blob/8c650ee8f537c3ded9a4a518f5f7df56c6a67904/nano/node/common.hpp#L162
@@ -34,18 +38,35 @@ pub struct Header {
 }
 
 impl Header {
+    /// Check the header is for our network. See [Self::negotiate] for the versions.
     pub fn validate(&self, network: &Network) -> anyhow::Result<()> {
-        if &self.network != network {
+        if self.network != network.magic() {
             return Err(anyhow!(
-                "network mismatch: They're on {:?}. We're on {:?}",
+                "network mismatch: They're on {:#04X}. We're on {} ({:#04X})",
                 self.network,
                 network,
+                network.magic(),
             ));
         }
+        Ok(())
+    }
 
-        // TODO: Check versions.
+    /// The newest version both sides support, which decides the message formats we use with
+    /// the peer. Fails if the sender's versions don't overlap with ours.
+    pub fn negotiate(&self) -> anyhow::Result<Version> {
+        let highest = self.version_max.min(Version::MAX as u8);
+        let lowest = self.version_min.max(Version::MIN as u8);
+        if self.version_min > self.version_max || highest < lowest {
+            return Err(anyhow!(
+                "version mismatch: They support {}-{}. We support {}-{}",
+                self.version_min,
+                self.version_max,
+                Version::MIN,
+                Version::MAX,
+            ));
+        }
 
-        Ok(())
+        Ok(Version::try_from(highest)?)
     }
 
     pub fn to_short_string(&self) -> String {
@@ -68,10 +89,10 @@ impl Header {
     pub fn new(network: Network, message_type: MessageType, ext: Extensions) -> Self {
         Self {
             magic_number: MagicNumber::new(),
-            network,
-            version_max: Version::V18,
-            version_using: Version::V18,
-            version_min: Version::V18,
+            network: network.magic(),
+            version_max: Version::MAX as u8,
+            version_using: Version::MAX as u8,
+            version_min: Version::MIN as u8,
             message_type,
             ext,
         }
@@ -90,16 +111,34 @@ impl Header {
     pub fn ext(&self) -> Extensions {
         self.ext
     }
+
+    pub fn version_max(&self) -> u8 {
+        self.version_max
+    }
+
+    pub fn version_using(&self) -> u8 {
+        self.version_using
+    }
+
+    pub fn version_min(&self) -> u8 {
+        self.version_min
+    }
+
+    /// Advertise the version we've negotiated with the peer.
+    pub fn set_version_using(&mut self, version: Version) -> &mut Self {
+        self.version_using = version as u8;
+        self
+    }
 }
 
 impl Wire for Header {
     fn serialize(&self) -> Vec<u8> {
         vec![
             self.magic_number.0,
-            self.network as u8,
-            self.version_max as u8,
-            self.version_using as u8,
-            self.version_min as u8,
+            self.network,
+            self.version_max,
+            self.version_using,
+            self.version_min,
             self.message_type as u8,
             self.ext.0[0],
             self.ext.0[1],
@@ -114,12 +153,19 @@ impl Wire for Header {
         expect_len(data.len(), Header::LEN, "Header")?;
         MagicNumber::try_from(data[Self::MAGIC_NUMBER]).with_context(context)?;
 
-        let network = Network::try_from(data[Self::NETWORK]).with_context(context)?;
         let message_type = MessageType::try_from(data[Self::MESSAGE_TYPE])?;
         let ext =
             Extensions::try_from(&data[Self::EXTENSIONS..Self::EXTENSIONS + Extensions::LEN])?;
 
-        Ok(Header::new(network, message_type, ext))
+        Ok(Self {
+            magic_number: MagicNumber::new(),
+            network: data[Self::NETWORK],
+            version_max: data[Self::VERSION_MAX],
+            version_using: data[Self::VERSION_USING],
+            version_min: data[Self::VERSION_MIN],
+            message_type,
+            ext,
+        })
     }
 
     fn len(_: Option<&Header>) -> anyhow::Result<usize> {
@@ -207,6 +253,13 @@ impl Extensions {
     // Bit offsets and lengths
     const QUERY: usize = 0;
     const RESPONSE: usize = 1;
+    /// Used by handshakes for the salted response with the genesis hash.
+    const V2: usize = 2;
+    /// Used by bulk pull, sharing the bit with [Self::QUERY].
+    const COUNT_PRESENT: usize = 0;
+    /// Used by telemetry ack for the payload size.
+    const TELEMETRY_SIZE: usize = 0;
+    const TELEMETRY_SIZE_BITS: usize = 10;
     const ITEM_COUNT: usize = 12;
     const ITEM_COUNT_BITS: usize = 4;
     const BLOCK_TYPE: usize = 8;
@@ -234,10 +287,55 @@ impl Extensions {
         self.bits()[Self::RESPONSE]
     }
 
+    /// A handshake query asking for a v2 response, or a handshake response in the v2 format.
+    pub fn v2(&mut self) -> &mut Self {
+        self.mut_bits().set(Self::V2, true);
+        self
+    }
+
+    pub fn is_v2(&self) -> bool {
+        self.bits()[Self::V2]
+    }
+
+    /// A bulk pull has a count after the end hash.
+    pub fn count_present(&mut self) -> &mut Self {
+        self.mut_bits().set(Self::COUNT_PRESENT, true);
+        self
+    }
+
+    pub fn is_count_present(&self) -> bool {
+        self.bits()[Self::COUNT_PRESENT]
+    }
+
+    /// The payload size of a telemetry ack.
+    pub fn set_telemetry_size(&mut self, size: usize) -> &mut Self {
+        self.mut_bits()[Self::TELEMETRY_SIZE..Self::TELEMETRY_SIZE + Self::TELEMETRY_SIZE_BITS]
+            .store_le(size);
+        self
+    }
+
+    pub fn telemetry_size(&self) -> usize {
+        self.bits()[Self::TELEMETRY_SIZE..Self::TELEMETRY_SIZE + Self::TELEMETRY_SIZE_BITS]
+            .load_le()
+    }
+
+    /// The number of hashes in a vote or confirm request. Only four bits are available.
+    pub fn set_item_count(&mut self, count: usize) -> &mut Self {
+        debug_assert!(count < 1 << Self::ITEM_COUNT_BITS);
+        self.mut_bits()[Self::ITEM_COUNT..Self::ITEM_COUNT + Self::ITEM_COUNT_BITS].store_be(count);
+        self
+    }
+
     pub fn item_count(&self) -> usize {
         self.bits()[Self::ITEM_COUNT..Self::ITEM_COUNT + Self::ITEM_COUNT_BITS].load_be()
     }
 
+    pub fn set_block_type(&mut self, block_type: &BlockType) -> &mut Self {
+        self.mut_bits()[Self::BLOCK_TYPE..Self::BLOCK_TYPE + Self::BLOCK_TYPE_BITS]
+            .store_be(block_type.as_u8());
+        self
+    }
+
     pub fn block_type(&self) -> anyhow::Result<BlockType> {
         self.bits()[Self::BLOCK_TYPE..Self::BLOCK_TYPE + Self::BLOCK_TYPE_BITS]
             .load_be::<u8>()
@@ -262,6 +360,9 @@ impl std::fmt::Debug for Extensions {
         if self.is_response() {
             s.push("Response")
         }
+        if self.is_v2() {
+            s.push("V2")
+        }
         write!(f, "[{}]", s.join(", "))?;
 
         Ok(())
@@ -298,7 +399,7 @@ mod tests {
         let h1 = Header::new(network, MessageType::Keepalive, ext);
         let s = h1.serialize();
         assert_eq!(s.len(), Header::LEN);
-        assert_eq!(s, vec![0x52, 0x43, 18, 18, 18, 2, 3, 0]);
+        assert_eq!(s, vec![0x52, 0x43, 20, 20, 18, 2, 3, 0]);
 
         let h2 = Header::deserialize(None, &s).unwrap();
         assert_eq!(h1, h2);
@@ -340,6 +441,31 @@ mod tests {
         assert_contains_err(result, "network mismatch");
     }
 
+    #[test]
+    fn versions() {
+        let negotiate = |max: u8, using: u8, min: u8| {
+            let s = vec![0x52, 0x43, max, using, min, 2, 0, 0];
+            let header = Header::deserialize(None, &s).unwrap();
+            assert_eq!(header.version_using(), using);
+            header.validate(&Network::Live).unwrap();
+            header.negotiate()
+        };
+        assert_eq!(negotiate(18, 18, 18).unwrap(), Version::V18);
+        assert_eq!(negotiate(19, 19, 17).unwrap(), Version::V19);
+        assert_eq!(negotiate(20, 20, 18).unwrap(), Version::V20);
+
+        // Newer than we know about, but they can still talk to us.
+        assert_eq!(negotiate(25, 25, 19).unwrap(), Version::V20);
+
+        assert_contains_err(negotiate(17, 17, 16), "version mismatch");
+        assert_contains_err(negotiate(22, 22, 21), "version mismatch");
+        assert_contains_err(negotiate(18, 18, 19), "version mismatch");
+
+        let mut header = Header::new(Network::Live, MessageType::Keepalive, Extensions::new());
+        header.set_version_using(Version::V18);
+        assert_eq!(header.serialize()[2..5], [20, 18, 18]);
+    }
+
     #[test]
     fn bad_message_type() {
         let s = vec![0x52, 0x43, 18, 18, 18, 100, 3, 0];
@@ -369,4 +495,19 @@ mod tests {
             assert_eq!(ext.item_count() as u8, *expected);
         }
     }
+
+    #[test]
+    fn set_item_count_and_block_type() {
+        let ext = *Extensions::new()
+            .set_block_type(&BlockType::NotABlock)
+            .set_item_count(2);
+        assert_eq!(ext.0, [0x00, 0x21]);
+        assert_eq!(ext.item_count(), 2);
+        assert_eq!(ext.block_type().unwrap(), BlockType::NotABlock);
+
+        let ext = *Extensions::new()
+            .set_item_count(15)
+            .set_block_type(&BlockType::State);
+        assert_eq!(ext.0, [0x00, 0xf6]);
+    }
 }
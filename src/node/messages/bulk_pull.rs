@@ -0,0 +1,178 @@
+use crate::blocks::{BlockHash, BlockHolder, BlockType};
+use crate::bytes::Bytes;
+use crate::node::header::{Extensions, Header};
+use crate::node::wire::Wire;
+use anyhow::{anyhow, Context};
+use std::convert::TryFrom;
+
+/// Request a chain of blocks, starting from `start` and following `previous` until `end`.
+///
+/// The response is a stream of blocks, each prefixed with its type, ending with a
+/// [BlockType::NotABlock](crate::blocks::BlockType::NotABlock).
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulkPull {
+    /// Either a block hash, or an account, which means starting from its frontier.
+    start: BlockHash,
+
+    /// Stop before this block. Zero pulls the whole chain.
+    end: BlockHash,
+
+    /// Maximum number of blocks to send.
+    count: Option<u32>,
+}
+
+impl BulkPull {
+    pub const LEN: usize = BlockHash::LEN * 2;
+
+    /// Only sent when [Extensions::is_count_present] is set.
+    pub const EXTENDED_PARAMETERS_LEN: usize = 8;
+
+    pub fn new(start: BlockHash, end: BlockHash, count: Option<u32>) -> Self {
+        Self { start, end, count }
+    }
+
+    pub fn start(&self) -> &BlockHash {
+        &self.start
+    }
+
+    pub fn end(&self) -> &BlockHash {
+        &self.end
+    }
+
+    pub fn count(&self) -> Option<u32> {
+        self.count
+    }
+
+    /// The header extensions needed to send this request.
+    pub fn extensions(&self) -> Extensions {
+        let mut ext = Extensions::new();
+        if self.count.is_some() {
+            ext.count_present();
+        }
+        ext
+    }
+}
+
+fn has_count(header: Option<&Header>) -> bool {
+    matches!(header, Some(h) if h.ext().is_count_present())
+}
+
+impl Wire for BulkPull {
+    fn serialize(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(Self::LEN + Self::EXTENDED_PARAMETERS_LEN);
+        v.extend_from_slice(self.start.as_bytes());
+        v.extend_from_slice(self.end.as_bytes());
+        if let Some(count) = self.count {
+            // Zero, count, then three reserved bytes.
+            v.push(0);
+            v.extend_from_slice(&count.to_le_bytes());
+            v.extend_from_slice(&[0, 0, 0]);
+        }
+        v
+    }
+
+    fn deserialize(header: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut bytes = Bytes::new(data);
+        let start = BlockHash::try_from(bytes.slice(BlockHash::LEN)?).context("Bulk pull start")?;
+        let end = BlockHash::try_from(bytes.slice(BlockHash::LEN)?).context("Bulk pull end")?;
+
+        let count = if has_count(header) {
+            let extended = bytes.slice(Self::EXTENDED_PARAMETERS_LEN)?;
+            let mut count = [0u8; 4];
+            count.copy_from_slice(&extended[1..5]);
+            Some(u32::from_le_bytes(count))
+        } else {
+            None
+        };
+
+        Ok(Self { start, end, count })
+    }
+
+    fn len(header: Option<&Header>) -> anyhow::Result<usize> {
+        if has_count(header) {
+            Ok(Self::LEN + Self::EXTENDED_PARAMETERS_LEN)
+        } else {
+            Ok(Self::LEN)
+        }
+    }
+}
+
+/// One entry in the response stream of a [BulkPull].
+#[derive(Debug)]
+pub enum BulkPullResp {
+    Block(Box<BlockHolder>),
+
+    /// The end of the stream.
+    End,
+}
+
+impl BulkPullResp {
+    /// The length of a response, including the type, given its first byte.
+    pub fn len_from_type(block_type: u8) -> anyhow::Result<usize> {
+        match BlockType::try_from(block_type)? {
+            BlockType::NotABlock => Ok(1),
+            t => Ok(1 + BlockHolder::len_for_type(&t)?),
+        }
+    }
+}
+
+impl Wire for BulkPullResp {
+    fn serialize(&self) -> Vec<u8> {
+        match self {
+            BulkPullResp::Block(holder) => {
+                let mut v = vec![holder.block_type().as_u8()];
+                v.extend(holder.serialize());
+                v
+            }
+            BulkPullResp::End => vec![BlockType::NotABlock.as_u8()],
+        }
+    }
+
+    fn deserialize(_: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut bytes = Bytes::new(data);
+        let block_type = BlockType::try_from(bytes.u8()?)?;
+        if block_type == BlockType::NotABlock {
+            return Ok(BulkPullResp::End);
+        }
+        let data = bytes.slice(BlockHolder::len_for_type(&block_type)?)?;
+        Ok(BulkPullResp::Block(Box::new(
+            BlockHolder::deserialize_typed(&block_type, data)?,
+        )))
+    }
+
+    /// The length depends on the block type, which is the first byte of the response. Use
+    /// [BulkPullResp::len_from_type] instead.
+    fn len(_: Option<&Header>) -> anyhow::Result<usize> {
+        Err(anyhow!(
+            "Bulk pull response length depends on the block type"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Network;
+    use crate::node::header::MessageType;
+    use std::str::FromStr;
+
+    #[test]
+    fn serialize() {
+        let start =
+            BlockHash::from_str("991CF190094C00F0B68E2E5F75F6BEE95A2E0BD93CEAA4A6734DB9F19B728948")
+                .unwrap();
+        for count in &[None, Some(100)] {
+            let pull = BulkPull::new(start.clone(), BlockHash::zero(), *count);
+            let header = Header::new(Network::Live, MessageType::BulkPull, pull.extensions());
+            let data = pull.serialize();
+            assert_eq!(data.len(), BulkPull::len(Some(&header)).unwrap());
+            assert_eq!(BulkPull::deserialize(Some(&header), &data).unwrap(), pull);
+        }
+    }
+}
@@ -1,15 +1,15 @@
-use crate::blocks::{Block, BlockHash, BlockType};
+use crate::blocks::{BlockHash, BlockHolder, BlockType};
 use crate::bytes::Bytes;
 use crate::encoding::blake2b;
-use crate::node::header::Header;
+use crate::node::header::{Extensions, Header};
 use crate::node::timestamp::Timestamp;
 use crate::node::wire::Wire;
-use crate::{Public, Signature};
+use crate::{Private, Public, Signature};
 use anyhow::Context;
 use std::convert::TryFrom;
 
 /// This is a vote on the network by a representative for one or more block hashes.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ConfirmAck {
     pub account: Public,
     pub signature: Signature,
@@ -18,12 +18,22 @@ pub struct ConfirmAck {
     pub confirm: Confirm,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Confirm {
     VoteByHash(Vec<BlockHash>),
 
     // TODO: This looks like it isn't used on the live network.
-    Block(Block),
+    Block(BlockHolder),
+}
+
+impl Confirm {
+    /// The hashes of the blocks being voted for.
+    pub fn hashes(&self) -> Vec<BlockHash> {
+        match self {
+            Confirm::VoteByHash(hashes) => hashes.to_owned(),
+            Confirm::Block(block) => vec![block.hash()],
+        }
+    }
 }
 
 impl ConfirmAck {
@@ -43,12 +53,37 @@ impl ConfirmAck {
         }
     }
 
+    /// Set the account to the public key of `private` and sign the vote with it.
+    pub fn sign(&mut self, private: &Private) -> anyhow::Result<()> {
+        self.account = private.to_public()?;
+        self.signature = private.sign(&self.inner_hash())?;
+        Ok(())
+    }
+
     pub fn verify_signature(&self) -> anyhow::Result<()> {
         self.account
             .verify(&self.inner_hash(), &self.signature)
             .context("Verify signature on ConfirmAck")
     }
 
+    /// Verify the signatures of many votes at once. Returns whether each one is valid.
+    ///
+    /// All signatures are checked in one batch first. Only if that fails are they checked one by
+    /// one, to find out which are bad.
+    pub fn verify_signatures(votes: &[ConfirmAck]) -> Vec<bool> {
+        let hashes: Vec<Vec<u8>> = votes.iter().map(|vote| vote.inner_hash()).collect();
+        let messages: Vec<&[u8]> = hashes.iter().map(|hash| hash.as_slice()).collect();
+        let signatures: Vec<Signature> = votes.iter().map(|vote| vote.signature.clone()).collect();
+        let accounts: Vec<Public> = votes.iter().map(|vote| vote.account.clone()).collect();
+        if Public::verify_batch(&messages, &signatures, &accounts).is_ok() {
+            return vec![true; votes.len()];
+        }
+        votes
+            .iter()
+            .map(|vote| vote.verify_signature().is_ok())
+            .collect()
+    }
+
     // nano::block_hash nano::vote::hash () const
     pub fn inner_hash(&self) -> Vec<u8> {
         let mut v = Vec::new();
@@ -56,22 +91,40 @@ impl ConfirmAck {
         // TODO: Only add this prefix if there's data. See nano::vote::hash()
         v.extend_from_slice("vote ".as_bytes());
 
-        if let Confirm::VoteByHash(hashes) = &self.confirm {
-            for hash in hashes {
-                v.extend_from_slice(hash.as_bytes())
-            }
-            v.extend_from_slice(&self.timestamp.to_bytes())
-        } else {
-            todo!("handle block hash");
+        for hash in self.confirm.hashes() {
+            v.extend_from_slice(hash.as_bytes())
         }
+        v.extend_from_slice(&self.timestamp.to_bytes());
 
         blake2b(BlockHash::LEN, &v).to_vec()
     }
+
+    /// The header extensions needed to send this vote.
+    pub fn extensions(&self) -> Extensions {
+        match &self.confirm {
+            Confirm::VoteByHash(hashes) => *Extensions::new()
+                .set_block_type(&BlockType::NotABlock)
+                .set_item_count(hashes.len()),
+            Confirm::Block(block) => *Extensions::new().set_block_type(&block.block_type()),
+        }
+    }
 }
 
 impl Wire for ConfirmAck {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        let mut v = Vec::with_capacity(Self::VOTE_COMMON_LEN);
+        v.extend_from_slice(self.account.as_bytes());
+        v.extend_from_slice(self.signature.as_bytes());
+        v.extend_from_slice(&self.timestamp.to_bytes());
+        match &self.confirm {
+            Confirm::VoteByHash(hashes) => {
+                for hash in hashes {
+                    v.extend_from_slice(hash.as_bytes());
+                }
+            }
+            Confirm::Block(block) => v.extend_from_slice(&block.serialize()),
+        }
+        v
     }
 
     fn deserialize(header: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
@@ -84,7 +137,6 @@ impl Wire for ConfirmAck {
         let mut data = Bytes::new(data);
         let account = Public::try_from(data.slice(Public::LEN)?)?;
         let signature = Signature::try_from(data.slice(Signature::LEN)?)?;
-        // `to_vec` here to stop a borrow problem
         // Looks like this is "sequence" on the live network, but will change to "timestamp".
         let timestamp = Timestamp::try_from(data.slice(Timestamp::LEN)?)?;
         let confirm = if header.ext().block_type()? == BlockType::NotABlock {
@@ -94,9 +146,8 @@ impl Wire for ConfirmAck {
             }
             Confirm::VoteByHash(block_hashes)
         } else {
-            // let block = Block;
-            dbg!("block!!!!!!!", header.ext().block_type().unwrap());
-            todo!()
+            let block = data.slice(BlockHolder::len(Some(header))?)?;
+            Confirm::Block(BlockHolder::deserialize(Some(header), block)?)
         };
 
         Ok(Self::new(account, signature, timestamp, confirm))
@@ -109,8 +160,7 @@ impl Wire for ConfirmAck {
         if header.ext().block_type()? == BlockType::NotABlock {
             Ok(Self::VOTE_COMMON_LEN + header.ext().item_count() * BlockHash::LEN)
         } else {
-            todo!("got a block in confirm ack {:#?}", header);
-            // Ok(Self::VOTE_COMMON_LEN + FullBlock::LEN)
+            Ok(Self::VOTE_COMMON_LEN + BlockHolder::len(Some(header))?)
         }
     }
 }
@@ -118,6 +168,8 @@ impl Wire for ConfirmAck {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::network::Network;
+    use crate::node::header::MessageType;
     use std::str::FromStr;
 
     #[test]
@@ -140,5 +192,69 @@ mod tests {
             Confirm::VoteByHash(vec![hash1, hash2]),
         );
         assert!(confirm_ack.verify_signature().is_ok());
+
+        // The fixture survives the trip through its wire format.
+        let header = Header::new(
+            Network::Live,
+            MessageType::ConfirmAck,
+            confirm_ack.extensions(),
+        );
+        let data = confirm_ack.serialize();
+        assert_eq!(data.len(), ConfirmAck::len(Some(&header)).unwrap());
+        assert_eq!(&data[..Public::LEN], confirm_ack.account.as_bytes());
+        let decoded = ConfirmAck::deserialize(Some(&header), &data).unwrap();
+        assert_eq!(decoded, confirm_ack);
+        assert!(decoded.verify_signature().is_ok());
+    }
+
+    #[test]
+    fn vote_by_block() {
+        let genesis = Network::Live.genesis_block();
+        let mut confirm_ack = ConfirmAck::new(
+            Public::zero(),
+            Signature::zero(),
+            Timestamp::from_u64(1),
+            Confirm::Block(genesis.to_holder().unwrap()),
+        );
+        assert_eq!(
+            confirm_ack.confirm.hashes(),
+            vec![genesis.hash().unwrap().to_owned()]
+        );
+        confirm_ack.sign(&Private::random()).unwrap();
+
+        let header = Header::new(
+            Network::Live,
+            MessageType::ConfirmAck,
+            confirm_ack.extensions(),
+        );
+        let data = confirm_ack.serialize();
+        assert_eq!(data.len(), ConfirmAck::len(Some(&header)).unwrap());
+        let decoded = ConfirmAck::deserialize(Some(&header), &data).unwrap();
+        assert_eq!(decoded, confirm_ack);
+        assert!(decoded.verify_signature().is_ok());
+    }
+
+    #[test]
+    fn verify_signatures() {
+        let private = Private::random();
+        let mut votes: Vec<ConfirmAck> = (0..10)
+            .map(|i| {
+                let mut vote = ConfirmAck::new(
+                    Public::zero(),
+                    Signature::zero(),
+                    Timestamp::from_u64(i),
+                    Confirm::VoteByHash(vec![BlockHash::zero()]),
+                );
+                vote.sign(&private).unwrap();
+                vote
+            })
+            .collect();
+        assert_eq!(ConfirmAck::verify_signatures(&votes), vec![true; 10]);
+
+        // Claim a different timestamp than the one that was signed.
+        votes[3].timestamp = Timestamp::from_u64(100);
+        let mut expected = vec![true; 10];
+        expected[3] = false;
+        assert_eq!(ConfirmAck::verify_signatures(&votes), expected);
     }
 }
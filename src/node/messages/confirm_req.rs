@@ -1,7 +1,7 @@
 use crate::blocks::{BlockHash, BlockHolder, BlockType};
 use crate::bytes::Bytes;
 use crate::encoding::expect_len;
-use crate::node::header::Header;
+use crate::node::header::{Extensions, Header};
 use crate::node::wire::Wire;
 use anyhow::Context;
 use std::convert::TryFrom;
@@ -16,7 +16,7 @@ use tracing::info;
 //  - id: block
 //    if: _root.header.block_type != enum_blocktype::not_a_block
 //    type: block_selector(_root.header.block_type_int)
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ConfirmReq {
     ConfirmReqByHash(Vec<RootHashPair>),
     BlockSelector(BlockHolder),
@@ -24,11 +24,37 @@ pub enum ConfirmReq {
 
 impl ConfirmReq {
     pub const CONFIRM_REQ_BY_HASH_LEN: usize = BlockHash::LEN * 2;
+
+    /// The most root/hash pairs that fit in one request, limited by the header item count.
+    pub const MAX_PAIRS: usize = 15;
+
+    /// The header extensions needed to send this request.
+    pub fn extensions(&self) -> Extensions {
+        match self {
+            ConfirmReq::ConfirmReqByHash(pairs) => *Extensions::new()
+                .set_block_type(&BlockType::NotABlock)
+                .set_item_count(pairs.len()),
+            ConfirmReq::BlockSelector(block) => {
+                *Extensions::new().set_block_type(&block.block_type())
+            }
+        }
+    }
 }
 
 impl Wire for ConfirmReq {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        match self {
+            ConfirmReq::ConfirmReqByHash(pairs) => {
+                debug_assert!(pairs.len() <= Self::MAX_PAIRS);
+                let mut v = Vec::with_capacity(RootHashPair::LEN * pairs.len());
+                for pair in pairs {
+                    v.extend_from_slice(pair.hash.as_bytes());
+                    v.extend_from_slice(pair.root.as_bytes());
+                }
+                v
+            }
+            ConfirmReq::BlockSelector(block) => block.serialize(),
+        }
     }
 
     fn deserialize(header: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
@@ -49,7 +75,7 @@ impl Wire for ConfirmReq {
                 "HandleConfirmReq root hash pairs",
             )?;
 
-            let mut pairs = Vec::with_capacity(expected_capacity);
+            let mut pairs = Vec::with_capacity(count);
             for _ in 0..count {
                 let value = bytes
                     .slice(RootHashPair::LEN)
@@ -90,7 +116,7 @@ impl Wire for ConfirmReq {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RootHashPair {
     pub hash: BlockHash,
     pub root: BlockHash,
@@ -111,3 +137,37 @@ impl TryFrom<&[u8]> for RootHashPair {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Network;
+    use crate::node::header::MessageType;
+    use std::str::FromStr;
+
+    fn round_trip(req: ConfirmReq) {
+        let header = Header::new(Network::Live, MessageType::ConfirmReq, req.extensions());
+        let data = req.serialize();
+        assert_eq!(data.len(), ConfirmReq::len(Some(&header)).unwrap());
+        assert_eq!(ConfirmReq::deserialize(Some(&header), &data).unwrap(), req);
+    }
+
+    #[test]
+    fn serialize() {
+        let hash =
+            BlockHash::from_str("C3A3FE56D584CB997199E3B09EC454F62DED3B7EF875D9D7E8E5011AC34C77A5")
+                .unwrap();
+        let root =
+            BlockHash::from_str("139E1064D7CCC26495EFB4030015C02CE78556EBE3547192843B0E71C91599FC")
+                .unwrap();
+        let req = ConfirmReq::ConfirmReqByHash(vec![RootHashPair {
+            hash: hash.clone(),
+            root: root.clone(),
+        }]);
+        assert_eq!(req.serialize(), [hash.as_bytes(), root.as_bytes()].concat());
+        round_trip(req);
+
+        let genesis = Network::Live.genesis_block().to_holder().unwrap();
+        round_trip(ConfirmReq::BlockSelector(genesis));
+    }
+}
@@ -13,11 +13,39 @@ pub struct FrontierReq {
 
 impl FrontierReq {
     pub const LEN: usize = 40;
+
+    pub fn new(start: Public, age: u32, count: u32) -> Self {
+        Self { start, age, count }
+    }
+
+    /// Request every frontier, starting from the lowest account.
+    pub fn all() -> Self {
+        Self::new(Public::zero(), u32::MAX, u32::MAX)
+    }
+
+    /// Frontiers are sent in account order starting at this account.
+    pub fn start(&self) -> &Public {
+        &self.start
+    }
+
+    /// Only frontiers modified within this many seconds. `u32::MAX` means any age.
+    pub fn age(&self) -> u32 {
+        self.age
+    }
+
+    /// Maximum number of frontiers. `u32::MAX` means no limit.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
 }
 
 impl Wire for FrontierReq {
     fn serialize(&self) -> Vec<u8> {
-        vec![]
+        let mut v = Vec::with_capacity(Self::LEN);
+        v.extend_from_slice(self.start.as_bytes());
+        v.extend_from_slice(&self.age.to_le_bytes());
+        v.extend_from_slice(&self.count.to_le_bytes());
+        v
     }
 
     fn deserialize(_: Option<&Header>, data: &[u8]) -> Result<Self, anyhow::Error>
@@ -41,3 +69,24 @@ impl Wire for FrontierReq {
         Ok(Self::LEN)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn serialize() {
+        let start =
+            Public::from_str("E89208DD038FBB269987689621D52292AE9C35941A7484756ECCED92A65093BA")
+                .unwrap();
+        let req = FrontierReq::new(start.clone(), 60, 1000);
+        let data = req.serialize();
+        assert_eq!(data.len(), FrontierReq::LEN);
+
+        let req = FrontierReq::deserialize(None, &data).unwrap();
+        assert_eq!(req.start(), &start);
+        assert_eq!(req.age(), 60);
+        assert_eq!(req.count(), 1000);
+    }
+}
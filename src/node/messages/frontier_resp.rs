@@ -14,11 +14,38 @@ pub struct FrontierResp {
 
 impl FrontierResp {
     pub const LEN: usize = Public::LEN + BlockHash::LEN;
+
+    pub fn new(account: Public, frontier_hash: BlockHash) -> Self {
+        Self {
+            account,
+            frontier_hash,
+        }
+    }
+
+    /// The all zero entry that terminates a frontier stream.
+    pub fn end() -> Self {
+        Self::new(Public::zero(), BlockHash::zero())
+    }
+
+    pub fn is_end(&self) -> bool {
+        self.account == Public::zero() && self.frontier_hash == BlockHash::zero()
+    }
+
+    pub fn account(&self) -> &Public {
+        &self.account
+    }
+
+    pub fn frontier_hash(&self) -> &BlockHash {
+        &self.frontier_hash
+    }
 }
 
 impl Wire for FrontierResp {
     fn serialize(&self) -> Vec<u8> {
-        todo!()
+        let mut v = Vec::with_capacity(Self::LEN);
+        v.extend_from_slice(self.account.as_bytes());
+        v.extend_from_slice(self.frontier_hash.as_bytes());
+        v
     }
 
     fn deserialize(header: Option<&Header>, data: &[u8]) -> Result<Self, anyhow::Error>
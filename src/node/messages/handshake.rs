@@ -1,8 +1,11 @@
+use crate::blocks::BlockHash;
 use crate::bytes::Bytes;
+use crate::encoding::blake2b;
 use crate::node::cookie::Cookie;
 use crate::node::header::Header;
 use crate::node::wire::Wire;
 use crate::{Public, Signature};
+use rand::RngCore;
 use std::convert::TryFrom;
 
 #[derive(Debug)]
@@ -13,7 +16,14 @@ pub struct Handshake {
 
 impl Wire for Handshake {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        let mut v = Vec::with_capacity(HandshakeQuery::LEN + HandshakeResponse::LEN_V2);
+        if let Some(query) = &self.query {
+            v.extend_from_slice(&query.serialize());
+        }
+        if let Some(response) = &self.response {
+            v.extend_from_slice(&response.serialize());
+        }
+        v
     }
 
     fn deserialize(header: Option<&Header>, data: &[u8]) -> Result<Self, anyhow::Error>
@@ -35,9 +45,10 @@ impl Wire for Handshake {
             )?);
         }
         if header.ext().is_response() {
+            let len = HandshakeResponse::len(Some(header))?;
             s.response = Some(HandshakeResponse::deserialize(
                 Some(header),
-                bytes.slice(HandshakeResponse::LEN)?,
+                bytes.slice(len)?,
             )?);
         }
         Ok(s)
@@ -51,7 +62,7 @@ impl Wire for Handshake {
             size += HandshakeQuery::LEN
         }
         if header.ext().is_response() {
-            size += HandshakeResponse::LEN
+            size += HandshakeResponse::len(Some(header))?
         };
         Ok(size)
     }
@@ -94,35 +105,159 @@ impl Wire for HandshakeQuery {
 pub struct HandshakeResponse {
     pub public: Public,
     pub signature: Signature,
+
+    /// Only in v2 responses, flagged with [Extensions::v2](crate::node::header::Extensions::v2).
+    pub v2: Option<HandshakeV2>,
+}
+
+/// Extra fields of a v2 response. The salt stops the responder from only ever signing the
+/// cookie they were given, and the genesis hash shows which ledger they follow.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandshakeV2 {
+    pub salt: [u8; HandshakeV2::SALT_LEN],
+    pub genesis: BlockHash,
+}
+
+impl HandshakeV2 {
+    pub const SALT_LEN: usize = 32;
+
+    pub fn new(genesis: BlockHash) -> Self {
+        let mut salt = [0u8; Self::SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        Self { salt, genesis }
+    }
 }
 
 impl HandshakeResponse {
     pub const LEN: usize = Public::LEN + Signature::LEN;
+    pub const LEN_V2: usize = Self::LEN + HandshakeV2::SALT_LEN + BlockHash::LEN;
 
     pub fn new(public: Public, signature: Signature) -> Self {
-        Self { public, signature }
+        Self {
+            public,
+            signature,
+            v2: None,
+        }
+    }
+
+    pub fn new_v2(public: Public, signature: Signature, v2: HandshakeV2) -> Self {
+        Self {
+            public,
+            signature,
+            v2: Some(v2),
+        }
+    }
+
+    /// What gets signed in answer to `cookie`: the cookie itself for v1, otherwise a hash of the
+    /// cookie, salt and genesis hash.
+    pub fn signed_data(cookie: &Cookie, v2: Option<&HandshakeV2>) -> Vec<u8> {
+        match v2 {
+            None => cookie.as_bytes().to_vec(),
+            Some(v2) => {
+                let mut data =
+                    Vec::with_capacity(Cookie::LEN + HandshakeV2::SALT_LEN + BlockHash::LEN);
+                data.extend_from_slice(cookie.as_bytes());
+                data.extend_from_slice(&v2.salt);
+                data.extend_from_slice(v2.genesis.as_bytes());
+                blake2b(32, &data).to_vec()
+            }
+        }
     }
 }
 
 impl Wire for HandshakeResponse {
     fn serialize(&self) -> Vec<u8> {
-        let mut v = Vec::with_capacity(Self::LEN);
-        v.extend_from_slice(&self.public.as_bytes());
-        v.extend_from_slice(&self.signature.as_bytes());
+        let mut v = Vec::with_capacity(Self::LEN_V2);
+        v.extend_from_slice(self.public.as_bytes());
+        if let Some(v2) = &self.v2 {
+            v.extend_from_slice(&v2.salt);
+            v.extend_from_slice(v2.genesis.as_bytes());
+        }
+        v.extend_from_slice(self.signature.as_bytes());
         v
     }
 
-    fn deserialize(_: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
+    fn deserialize(header: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
     where
         Self: Sized,
     {
+        let mut bytes = Bytes::new(data);
+        let public = Public::try_from(bytes.slice(Public::LEN)?)?;
+        let v2 = if header.map(|h| h.ext().is_v2()).unwrap_or(false) {
+            let salt =
+                <[u8; HandshakeV2::SALT_LEN]>::try_from(bytes.slice(HandshakeV2::SALT_LEN)?)?;
+            let genesis = BlockHash::try_from(bytes.slice(BlockHash::LEN)?)?;
+            Some(HandshakeV2 { salt, genesis })
+        } else {
+            None
+        };
+        let signature = Signature::try_from(bytes.slice(Signature::LEN)?)?;
         Ok(Self {
-            public: Public::try_from(&data[0..Public::LEN])?,
-            signature: Signature::try_from(&data[Public::LEN..])?,
+            public,
+            signature,
+            v2,
         })
     }
 
-    fn len(_: Option<&Header>) -> anyhow::Result<usize> {
-        Ok(Self::LEN)
+    fn len(header: Option<&Header>) -> anyhow::Result<usize> {
+        if header.map(|h| h.ext().is_v2()).unwrap_or(false) {
+            Ok(Self::LEN_V2)
+        } else {
+            Ok(Self::LEN)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Network;
+    use crate::node::header::{Extensions, MessageType};
+    use crate::Private;
+
+    #[test]
+    fn response_v2() {
+        let key = Private::random();
+        let cookie = Cookie::random();
+        let v2 = HandshakeV2::new(Network::Live.genesis_hash());
+        let signature = key
+            .sign(&HandshakeResponse::signed_data(&cookie, Some(&v2)))
+            .unwrap();
+        let handshake = Handshake {
+            query: None,
+            response: Some(HandshakeResponse::new_v2(
+                key.to_public().unwrap(),
+                signature,
+                v2.clone(),
+            )),
+        };
+
+        let header = Header::new(
+            Network::Live,
+            MessageType::Handshake,
+            *Extensions::new().response().v2(),
+        );
+        let data = handshake.serialize();
+        assert_eq!(data.len(), Handshake::len(Some(&header)).unwrap());
+        assert_eq!(data.len(), HandshakeResponse::LEN_V2);
+
+        let response = Handshake::deserialize(Some(&header), &data)
+            .unwrap()
+            .response
+            .unwrap();
+        assert_eq!(response.v2, Some(v2));
+        let signed = HandshakeResponse::signed_data(&cookie, response.v2.as_ref());
+        assert!(response.public.verify(&signed, &response.signature).is_ok());
+
+        // Without the flag it's read as a v1 response, and the lengths don't match.
+        let header = Header::new(
+            Network::Live,
+            MessageType::Handshake,
+            *Extensions::new().response(),
+        );
+        assert_eq!(
+            Handshake::len(Some(&header)).unwrap(),
+            HandshakeResponse::LEN
+        );
     }
 }
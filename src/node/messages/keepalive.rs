@@ -8,11 +8,27 @@ pub struct Keepalive(Vec<PeerInfo>);
 
 impl Keepalive {
     pub const PEERS: usize = 8;
+
+    /// Only the first [Keepalive::PEERS] peers are used.
+    pub fn new(mut peers: Vec<PeerInfo>) -> Self {
+        peers.truncate(Self::PEERS);
+        Self(peers)
+    }
+
+    pub fn peers(&self) -> &[PeerInfo] {
+        &self.0
+    }
 }
 
 impl Wire for Keepalive {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        let mut v = Vec::with_capacity(PeerInfo::LEN * Keepalive::PEERS);
+        for peer in &self.0 {
+            v.extend_from_slice(&peer.serialize());
+        }
+        // Unused slots are zero filled.
+        v.resize(PeerInfo::LEN * Keepalive::PEERS, 0);
+        v
     }
 
     fn deserialize(header: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
@@ -36,3 +52,27 @@ impl Wire for Keepalive {
         Ok(PeerInfo::LEN * Keepalive::PEERS)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn serialize() {
+        let peers = vec![
+            PeerInfo::from_str("[::ffff:1.2.3.4]:7075").unwrap(),
+            PeerInfo::from_str("[2001:db8::1]:54000").unwrap(),
+        ];
+        let keepalive = Keepalive::new(peers);
+        let data = keepalive.serialize();
+        assert_eq!(data.len(), Keepalive::len(None).unwrap());
+
+        let keepalive2 = Keepalive::deserialize(None, &data).unwrap();
+        assert_eq!(keepalive2.peers().len(), 2);
+        assert_eq!(
+            keepalive2.peers()[1].socket_addr_v6().to_string(),
+            "[2001:db8::1]:54000"
+        );
+    }
+}
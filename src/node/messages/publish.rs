@@ -1,13 +1,24 @@
 use crate::blocks::BlockHolder;
-use crate::node::header::Header;
+use crate::node::header::{Extensions, Header};
 use crate::node::wire::Wire;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Publish(pub(crate) BlockHolder);
 
+impl Publish {
+    pub fn new(block: BlockHolder) -> Self {
+        Self(block)
+    }
+
+    /// The header extensions needed to send this block.
+    pub fn extensions(&self) -> Extensions {
+        *Extensions::new().set_block_type(&self.0.block_type())
+    }
+}
+
 impl Wire for Publish {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        self.0.serialize()
     }
 
     fn deserialize(header: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
@@ -21,3 +32,20 @@ impl Wire for Publish {
         BlockHolder::len(header)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Network;
+    use crate::node::header::MessageType;
+
+    #[test]
+    fn serialize() {
+        let genesis = Network::Live.genesis_block().to_holder().unwrap();
+        let publish = Publish::new(genesis);
+        let header = Header::new(Network::Live, MessageType::Publish, publish.extensions());
+        let data = publish.serialize();
+        assert_eq!(data.len(), Publish::len(Some(&header)).unwrap());
+        assert_eq!(Publish::deserialize(Some(&header), &data).unwrap(), publish);
+    }
+}
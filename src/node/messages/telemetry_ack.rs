@@ -2,90 +2,168 @@ use crate::blocks::BlockHash;
 use crate::bytes::Bytes;
 use crate::node::header::Header;
 use crate::node::wire::Wire;
-use crate::{Public, Signature};
+use crate::{Private, Public, Signature};
 use anyhow::Context;
 use std::convert::TryFrom;
-use tracing::warn;
 
-#[derive(Debug)]
+/// Identifies feeless in [TelemetryAck::maker]. The reference node uses 0 and 1.
+pub const MAKER: u8 = 0xfe;
+
+/// Statistics a node shares about itself, signed by its node ID.
+///
+/// All numbers are big endian on the wire. The signature covers everything after itself,
+/// including any trailing data from newer protocol versions that we don't understand.
+///
+/// Extended telemetry isn't supported yet. Fields that newer versions add are kept as they came
+/// so the signature verifies, but they aren't read, and we never send them.
+#[derive(Debug, Clone)]
 pub struct TelemetryAck {
     signature: Signature,
     node_id: Public,
-    block_count: u64,
-    cemented_count: u64,
-    unchecked_count: u64,
-    account_count: u64,
-    bandwidth_cap: u64,
-    uptime: u64,
-    peer_count: u32,
-    protocol_version: u8,
-    genesis_block: BlockHash,
-    major_version: u8,
-    minor_version: u8,
-    patch_version: u8,
-    prerelease_version: u8,
-    maker: u8,
-    timestamp: [u8; 8],
-    active_difficulty: [u8; 8],
+    pub block_count: u64,
+    pub cemented_count: u64,
+    pub unchecked_count: u64,
+    pub account_count: u64,
+    pub bandwidth_cap: u64,
+    pub peer_count: u32,
+    pub protocol_version: u8,
+
+    /// Seconds since the node started.
+    pub uptime: u64,
+    pub genesis_block: BlockHash,
+    pub major_version: u8,
+    pub minor_version: u8,
+    pub patch_version: u8,
+    pub prerelease_version: u8,
+
+    /// Which implementation the node runs.
+    pub maker: u8,
+
+    /// Milliseconds since the unix epoch when the telemetry was generated.
+    pub timestamp: u64,
+    pub active_difficulty: u64,
+
+    /// Fields added after the version we know about.
+    unknown_data: Vec<u8>,
 }
 
 impl TelemetryAck {
     pub const LEN: usize = 202;
-}
-
-impl Wire for TelemetryAck {
-    fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
-    }
-
-    fn deserialize(_header: Option<&Header>, data: &[u8]) -> Result<Self, anyhow::Error>
-    where
-        Self: Sized,
-    {
-        let mut bytes = Bytes::new(data);
 
-        let mut s = Self {
-            signature: Signature::try_from(bytes.slice(Signature::LEN)?)
-                .context("Telemetry ack decoding signature")?,
-            node_id: Public::try_from(bytes.slice(Public::LEN)?)
-                .context("Telemetry ack decoding node_id")?,
+    /// An unsigned ack with everything zeroed. Fill in the fields then [Self::sign] it.
+    pub fn new(genesis_block: BlockHash) -> Self {
+        Self {
+            signature: Signature::zero(),
+            node_id: Public::zero(),
             block_count: 0,
             cemented_count: 0,
             unchecked_count: 0,
             account_count: 0,
             bandwidth_cap: 0,
-            uptime: 0,
             peer_count: 0,
             protocol_version: 0,
-            genesis_block: BlockHash::zero(),
+            uptime: 0,
+            genesis_block,
             major_version: 0,
             minor_version: 0,
             patch_version: 0,
             prerelease_version: 0,
             maker: 0,
-            timestamp: [0u8; 8],
-            active_difficulty: [0u8; 8],
-        };
+            timestamp: 0,
+            active_difficulty: 0,
+            unknown_data: vec![],
+        }
+    }
 
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    pub fn node_id(&self) -> &Public {
+        &self.node_id
+    }
+
+    /// Set the node ID to the public key of `node_key` and sign the rest of the data with it.
+    pub fn sign(&mut self, node_key: &Private) -> anyhow::Result<()> {
+        self.node_id = node_key.to_public()?;
+        self.signature = node_key.sign(&self.signed_data())?;
+        Ok(())
+    }
+
+    /// Check the signature was made by [Self::node_id].
+    pub fn verify(&self) -> anyhow::Result<()> {
+        self.node_id
+            .verify(&self.signed_data(), &self.signature)
+            .context("Invalid telemetry signature")?;
+        Ok(())
+    }
+
+    fn signed_data(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(Self::LEN - Signature::LEN + self.unknown_data.len());
+        v.extend_from_slice(self.node_id.as_bytes());
+        v.extend_from_slice(&self.block_count.to_be_bytes());
+        v.extend_from_slice(&self.cemented_count.to_be_bytes());
+        v.extend_from_slice(&self.unchecked_count.to_be_bytes());
+        v.extend_from_slice(&self.account_count.to_be_bytes());
+        v.extend_from_slice(&self.bandwidth_cap.to_be_bytes());
+        v.extend_from_slice(&self.peer_count.to_be_bytes());
+        v.push(self.protocol_version);
+        v.extend_from_slice(&self.uptime.to_be_bytes());
+        v.extend_from_slice(self.genesis_block.as_bytes());
+        v.push(self.major_version);
+        v.push(self.minor_version);
+        v.push(self.patch_version);
+        v.push(self.prerelease_version);
+        v.push(self.maker);
+        v.extend_from_slice(&self.timestamp.to_be_bytes());
+        v.extend_from_slice(&self.active_difficulty.to_be_bytes());
+        v.extend_from_slice(&self.unknown_data);
+        v
+    }
+}
+
+fn be_u64(bytes: &mut Bytes) -> anyhow::Result<u64> {
+    let mut s64 = [0u8; 8];
+    s64.copy_from_slice(bytes.slice(8)?);
+    Ok(u64::from_be_bytes(s64))
+}
+
+impl Wire for TelemetryAck {
+    fn serialize(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(Self::LEN + self.unknown_data.len());
+        v.extend_from_slice(self.signature.as_bytes());
+        v.extend_from_slice(&self.signed_data());
+        v
+    }
+
+    fn deserialize(_header: Option<&Header>, data: &[u8]) -> Result<Self, anyhow::Error>
+    where
+        Self: Sized,
+    {
+        // Nodes with telemetry disabled reply with no data, which isn't an error.
+        if data.is_empty() {
+            return Ok(Self::new(BlockHash::zero()));
+        }
+        let mut bytes = Bytes::new(data);
+
+        let signature = Signature::try_from(bytes.slice(Signature::LEN)?)
+            .context("Telemetry ack decoding signature")?;
+        let node_id = Public::try_from(bytes.slice(Public::LEN)?)
+            .context("Telemetry ack decoding node_id")?;
+
+        let mut s = Self::new(BlockHash::zero());
+        s.signature = signature;
+        s.node_id = node_id;
+        s.block_count = be_u64(&mut bytes)?;
+        s.cemented_count = be_u64(&mut bytes)?;
+        s.unchecked_count = be_u64(&mut bytes)?;
+        s.account_count = be_u64(&mut bytes)?;
+        s.bandwidth_cap = be_u64(&mut bytes)?;
         let mut s32 = [0u8; 4];
-        let mut s64 = [0u8; 8];
-
-        // TODO s.block_count = bytes.read_be_u64() ?
-        s64.copy_from_slice(bytes.slice(8)?);
-        s.block_count = u64::from_be_bytes(s64);
-        s64.copy_from_slice(bytes.slice(8)?);
-        s.cemented_count = u64::from_be_bytes(s64);
-        s64.copy_from_slice(bytes.slice(8)?);
-        s.unchecked_count = u64::from_be_bytes(s64);
-        s64.copy_from_slice(bytes.slice(8)?);
-        s.account_count = u64::from_be_bytes(s64);
-        s64.copy_from_slice(bytes.slice(8)?);
-        s.bandwidth_cap = u64::from_be_bytes(s64);
-        s64.copy_from_slice(bytes.slice(8)?);
-        s.uptime = u64::from_be_bytes(s64);
         s32.copy_from_slice(bytes.slice(4)?);
         s.peer_count = u32::from_be_bytes(s32);
         s.protocol_version = bytes.u8()?;
+        s.uptime = be_u64(&mut bytes)?;
         s.genesis_block = BlockHash::try_from(bytes.slice(BlockHash::LEN)?)
             .context("Telemetry ack decoding genesis block")?;
 
@@ -94,17 +172,67 @@ impl Wire for TelemetryAck {
         s.patch_version = bytes.u8()?;
         s.prerelease_version = bytes.u8()?;
         s.maker = bytes.u8()?;
-
-        warn!("TODO: telemetry ack timestamp");
-        warn!("TODO: telemetry ack active_difficulty");
+        s.timestamp = be_u64(&mut bytes)?;
+        s.active_difficulty = be_u64(&mut bytes)?;
+        s.unknown_data = bytes.slice(bytes.remain())?.to_vec();
 
         Ok(s)
     }
 
-    fn len(_header: Option<&Header>) -> Result<usize, anyhow::Error>
+    /// The size is in the header, since newer versions might send more data. A size of 0 means
+    /// the node has no telemetry to share.
+    fn len(header: Option<&Header>) -> Result<usize, anyhow::Error>
     where
         Self: Sized,
     {
-        Ok(TelemetryAck::LEN)
+        Ok(match header {
+            Some(header) => header.ext().telemetry_size(),
+            None => TelemetryAck::LEN,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Network;
+    use crate::node::header::{Extensions, MessageType};
+
+    #[test]
+    fn sign_and_serialize() {
+        let key = Private::random();
+        let mut ack = TelemetryAck::new(Network::Live.genesis_hash());
+        ack.block_count = 100;
+        ack.cemented_count = 99;
+        ack.peer_count = 3;
+        ack.uptime = 60;
+        ack.timestamp = 1_600_000_000_000;
+        ack.sign(&key).unwrap();
+        ack.verify().unwrap();
+
+        let data = ack.serialize();
+        assert_eq!(data.len(), TelemetryAck::LEN);
+
+        let header = Header::new(
+            Network::Live,
+            MessageType::TelemetryAck,
+            *Extensions::new().set_telemetry_size(data.len()),
+        );
+        assert_eq!(
+            header.serialize()[6..],
+            (TelemetryAck::LEN as u16).to_le_bytes()
+        );
+        assert_eq!(TelemetryAck::len(Some(&header)).unwrap(), data.len());
+        let decoded = TelemetryAck::deserialize(Some(&header), &data).unwrap();
+        decoded.verify().unwrap();
+        assert_eq!(decoded.node_id(), &key.to_public().unwrap());
+        assert_eq!(decoded.block_count, 100);
+        assert_eq!(decoded.timestamp, 1_600_000_000_000);
+        assert_eq!(decoded.serialize(), data);
+
+        // Tampering with any field breaks the signature.
+        let mut tampered = decoded;
+        tampered.block_count = 101;
+        assert!(tampered.verify().is_err());
     }
 }
@@ -1,40 +1,113 @@
 mod command;
 mod cookie;
+mod duplicate_filter;
+mod elections;
 mod header;
 mod messages;
+pub mod node_id;
 mod peer;
 mod peer_info;
+mod peer_manager;
 mod state;
 mod timestamp;
+mod unchecked;
+mod voter;
 mod wire;
 
+use crate::blocks::BlockHash;
+use crate::node::elections::Root;
+use crate::node::header::MessageType;
+use crate::node::messages::confirm_req::{ConfirmReq, RootHashPair};
+use crate::paths::Paths;
+use crate::rpc::calls::{DetailedPeerInfo, NetType, Peers};
 use crate::rpc::server::RPCServer;
-use crate::Network;
 pub use crate::Version;
-use anyhow::Context;
+use crate::{Network, Private};
+use anyhow::{anyhow, Context};
 pub use command::{NodeCommand, NodeCommandReceiver, NodeCommandSender};
+pub use duplicate_filter::{ArcDuplicateFilter, DuplicateFilter};
+pub use elections::{ArcElections, ElectionConfig, Elections};
 pub use header::Header;
+pub(crate) use peer::state_subtype;
 pub use peer::{Packet, Peer};
+pub use peer_manager::{ArcPeerManager, PeerManager};
+use rand::seq::SliceRandom;
 pub use state::{ArcState, MemoryState, SledDiskState};
+use std::convert::TryFrom;
 use std::net::SocketAddr;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
-use tokio::sync::Mutex;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
 use tokio::task::JoinHandle;
+use tokio::time::sleep;
 use tracing::{debug, error, info, instrument};
+pub use unchecked::{ArcUnchecked, Unchecked};
+pub use voter::{ArcVoter, Voter};
 pub use wire::Wire;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionKind {
+    /// A peer connected to us.
+    Incoming,
+
+    /// We connected to a peer.
+    Outgoing,
+
+    /// We connected to a peer to bootstrap from it.
+    Bootstrap,
+}
+
+#[derive(Clone)]
 pub struct Node {
     network: Network,
     state: ArcState,
+
+    /// Address to listen on for incoming peer connections.
+    listen_addr: SocketAddr,
+
+    /// Send commands to ourselves, e.g. from the RPC server or from a [Peer].
+    node_tx: NodeCommandSender,
+
+    /// Connection state of every peer we know about.
+    peer_manager: ArcPeerManager,
+
+    /// Elections for blocks received from any peer.
+    elections: ArcElections,
+
+    /// Publishes and votes recently received from any peer.
+    duplicate_filter: ArcDuplicateFilter,
+
+    /// Blocks from any peer waiting for a block they depend on.
+    unchecked: ArcUnchecked,
+
+    /// Our node ID, shared by every peer connection.
+    node_key: Private,
+
+    /// Votes with a representative's key, when the node is configured with one.
+    voter: Option<ArcVoter>,
+
+    started: Instant,
 }
 
 impl Node {
-    pub async fn start(override_peers: Option<Vec<String>>) -> anyhow::Result<()> {
-        let mut node = Node::new(Network::Live);
-        let rpc_rx = node.start_rpc_server().await?;
+    pub async fn start(
+        network: Network,
+        paths: &Paths,
+        override_peers: Option<Vec<String>>,
+        listen_addr: SocketAddr,
+        rpc_port: u16,
+        representative: Option<Private>,
+        election_config: ElectionConfig,
+    ) -> anyhow::Result<()> {
+        let (mut node, node_rx) = Node::new_with_channel(network, paths, listen_addr)?;
+        node.set_election_config(election_config);
+        if let Some(key) = representative {
+            node.set_representative(key)?;
+        }
+        node.start_rpc_server(rpc_port).await?;
         if let Some(str_addrs) = override_peers {
             let mut socket_addrs = vec![];
             for str_addr in str_addrs {
@@ -47,34 +120,98 @@ impl Node {
             node.peer_autodiscovery().await?;
         }
 
-        node.run(rpc_rx).await
+        node.run(node_rx).await
     }
 
-    pub fn new(network: Network) -> Self {
-        // let state = SledDiskState::new(Network::Live);
-        let state = MemoryState::new(network);
+    /// A node with its ledger on disk, in the data directory for `network`.
+    pub fn new_with_channel(
+        network: Network,
+        paths: &Paths,
+        listen_addr: SocketAddr,
+    ) -> anyhow::Result<(Self, NodeCommandReceiver)> {
+        let state = SledDiskState::new(network, paths).context("Opening ledger database")?;
         let state = Arc::new(Mutex::new(state));
-        Self { state, network }
+        let (mut node, node_rx) = Self::new_with_state(network, state, listen_addr);
+        node.node_key = node_id::load_or_create(paths).context("Loading node ID")?;
+        Ok((node, node_rx))
+    }
+
+    pub fn new_with_state(
+        network: Network,
+        state: ArcState,
+        listen_addr: SocketAddr,
+    ) -> (Self, NodeCommandReceiver) {
+        let (node_tx, node_rx) = mpsc::channel(100);
+        let node = Self {
+            state,
+            network,
+            listen_addr,
+            node_tx,
+            peer_manager: Arc::new(Mutex::new(PeerManager::default())),
+            elections: Arc::new(Mutex::new(Elections::default())),
+            duplicate_filter: Arc::new(Mutex::new(DuplicateFilter::default())),
+            unchecked: Arc::new(Mutex::new(Unchecked::new())),
+            // Replaced with the persisted key when the node has a data directory.
+            node_key: Private::random(),
+            voter: None,
+            started: Instant::now(),
+        };
+        (node, node_rx)
     }
 
-    pub async fn start_rpc_server(&self) -> anyhow::Result<NodeCommandReceiver> {
-        let (rpc_server, rx) = RPCServer::new_with_channel(self.state.clone());
+    /// Answer confirm requests with votes signed by the representative `key`.
+    pub fn set_representative(&mut self, key: Private) -> anyhow::Result<()> {
+        let voter = Voter::new(key)?;
+        info!("Voting as representative {}", voter.account().to_address());
+        self.voter = Some(Arc::new(Mutex::new(voter)));
+        Ok(())
+    }
+
+    /// Confirm blocks with a different quorum than the live network's. Call this before the
+    /// node runs, since it starts with no elections.
+    pub fn set_election_config(&mut self, config: ElectionConfig) {
+        self.elections = Arc::new(Mutex::new(Elections::new(config)));
+    }
+
+    pub async fn start_rpc_server(&self, port: u16) -> anyhow::Result<()> {
+        let rpc_server = RPCServer::new(self.state.clone(), self.node_tx.clone(), port);
         tokio::spawn(rpc_server.run());
-        Ok(rx)
+        Ok(())
     }
 
     pub async fn run(self, mut node_rx: NodeCommandReceiver) -> anyhow::Result<()> {
-        let initial_peers = self.state.lock().await.peers().await?;
-        for address in initial_peers {
-            let state = self.state.clone();
-            let network = self.network.clone();
-            Self::connection(network, state, address).await?;
+        let listener = TcpListener::bind(self.listen_addr)
+            .await
+            .with_context(|| format!("Could not listen on {}", self.listen_addr))?;
+        tokio::spawn(self.clone().listen(listener));
+
+        let initial_peers: Vec<SocketAddr> =
+            self.state.lock().await.peers().await?.into_iter().collect();
+        self.connect_peers(&initial_peers).await;
+        if let Some(address) = initial_peers.choose(&mut rand::thread_rng()) {
+            let address = *address;
+            let node = self.clone();
+            tokio::spawn(async move {
+                if let Err(err) = node.bootstrap(address).await {
+                    error!("Bootstrap from {} failed: {:?}", address, err);
+                }
+            });
         }
 
         while let Some(node_command) = node_rx.recv().await {
-            dbg!("todo node command", &node_command);
+            debug!("Node command: {:?}", &node_command);
             match node_command {
-                NodeCommand::PeerInfo(_tx) => todo!("get_active_peers()"),
+                NodeCommand::PeerInfo(details, tx) => {
+                    // The receiver might have given up waiting, which is fine.
+                    let _ = tx.send(self.peer_info(details).await);
+                }
+                NodeCommand::ConnectPeers(addresses) => self.connect_peers(&addresses).await,
+                NodeCommand::NodeId(tx) => {
+                    let _ = tx.send(self.node_key.to_public().context("Node ID"));
+                }
+                NodeCommand::BlockConfirm(hash, tx) => {
+                    let _ = tx.send(self.confirm_block(&hash).await);
+                }
             };
         }
 
@@ -82,22 +219,196 @@ impl Node {
         Ok(())
     }
 
-    #[instrument(skip(network, state))]
-    pub async fn connection(
-        network: Network,
-        state: ArcState,
-        address: SocketAddr,
-    ) -> anyhow::Result<()> {
+    /// Accept incoming connections forever, handling each one in its own task.
+    #[instrument(skip(self, listener))]
+    pub async fn listen(self, listener: TcpListener) {
+        info!("Listening for peers on {:?}", listener.local_addr());
+        loop {
+            let (stream, address) = match listener.accept().await {
+                Ok(s) => s,
+                Err(err) => {
+                    error!("Could not accept connection: {:?}", err);
+                    continue;
+                }
+            };
+            if !self
+                .peer_manager
+                .lock()
+                .await
+                .try_accept(address, Instant::now())
+            {
+                debug!("Refusing connection from {}", address);
+                continue;
+            }
+
+            info!("Accepted connection from {}", address);
+            let node = self.clone();
+            tokio::spawn(async move {
+                let result = node
+                    .handle_stream(stream, address, ConnectionKind::Incoming)
+                    .await;
+                if let Err(err) = &result {
+                    error!("Incoming connection from {} failed: {:?}", address, err);
+                }
+                node.peer_manager
+                    .lock()
+                    .await
+                    .disconnected(address, &result, Instant::now());
+            });
+        }
+    }
+
+    /// Connect to each address in its own task, unless the [PeerManager] says otherwise.
+    /// Failed connections are retried with a backoff.
+    pub async fn connect_peers(&self, addresses: &[SocketAddr]) {
+        for address in addresses {
+            if !self
+                .peer_manager
+                .lock()
+                .await
+                .try_connect(*address, Instant::now())
+            {
+                continue;
+            }
+
+            let node = self.clone();
+            let address = *address;
+            tokio::spawn(async move {
+                loop {
+                    let result = node.connection(address).await;
+                    if let Err(err) = &result {
+                        error!("Connection to {} failed: {:?}", address, err);
+                    }
+
+                    let mut peer_manager = node.peer_manager.lock().await;
+                    let delay = match peer_manager.disconnected(address, &result, Instant::now()) {
+                        Some(delay) => delay,
+                        None => break,
+                    };
+                    drop(peer_manager);
+
+                    debug!("Reconnecting to {} in {:?}", address, delay);
+                    sleep(delay).await;
+                    if !node
+                        .peer_manager
+                        .lock()
+                        .await
+                        .try_connect(address, Instant::now())
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn connection(&self, address: SocketAddr) -> anyhow::Result<()> {
         info!("Connecting.");
-        let stream = match TcpStream::connect(address).await {
-            Ok(s) => s,
-            Err(err) => {
-                error!("Could not connect: {:?}", err);
-                return Ok(());
+        let stream = TcpStream::connect(address)
+            .await
+            .context("Could not connect")?;
+        self.peer_manager.lock().await.connected(address);
+
+        self.handle_stream(stream, address, ConnectionKind::Outgoing)
+            .await
+    }
+
+    /// Currently connected peers, for [NodeCommand::PeerInfo]. Peers that haven't completed a
+    /// handshake are left out of the detailed list, since we don't know their node ID yet.
+    pub async fn peer_info(&self, details: bool) -> Peers {
+        let peer_manager = self.peer_manager.lock().await;
+        let peers = peer_manager.connected_peers();
+        if !details {
+            return Peers::Simple(peers.map(|(addr, _)| *addr).collect());
+        }
+
+        Peers::Details(
+            peers
+                .filter_map(|(addr, status)| {
+                    let node_id = status.node_id_string()?;
+                    let version = status.version.unwrap_or(Version::V18);
+                    Some((*addr, DetailedPeerInfo::new(version, node_id, NetType::Tcp)))
+                })
+                .collect(),
+        )
+    }
+
+    /// Put a block from our ledger up for election and ask every connected peer to vote on it,
+    /// for [NodeCommand::BlockConfirm]. Cemented blocks are already confirmed, so nothing is
+    /// sent for them. Returns false if we don't have the block.
+    pub async fn confirm_block(&self, hash: &BlockHash) -> anyhow::Result<bool> {
+        let block = {
+            let state = self.state.lock().await;
+            let block = match state.get_block_by_hash(hash).await? {
+                Some(block) => block,
+                None => return Ok(false),
+            };
+            if state.is_cemented(&block).await? {
+                return Ok(true);
             }
+            block
         };
 
-        let (peer, tx, mut rx) = Peer::new_with_channels(network, state.clone(), address);
+        let root = BlockHash::try_from(Root::of(&block).as_bytes())?;
+        self.elections.lock().await.insert(block, Instant::now())?;
+        let request = ConfirmReq::ConfirmReqByHash(vec![RootHashPair {
+            hash: hash.to_owned(),
+            root,
+        }]);
+        let mut data =
+            Header::new(self.network, MessageType::ConfirmReq, request.extensions()).serialize();
+        data.extend(request.serialize());
+        let peer_manager = self.peer_manager.lock().await;
+        for (_, status) in peer_manager.connected_peers() {
+            if let Some(sender) = &status.sender {
+                if sender.try_send(Packet::new(data.clone())).is_err() {
+                    debug!("Could not send confirm req to a peer");
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Open a separate connection to `address` to find out which accounts we're missing.
+    #[instrument(skip(self))]
+    pub async fn bootstrap(self, address: SocketAddr) -> anyhow::Result<()> {
+        info!("Bootstrapping.");
+        let stream = TcpStream::connect(address)
+            .await
+            .context("Could not connect for bootstrap")?;
+        self.handle_stream(stream, address, ConnectionKind::Bootstrap)
+            .await
+    }
+
+    /// Run a [Peer] over a connected socket until either side disconnects, or the peer is done
+    /// with the connection.
+    pub async fn handle_stream(
+        &self,
+        stream: TcpStream,
+        address: SocketAddr,
+        kind: ConnectionKind,
+    ) -> anyhow::Result<()> {
+        let (mut peer, tx, mut rx) =
+            Peer::new_with_channels(self.network, self.state.clone(), address);
+        // The side that connects is the one expected to send the first handshake query.
+        peer.initiate_handshake = kind == ConnectionKind::Outgoing;
+        peer.bootstrap = kind == ConnectionKind::Bootstrap;
+        peer.node_tx = Some(self.node_tx.clone());
+        peer.peer_manager = Some(self.peer_manager.clone());
+        peer.elections = Some(self.elections.clone());
+        peer.duplicate_filter = Some(self.duplicate_filter.clone());
+        peer.unchecked = Some(self.unchecked.clone());
+        peer.node_key = self.node_key.clone();
+        peer.voter = self.voter.clone();
+        peer.node_started = self.started;
+        peer.init().await?;
+        if kind != ConnectionKind::Bootstrap {
+            self.peer_manager
+                .lock()
+                .await
+                .set_sender(&address, peer.sender());
+        }
 
         // Task for the Peer handler.
         let peer_task = tokio::spawn(peer.run());
@@ -112,6 +423,10 @@ impl Node {
                     .read(&mut buffer)
                     .await
                     .with_context(|| format!("Could not read from socket at {}", address))?;
+                if bytes == 0 {
+                    // The remote side closed the connection. Dropping `tx` will let Peer finish.
+                    break;
+                }
 
                 let result = tx.send(Packet::new(Vec::from(&buffer[0..bytes]))).await;
                 if result.is_err() {
@@ -141,16 +456,20 @@ impl Node {
             Ok(())
         });
 
-        let (peer, reader, writer) = tokio::try_join!(peer_task, reader_task, writer_task)?;
-        if let Err(err) = peer {
-            error!("Disconnected because of peer: {:?}", err);
-        };
-        if let Err(err) = reader {
-            error!("Disconnected because of read socket: {:?}", err);
-        };
-        if let Err(err) = writer {
-            error!("Disconnected because of write socket: {:?}", err);
+        let peer = peer_task.await?;
+        // The writer finishes once every sender to it is gone, including the relay's copy.
+        self.peer_manager.lock().await.remove_sender(&address);
+        // The peer can finish while the socket is still open, e.g. after bootstrapping or when
+        // it sent us something invalid, so don't wait for the other side to close it.
+        reader_task.abort();
+        let reader = match reader_task.await {
+            Err(err) if err.is_cancelled() => Ok(()),
+            result => result?,
         };
+        let writer = writer_task.await?;
+        peer.context("Disconnected because of peer")?;
+        reader.context("Disconnected because of read socket")?;
+        writer.context("Disconnected because of write socket")?;
         Ok(())
     }
 
@@ -161,7 +480,12 @@ impl Node {
     }
 
     pub async fn peer_autodiscovery(&mut self) -> anyhow::Result<()> {
-        let host = self.network.peering_host();
+        let host = self.network.peering_host().ok_or_else(|| {
+            anyhow!(
+                "The {} network has no peering host, so peers need to be given",
+                self.network
+            )
+        })?;
         info!("Peer autodiscovery initiated with {}", host);
         let socket_addrs: Vec<SocketAddr> = tokio::net::lookup_host(host)
             .await
@@ -171,3 +495,183 @@ impl Node {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{Block, BlockType, Link, Previous, SendBlock, ValidationState};
+    use crate::node::cookie::Cookie;
+    use crate::node::header::{Extensions, MessageType};
+    use crate::node::messages::handshake::{Handshake, HandshakeQuery};
+    use crate::node::state::{LedgerChange, State};
+    use crate::{DevNetwork, Difficulty, Raw, Work};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn closes_connection_on_invalid_message() {
+        let network = Network::Live;
+        let state: ArcState = Arc::new(Mutex::new(MemoryState::new(network)));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (node, _node_rx) = Node::new_with_state(network, state, address);
+        tokio::spawn(node.listen(listener));
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let header = Header::new(Network::Beta, MessageType::Keepalive, Extensions::new());
+        stream.write_all(&header.serialize()).await.unwrap();
+
+        // The relay keeps a sender for the peer, which mustn't hold the connection open.
+        let mut buffer = vec![];
+        let read = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut buffer));
+        assert!(read.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn answers_incoming_handshake() {
+        let network = Network::Live;
+        let state: ArcState = Arc::new(Mutex::new(MemoryState::new(network)));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (node, _node_rx) = Node::new_with_state(network, state, address);
+        tokio::spawn(node.listen(listener));
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let cookie = Cookie::random();
+        let header = Header::new(network, MessageType::Handshake, *Extensions::new().query());
+        stream.write_all(&header.serialize()).await.unwrap();
+        stream
+            .write_all(&HandshakeQuery::new(cookie.clone()).serialize())
+            .await
+            .unwrap();
+
+        let mut buffer = [0u8; Header::LEN];
+        stream.read_exact(&mut buffer).await.unwrap();
+        let header = Header::deserialize(None, &buffer).unwrap();
+        assert_eq!(header.message_type(), MessageType::Handshake);
+        assert!(header.ext().is_query());
+        assert!(header.ext().is_response());
+
+        let mut buffer = vec![0u8; Handshake::len(Some(&header)).unwrap()];
+        stream.read_exact(&mut buffer).await.unwrap();
+        let handshake = Handshake::deserialize(Some(&header), &buffer).unwrap();
+        assert!(handshake.query.is_some());
+        let response = handshake.response.unwrap();
+        response
+            .public
+            .verify(cookie.as_bytes(), &response.signature)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn bootstrap_from_another_node() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let send: SendBlock = serde_json::from_str(
+            r#"{
+                "type": "send",
+                "previous": "991CF190094C00F0B68E2E5F75F6BEE95A2E0BD93CEAA4A6734DB9F19B728948",
+                "destination": "nano_13ezf4od79h1tgj9aiu4djzcmmguendtjfuhwfukhuucboua8cpoihmh8byo",
+                "balance": "FD89D89D89D89D89D89D89D89D89D89D",
+                "work": "3c82cc724905ee95",
+                "signature": "5B11B17DB9C8FE0CC58CAC6A6EECEF9CB122DA8A81C6D3DB1B5EE3AB065AA8F8CB1D6765C8EB91B58530C5FF5987AD95E6D34BB57F44257E20795EE412E61600"
+            }"#,
+        )
+        .unwrap();
+        let send = Block::from_send_block(&send, genesis.account(), genesis.representative());
+
+        let mut server_state = MemoryState::new(network);
+        server_state
+            .add_block(&genesis, &LedgerChange::default())
+            .await
+            .unwrap();
+        server_state
+            .add_block(&send, &LedgerChange::default())
+            .await
+            .unwrap();
+        let server_state: ArcState = Arc::new(Mutex::new(server_state));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_address = listener.local_addr().unwrap();
+        let (server, _rx) = Node::new_with_state(network, server_state, server_address);
+        tokio::spawn(server.listen(listener));
+
+        let client_state: ArcState = Arc::new(Mutex::new(MemoryState::new(network)));
+        let client_address = SocketAddr::from_str("127.0.0.1:0").unwrap();
+        let (client, _rx) = Node::new_with_state(network, client_state.clone(), client_address);
+        client.bootstrap(server_address).await.unwrap();
+
+        let frontier = client_state
+            .lock()
+            .await
+            .get_latest_block_hash_for_account(genesis.account())
+            .await
+            .unwrap();
+        assert_eq!(frontier.as_ref(), Some(send.hash().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn dev_network_over_loopback() {
+        let key = Private::random();
+        let dev = DevNetwork::generate(
+            &key,
+            DevNetwork::DEFAULT_MAGIC,
+            Difficulty::new(DevNetwork::DEFAULT_WORK_THRESHOLD),
+        )
+        .unwrap();
+        let network = dev.into_network();
+        let genesis = network.genesis_block();
+
+        let account = key.to_public().unwrap();
+        let mut send = Block::new(
+            BlockType::State,
+            account.to_owned(),
+            Previous::Block(genesis.hash().unwrap().to_owned()),
+            account,
+            Raw::from(1000),
+            Link::DestinationAccount(Private::random().to_public().unwrap()),
+            ValidationState::Valid,
+        );
+        send.sign(key).unwrap();
+        let threshold = network.work_thresholds().epoch_2;
+        send.set_work(Work::generate(&send.work_subject(), &threshold).unwrap());
+
+        let mut server_state = MemoryState::new(network);
+        server_state
+            .add_block(&genesis, &LedgerChange::default())
+            .await
+            .unwrap();
+        server_state
+            .add_block(&send, &LedgerChange::default())
+            .await
+            .unwrap();
+        let server_state: ArcState = Arc::new(Mutex::new(server_state));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_address = listener.local_addr().unwrap();
+        let (server, _rx) = Node::new_with_state(network, server_state, server_address);
+        tokio::spawn(server.listen(listener));
+
+        // A node on the same dev network starts from the same genesis block and syncs.
+        let client_state: ArcState = Arc::new(Mutex::new(MemoryState::new(network)));
+        let client_address = SocketAddr::from_str("127.0.0.1:0").unwrap();
+        let (client, _rx) = Node::new_with_state(network, client_state.clone(), client_address);
+        client.bootstrap(server_address).await.unwrap();
+        let frontier = client_state
+            .lock()
+            .await
+            .get_latest_block_hash_for_account(genesis.account())
+            .await
+            .unwrap();
+        assert_eq!(frontier.as_ref(), Some(send.hash().unwrap()));
+
+        // Nodes on other networks are turned away by the magic byte.
+        let live_state: ArcState = Arc::new(Mutex::new(MemoryState::new(Network::Live)));
+        let (live, _rx) = Node::new_with_state(Network::Live, live_state.clone(), client_address);
+        let _ = live.bootstrap(server_address).await;
+        let frontier = live_state
+            .lock()
+            .await
+            .get_latest_block_hash_for_account(genesis.account())
+            .await
+            .unwrap();
+        assert_eq!(frontier, None);
+    }
+}
@@ -0,0 +1,94 @@
+//! The key our node identifies itself with to other peers, e.g. in handshakes and telemetry.
+//!
+//! It is created the first time the node starts and kept in the data directory, so peers can
+//! recognise us across connections and restarts.
+use crate::paths::Paths;
+use crate::{Address, Private, Public};
+use anyhow::Context;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use tracing::info;
+
+/// The node ID in the form used by the RPC, e.g. `node_1y7j...`.
+pub fn node_id_string(public: &Public) -> String {
+    Address::from(public)
+        .to_string()
+        .replacen("nano_", "node_", 1)
+}
+
+/// Load the node key, creating it if it doesn't exist yet.
+pub fn load_or_create(paths: &Paths) -> anyhow::Result<Private> {
+    let path = paths.node_key_path();
+    if path.exists() {
+        return load(&path);
+    }
+    info!("Creating a new node ID");
+    create(paths)
+}
+
+/// Replace the node key with a new one. Peers will see us as a different node.
+pub fn rotate(paths: &Paths) -> anyhow::Result<Private> {
+    info!("Rotating node ID");
+    create(paths)
+}
+
+fn load(path: &Path) -> anyhow::Result<Private> {
+    let s =
+        fs::read_to_string(path).with_context(|| format!("Could not read node key: {:?}", path))?;
+    Private::from_str(s.trim()).with_context(|| format!("Invalid node key in {:?}", path))
+}
+
+fn create(paths: &Paths) -> anyhow::Result<Private> {
+    paths.ensure_data_path()?;
+    let path = paths.node_key_path();
+    let private = Private::random();
+    write_private(&path, &private.to_string())
+        .with_context(|| format!("Could not write node key: {:?}", path))?;
+    Ok(private)
+}
+
+/// Only the owner can read the key.
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Network;
+
+    #[test]
+    fn persisted_and_rotated() {
+        let dir = std::env::temp_dir().join(format!("feeless-node-id-{}", Private::random()));
+        let paths = Paths::new_custom(Network::Live, dir.clone());
+
+        let key = load_or_create(&paths).unwrap();
+        let public = key.to_public().unwrap();
+        assert_eq!(load_or_create(&paths).unwrap().to_public().unwrap(), public);
+
+        let rotated = rotate(&paths).unwrap().to_public().unwrap();
+        assert_ne!(rotated, public);
+        assert_eq!(
+            load_or_create(&paths).unwrap().to_public().unwrap(),
+            rotated
+        );
+        assert!(node_id_string(&rotated).starts_with("node_"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
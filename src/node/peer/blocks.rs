@@ -1,9 +1,17 @@
-use crate::blocks::{Block, BlockHash, BlockType, Previous};
-use crate::node::messages::confirm_ack::{Confirm, ConfirmAck};
-use crate::node::peer::Peer;
-use crate::{Public, Raw, Signature};
+use crate::blocks::{Block, BlockHash, BlockHolder, Previous};
+use crate::node::elections::{ElectionStatus, Root};
+use crate::node::messages::confirm_ack::ConfirmAck;
+use crate::node::peer::ledger::BlockRejection;
+use crate::node::peer::{Peer, VOTE_BATCH_SIZE};
+use crate::node::state::{LedgerChange, WeightChange};
+use crate::node::timestamp::Timestamp;
+use crate::node::voter::Candidate;
+use crate::pow::Subject;
+use crate::{Public, Raw};
 use anyhow::{anyhow, Context};
-use tracing::{debug, instrument, warn};
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+use tracing::{debug, instrument, trace, warn};
 
 struct AccountDelta {
     from: Public,
@@ -11,160 +19,511 @@ struct AccountDelta {
     amount: Raw,
 }
 
+/// How far ahead of our clock a vote timestamp can be.
+pub const MAX_VOTE_CLOCK_DRIFT: Duration = Duration::from_secs(60);
+
+/// Why a vote wasn't recorded. Attached to the error from [Peer::add_vote] so the caller can
+/// tell a bad vote apart from a failure on our side.
+#[derive(Debug, thiserror::Error)]
+pub enum VoteRejection {
+    #[error("Invalid vote signature")]
+    BadSignature,
+
+    #[error("Vote timestamp {0} is too far in the future")]
+    FutureTimestamp(u64),
+
+    #[error("Vote timestamp {timestamp} is not newer than the vote we have: {latest}")]
+    Replay { timestamp: u64, latest: u64 },
+}
+
 impl Peer {
+    /// Queue a vote to have its signature verified with others in a batch.
+    pub async fn queue_vote(&mut self, confirm_ack: ConfirmAck) -> anyhow::Result<()> {
+        self.pending_votes.push(confirm_ack);
+        if self.pending_votes.len() >= VOTE_BATCH_SIZE {
+            self.process_votes().await?;
+        }
+        Ok(())
+    }
+
+    /// Verify the signatures of all queued votes in one go, then record the valid ones.
+    #[instrument(skip(self))]
+    pub async fn process_votes(&mut self) -> anyhow::Result<()> {
+        let votes = std::mem::take(&mut self.pending_votes);
+        trace!("Verifying {} votes", votes.len());
+        let valid = ConfirmAck::verify_signatures(&votes);
+        for (vote, valid) in votes.iter().zip(valid) {
+            let result = if valid {
+                self.add_vote(vote).await
+            } else {
+                Err(VoteRejection::BadSignature.into())
+            };
+            if let Err(err) = result {
+                match err.downcast_ref::<VoteRejection>() {
+                    Some(rejection) => debug!("Rejected vote {:?}: {}", vote, rejection),
+                    None => return Err(err),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Record a vote whose signature has already been verified, e.g. by [Self::process_votes].
     #[instrument(skip(self))]
     pub async fn add_vote(&mut self, confirm_ack: &ConfirmAck) -> anyhow::Result<()> {
         let context = || format!("Adding vote {:?}", &confirm_ack);
 
-        let hashes = if let Confirm::VoteByHash(hashes) = &confirm_ack.confirm {
-            hashes
-        } else {
-            return Err(anyhow!("Confirm::Block not implemented")).with_context(context);
-        };
-
-        for hash in hashes {
-            self.validate_vote(hash, &confirm_ack.account, &confirm_ack.signature)
+        for hash in &confirm_ack.confirm.hashes() {
+            self.validate_vote(hash, &confirm_ack.account, &confirm_ack.timestamp)
                 .await
                 .with_context(context)?;
 
             self.state
                 .lock()
                 .await
-                .add_vote(hash, &confirm_ack.account)
+                .add_vote(hash, &confirm_ack.account, confirm_ack.timestamp.to_u64())
                 .await
                 .with_context(context)?;
 
-            // self.check_votes(&confirm_ack.confirm)
-            //     .await
-            //     .with_context(context)?;
+            self.tally_vote(hash, &confirm_ack.account, confirm_ack.timestamp.to_u64())
+                .await
+                .with_context(context)?;
         }
 
         Ok(())
     }
 
-    #[instrument(skip(self))]
-    pub async fn validate_vote(
+    /// The block we'd vote for when asked about `hash`: the leader of its election, or the block
+    /// itself if it's in our ledger. None if we don't know the block, or it doesn't have the
+    /// requested root.
+    pub async fn vote_candidate(
+        &self,
+        hash: &BlockHash,
+        requested_root: Option<&BlockHash>,
+    ) -> anyhow::Result<Option<Candidate>> {
+        let mut confirmed = false;
+        if let Some(elections) = &self.elections {
+            let elections = elections.lock().await;
+            if let Some(election) = elections.election_for_block(hash) {
+                let leader = election.leader().map(|(hash, _)| hash);
+                let candidate = Candidate {
+                    root: election.root().to_owned(),
+                    hash: leader.unwrap_or_else(|| hash.to_owned()),
+                    confirmed: false,
+                };
+                return Ok(Self::matching_root(candidate, requested_root));
+            }
+            confirmed = elections.status(hash) == Some(ElectionStatus::Confirmed);
+        }
+
+        let block = match self.block_by_hash(hash).await? {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+        let candidate = Candidate {
+            root: Root::of(&block),
+            hash: hash.to_owned(),
+            confirmed,
+        };
+        Ok(Self::matching_root(candidate, requested_root))
+    }
+
+    fn matching_root(
+        candidate: Candidate,
+        requested_root: Option<&BlockHash>,
+    ) -> Option<Candidate> {
+        match requested_root {
+            Some(root) if root.as_bytes() != candidate.root.as_bytes() => None,
+            _ => Some(candidate),
+        }
+    }
+
+    /// Count a vote in the election for `hash`, and add the winner to the ledger if it confirmed
+    /// the block, rolling back a block it won a fork against. The winner is then cemented.
+    async fn tally_vote(
         &mut self,
         hash: &BlockHash,
         representative: &Public,
-        signature: &Signature,
+        timestamp: u64,
     ) -> anyhow::Result<()> {
-        warn!("TODO validate vote");
+        let elections = match &self.elections {
+            Some(elections) => elections.clone(),
+            None => return Ok(()),
+        };
+        let weight = self.representative_weight(representative).await?;
+        let winner =
+            elections
+                .lock()
+                .await
+                .vote(representative, weight, hash, timestamp, Instant::now());
+
+        if let Some(block) = winner {
+            match self.add_confirmed_block(&block).await {
+                Ok(()) => {
+                    if let Err(err) = self.cement(block.hash()?).await {
+                        warn!("Could not cement confirmed block: {:?}", err);
+                    }
+                }
+                Err(err) => {
+                    let dependency = match err.downcast_ref::<BlockRejection>() {
+                        Some(BlockRejection::GapPrevious(hash))
+                        | Some(BlockRejection::GapSource(hash)) => Some(hash.to_owned()),
+                        _ => None,
+                    };
+                    match (dependency, &self.unchecked) {
+                        (Some(dependency), Some(unchecked)) => {
+                            debug!("Confirmed block waits for {:?}", dependency);
+                            unchecked.lock().await.insert(
+                                dependency,
+                                block.to_holder()?,
+                                Instant::now(),
+                            );
+                        }
+                        _ => warn!("Could not add confirmed block: {:?}", err),
+                    }
+                }
+            }
+        }
         Ok(())
     }
 
-    /// Add a block that has been deemed valid by ORV.
-    ///
-    /// Before adding a block we need to make sure it:
-    /// * Doesn't already exist.
-    /// * Verify the work.
-    /// * Verify the signature.
-    /// * Handle the specific block type appropriately.
-    ///
-    /// After adding we need to update any representative weights.
-    pub async fn add_elected_block(&mut self, block: &Block) -> anyhow::Result<()> {
-        debug!("Adding elected block {:?}", &block);
-        let context = || format!("Block {:?}", &block);
-        let block_hash = block.hash().with_context(context)?;
+    /// The previous or source block `holder` needs that isn't in our ledger yet, if any.
+    pub async fn missing_dependency(
+        &self,
+        holder: &BlockHolder,
+    ) -> anyhow::Result<Option<BlockHash>> {
+        let (previous, source) = match holder {
+            BlockHolder::Send(send) => (Some(&send.previous), None),
+            BlockHolder::Change(change) => (Some(&change.previous), None),
+            BlockHolder::Receive(receive) => (Some(&receive.previous), Some(&receive.source)),
+            BlockHolder::Open(open) => (None, Some(&open.source)),
+            BlockHolder::State(state_block) => {
+                let previous_balance = match &state_block.previous {
+                    Previous::Open => Raw::zero(),
+                    Previous::Block(hash) => match self.block_by_hash(hash).await? {
+                        Some(previous) => previous.balance().to_owned(),
+                        None => return Ok(Some(hash.to_owned())),
+                    },
+                };
+                // Only a receive links to a block, and only one that increases the balance.
+                if state_block.balance <= previous_balance {
+                    return Ok(None);
+                }
+                let source = BlockHash::try_from(state_block.link.as_bytes())?;
+                if self.block_by_hash(&source).await?.is_none() {
+                    return Ok(Some(source));
+                }
+                return Ok(None);
+            }
+        };
 
-        // Block already exists, we can ignore this.
-        // In reality this shouldn't even happen so it should be a panic.
-        // This function should only have the chance to be called once per block.
-        if self
-            .state
-            .lock()
-            .await
-            .get_block_by_hash(&block_hash)
-            .await
-            .with_context(context)?
-            .is_some()
-        {
-            return Err(anyhow!("Block already exists")).with_context(context);
+        for hash in previous.into_iter().chain(source) {
+            if self.block_by_hash(hash).await?.is_none() {
+                return Ok(Some(hash.to_owned()));
+            }
         }
+        Ok(None)
+    }
 
-        let context = || format!("Block {:?}", block);
-        block
-            .verify_signature(&block.account())
-            .context("Incorrect signature")
-            .with_context(context)?;
+    /// Check what we can of `holder` without our ledger, so blocks aren't kept waiting for a
+    /// dependency unless they're worked and signed: its work against the lowest threshold it
+    /// could need, and its signature when the block names its signer. Legacy sends, receives
+    /// and changes only get their account from the previous block, so their signatures are
+    /// checked once that is in the ledger.
+    pub fn precheck(&self, holder: &BlockHolder) -> anyhow::Result<()> {
+        let thresholds = self.network.work_thresholds();
+        let previous_subject = |previous: &BlockHash| Subject::Hash(previous.to_owned());
+        let (work, subject, threshold, signature, signers) = match holder {
+            BlockHolder::Send(b) => (
+                &b.work,
+                previous_subject(&b.previous),
+                thresholds.epoch_1,
+                &b.signature,
+                vec![],
+            ),
+            BlockHolder::Receive(b) => (
+                &b.work,
+                previous_subject(&b.previous),
+                thresholds.epoch_1,
+                &b.signature,
+                vec![],
+            ),
+            BlockHolder::Change(b) => (
+                &b.work,
+                previous_subject(&b.previous),
+                thresholds.epoch_1,
+                &b.signature,
+                vec![],
+            ),
+            BlockHolder::Open(b) => (
+                &b.work,
+                Subject::Public(b.account.to_owned()),
+                thresholds.epoch_1,
+                &b.signature,
+                vec![b.account.to_owned()],
+            ),
+            BlockHolder::State(b) => {
+                // Blocks with an epoch link are either epoch blocks or sends to the link.
+                let mut signers = vec![b.account.to_owned()];
+                signers.extend(
+                    self.network
+                        .epoch_for_link(&b.link)
+                        .and_then(|epoch| self.network.epoch_signer(&epoch)),
+                );
+                let subject = match &b.previous {
+                    Previous::Block(previous) => previous_subject(previous),
+                    Previous::Open => Subject::Public(b.account.to_owned()),
+                };
+                (&b.work, subject, thresholds.lowest(), &b.signature, signers)
+            }
+        };
 
-        let work = block.work();
-        if work.is_none() {
-            return Err(anyhow!("Work is missing from block")).with_context(context);
+        let work = work.as_ref().ok_or(BlockRejection::MissingWork)?;
+        let difficulty = work.difficulty(&subject)?;
+        if difficulty < threshold {
+            return Err(BlockRejection::InsufficientWork {
+                difficulty,
+                threshold,
+            }
+            .into());
         }
-        // TODO: Verify work
-
-        // TODO: For now just assume this is a send block
-        match block.block_type() {
-            BlockType::Send => {
-                dbg!(block);
-
-                let previous_hash = match block.previous() {
-                    Previous::Block(h) => h,
-                    Previous::Open => {
-                        return Err(anyhow!("Send block has a blank previous block hash"))
-                            .with_context(context)
-                    }
-                };
+        if !signers.is_empty() {
+            let hash = holder.hash();
+            let signature = signature.as_ref().ok_or(BlockRejection::BadSignature)?;
+            if !signers
+                .iter()
+                .any(|signer| signer.verify(hash.as_bytes(), signature).is_ok())
+            {
+                return Err(BlockRejection::BadSignature.into());
+            }
+        }
+        Ok(())
+    }
 
-                let prev_block = self
-                    .state
-                    .lock()
-                    .await
-                    .get_block_by_hash(previous_hash)
-                    .await
-                    .context("Previous block")
-                    .with_context(context)?
-                    .ok_or_else(|| anyhow!("Could not find previous block"))
-                    .with_context(context)?;
-                let prev_balance = prev_block.balance();
-
-                if block.balance() >= prev_balance {
-                    return Err(anyhow!(
-                        "Can not increase balance in a send block. Prev: {:?}",
-                        prev_block
-                    ))
-                    .with_context(context);
+    /// Keep `holder` for later if a block it depends on is missing. Returns true if it was kept,
+    /// or was already waiting.
+    pub async fn queue_unchecked(&self, holder: &BlockHolder) -> anyhow::Result<bool> {
+        let unchecked = match &self.unchecked {
+            Some(unchecked) => unchecked,
+            None => return Ok(false),
+        };
+        let dependency = match self.missing_dependency(holder).await? {
+            Some(dependency) => dependency,
+            None => return Ok(false),
+        };
+        debug!("Block {:?} waits for {:?}", holder.hash(), dependency);
+        unchecked
+            .lock()
+            .await
+            .insert(dependency, holder.to_owned(), Instant::now());
+        Ok(true)
+    }
+
+    /// Handle the blocks that were waiting for blocks we've since added to the ledger. Handling
+    /// them can add more blocks, which are retried in turn. A block that fails is logged and
+    /// doesn't stop the others.
+    pub async fn retry_unchecked(&mut self) -> anyhow::Result<()> {
+        let unchecked = match &self.unchecked {
+            Some(unchecked) => unchecked.clone(),
+            None => {
+                self.arrived.clear();
+                return Ok(());
+            }
+        };
+        while let Some(hash) = self.arrived.pop() {
+            let ready = unchecked.lock().await.take(&hash);
+            for holder in ready {
+                let hash = holder.hash();
+                if let Err(err) = self.handle_block(holder).await {
+                    warn!("Unchecked block {:?} failed: {:?}", hash, err);
                 }
+            }
+        }
+        Ok(())
+    }
+
+    /// Start an election for a block, or add it to an existing one if it is a fork. The block is
+    /// added to the ledger once it is confirmed.
+    pub async fn start_election(&self, block: Block) -> anyhow::Result<()> {
+        match &self.elections {
+            Some(elections) => {
+                elections.lock().await.insert(block, Instant::now())?;
+            }
+            None => return Err(anyhow!("No elections to start")),
+        }
+        Ok(())
+    }
+
+    /// The sum of the balances of accounts delegating to `representative`.
+    pub async fn representative_weight(&self, representative: &Public) -> anyhow::Result<Raw> {
+        self.state
+            .lock()
+            .await
+            .representative_weight(representative)
+            .await
+    }
+
+    /// Check the timestamp of a vote. Fails with a [VoteRejection] if it is from the future, or
+    /// isn't newer than the latest vote we have from the representative for this block.
+    #[instrument(skip(self))]
+    pub async fn validate_vote(
+        &mut self,
+        hash: &BlockHash,
+        representative: &Public,
+        timestamp: &Timestamp,
+    ) -> anyhow::Result<()> {
+        if !timestamp.is_final() && !timestamp.is_sequence() {
+            let max = Timestamp::now().to_u64() + MAX_VOTE_CLOCK_DRIFT.as_millis() as u64;
+            if timestamp.to_u64() > max {
+                return Err(VoteRejection::FutureTimestamp(timestamp.to_u64()).into());
+            }
+        }
 
-                let _to_account = block.destination().with_context(context)?;
-                let _amount = prev_balance
-                    .checked_sub(block.balance())
-                    .ok_or_else(|| {
-                        anyhow!(
-                            "Subtracting prev_balance {:?} and new balance {:?}",
-                            prev_balance,
-                            block.balance()
-                        )
-                    })
-                    .with_context(context)?;
-            }
-            BlockType::Open => {
-                dbg!(block);
-
-                // If the block is the genesis block, we basically just trust the balance.
-                if !block.is_genesis(&self.network)? {
-                    // TODO: Make sure the balance in the open block matches the amount in the
-                    // send block.
+        let latest = self
+            .state
+            .lock()
+            .await
+            .vote_timestamp(hash, representative)
+            .await?;
+        if let Some(latest) = latest {
+            if timestamp.to_u64() <= latest {
+                return Err(VoteRejection::Replay {
+                    timestamp: timestamp.to_u64(),
+                    latest,
                 }
+                .into());
             }
-            _ => todo!(),
         }
+        Ok(())
+    }
 
+    /// Add a block that has been deemed valid by ORV.
+    ///
+    /// The block is checked against the ledger first with [Self::validate_block], which also
+    /// works out everything else adding it changes, e.g. the representative weights.
+    pub async fn add_elected_block(&mut self, block: &Block) -> anyhow::Result<()> {
+        debug!("Adding elected block {:?}", &block);
+        let context = || format!("Block {:?}", &block);
+
+        let change = self.validate_block(block).await.with_context(context)?;
         self.state
             .lock()
             .await
-            .add_block(block)
+            .add_block(block, &change)
             .await
             .with_context(context)?;
-
-        // self.balance_rep_weights(block)
-        //     .await
-        //     .with_context(context)?;
+        self.arrived.push(block.hash()?.to_owned());
 
         Ok(())
     }
 
+    /// Add a block without validating it against the ledger, e.g. the genesis block, or when
+    /// we aren't running elections. Only the representative weights are updated along with
+    /// it, and only if we have the previous block, since otherwise we can't tell what it moves.
+    pub async fn add_unvalidated_block(&self, block: &Block) -> anyhow::Result<()> {
+        let weights = match block.previous() {
+            Previous::Block(previous) => match self.block_by_hash(previous).await? {
+                Some(previous) => WeightChange::for_block(block, Some(&previous)),
+                None => vec![],
+            },
+            Previous::Open => WeightChange::for_block(block, None),
+        };
+        let change = LedgerChange {
+            weights,
+            ..Default::default()
+        };
+        self.state.lock().await.add_block(block, &change).await
+    }
+
+    /// Convert a block received from the network into a [Block], filling in the details that
+    /// legacy blocks don't contain from our ledger, e.g. the account of a send block.
+    pub async fn block_from_holder(&self, holder: BlockHolder) -> anyhow::Result<Block> {
+        Ok(match holder {
+            BlockHolder::Send(send) => {
+                let previous = self
+                    .block_by_hash(&send.previous)
+                    .await?
+                    .ok_or_else(|| BlockRejection::GapPrevious(send.previous.to_owned()))?;
+                Block::from_send_block(&send, previous.account(), previous.representative())
+            }
+            BlockHolder::Open(open) => {
+                let amount = self
+                    .sent_amount(&open.source)
+                    .await?
+                    .ok_or_else(|| BlockRejection::GapSource(open.source.to_owned()))?;
+                Block::from_open_block(&open, &Previous::Open, &amount)
+            }
+            BlockHolder::Receive(receive) => {
+                let previous = self
+                    .block_by_hash(&receive.previous)
+                    .await?
+                    .ok_or_else(|| BlockRejection::GapPrevious(receive.previous.to_owned()))?;
+                let amount = self
+                    .sent_amount(&receive.source)
+                    .await?
+                    .ok_or_else(|| BlockRejection::GapSource(receive.source.to_owned()))?;
+                let balance = previous
+                    .balance()
+                    .checked_add(&amount)
+                    .ok_or_else(|| anyhow!("Balance overflow receiving {:?}", &receive.source))?;
+                Block::from_receive_block(
+                    &receive,
+                    previous.account(),
+                    previous.representative(),
+                    &balance,
+                )
+            }
+            BlockHolder::Change(change) => {
+                let previous = self
+                    .block_by_hash(&change.previous)
+                    .await?
+                    .ok_or_else(|| BlockRejection::GapPrevious(change.previous.to_owned()))?;
+                Block::from_change_block(&change, previous.account(), previous.balance())
+            }
+            BlockHolder::State(mut state_block) => {
+                let previous_balance = match &state_block.previous {
+                    Previous::Open => Raw::zero(),
+                    Previous::Block(hash) => self
+                        .block_by_hash(hash)
+                        .await?
+                        .ok_or_else(|| anyhow!("Previous block {:?} not found", hash))?
+                        .balance()
+                        .to_owned(),
+                };
+                let is_send = state_block.balance < previous_balance;
+                let amount = if is_send {
+                    previous_balance.checked_sub(&state_block.balance)
+                } else {
+                    state_block.balance.checked_sub(&previous_balance)
+                }
+                .ok_or_else(|| anyhow!("Could not calculate amount"))?;
+                state_block.set_link_type(is_send, amount)?;
+                Block::from_state_block(&state_block)
+            }
+        })
+    }
+
+    /// The amount sent by a send block in our ledger, or None if we don't have it.
+    pub async fn sent_amount(&self, send_hash: &BlockHash) -> anyhow::Result<Option<Raw>> {
+        let send = match self.block_by_hash(send_hash).await? {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+        let previous_hash = match send.previous() {
+            Previous::Block(hash) => hash,
+            Previous::Open => return Err(anyhow!("Source {:?} is not a send", send_hash)),
+        };
+        let previous = self
+            .block_by_hash(previous_hash)
+            .await?
+            .ok_or_else(|| anyhow!("Block before source {:?} not found", send_hash))?;
+        let amount = previous
+            .balance()
+            .checked_sub(send.balance())
+            .ok_or_else(|| anyhow!("Source {:?} is not a send", send_hash))?;
+        Ok(Some(amount))
+    }
+
     pub async fn get_latest_block(&self, account: &Public) -> anyhow::Result<Option<Block>> {
         let block_hash = self
             .state
@@ -0,0 +1,602 @@
+use super::ledger::BlockRejection;
+use super::Peer;
+use crate::blocks::{BlockHash, BlockHolder, Previous};
+use crate::node::header::{Extensions, Header, MessageType};
+use crate::node::messages::bulk_pull::{BulkPull, BulkPullResp};
+use crate::node::messages::frontier_req::FrontierReq;
+use crate::node::messages::frontier_resp::FrontierResp;
+use crate::node::peer_manager::InvalidMessage;
+use crate::node::state::unix_seconds;
+use crate::node::wire::Wire;
+use crate::Public;
+use anyhow::{anyhow, Context};
+use std::convert::TryFrom;
+use tracing::{debug, info, instrument, warn};
+
+/// How many frontiers to read from [State](crate::node::state::State) at a time when serving a
+/// frontier req.
+const FRONTIER_BATCH: usize = 1000;
+
+/// An account chain we're missing blocks of, found while bootstrapping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pull {
+    pub account: Public,
+
+    /// The peer's frontier for the account. Pulling starts here.
+    pub head: BlockHash,
+
+    /// Our frontier for the account, where pulling stops. Zero if we don't have the account.
+    pub end: BlockHash,
+}
+
+impl Peer {
+    /// Ask for every frontier the peer has. The peer answers with a stream of [FrontierResp]
+    /// without any headers, so this should only be sent on a dedicated bootstrap connection.
+    #[instrument(skip(self))]
+    pub async fn send_frontier_req(&mut self) -> anyhow::Result<()> {
+        self.send_header(MessageType::FrontierReq, Extensions::new())
+            .await?;
+        self.send(&FrontierReq::all()).await?;
+        self.frontier_stream = true;
+        Ok(())
+    }
+
+    /// Are we in the middle of a header-less frontier or bulk pull stream?
+    pub(crate) fn in_bootstrap_stream(&self) -> bool {
+        self.frontier_stream || self.current_pull.is_some()
+    }
+
+    /// Parse as many frontiers or pulled blocks as are available in the incoming buffer.
+    pub(crate) async fn recv_bootstrap_stream(&mut self) -> anyhow::Result<()> {
+        while self.frontier_stream {
+            match self.recv::<FrontierResp>(None)? {
+                Some(frontier_resp) => self.handle_frontier_resp(frontier_resp).await?,
+                None => return Ok(()),
+            }
+        }
+
+        while self.current_pull.is_some() {
+            let len = match self.incoming_buffer.first() {
+                Some(b) => BulkPullResp::len_from_type(*b).context(InvalidMessage)?,
+                None => return Ok(()),
+            };
+            if self.incoming_buffer.len() < len {
+                return Ok(());
+            }
+            let data = self.recv_immediate(len)?;
+            match BulkPullResp::deserialize(None, &data).context(InvalidMessage)? {
+                BulkPullResp::Block(holder) => self.pulled_blocks.push(*holder),
+                BulkPullResp::End => self.finish_pull().await?,
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn handle_frontier_resp(
+        &mut self,
+        frontier_resp: FrontierResp,
+    ) -> anyhow::Result<()> {
+        if frontier_resp.is_end() {
+            info!(
+                "Frontier stream finished with {} accounts to pull",
+                self.pulls.len()
+            );
+            self.frontier_stream = false;
+            return self.send_next_bulk_pull().await;
+        }
+
+        if let Some(pull) = self.frontier_pull(&frontier_resp).await? {
+            debug!("Need to pull {:?}", pull);
+            self.pulls.push(pull);
+        }
+        Ok(())
+    }
+
+    /// Compare a peer's frontier with our ledger, returning what needs to be pulled, if anything.
+    async fn frontier_pull(&self, frontier_resp: &FrontierResp) -> anyhow::Result<Option<Pull>> {
+        let account = frontier_resp.account();
+        let head = frontier_resp.frontier_hash();
+
+        let state = self.state.lock().await;
+        let end = match state.get_latest_block_hash_for_account(account).await? {
+            Some(ours) if &ours == head => return Ok(None),
+            Some(ours) => {
+                if state.get_block_by_hash(head).await?.is_some() {
+                    // We're ahead of the peer.
+                    return Ok(None);
+                }
+                ours
+            }
+            None => BlockHash::zero(),
+        };
+
+        Ok(Some(Pull {
+            account: account.to_owned(),
+            head: head.to_owned(),
+            end,
+        }))
+    }
+
+    /// Request the next chain in [Peer::pulls]. Once there are none left, retry the chains that
+    /// were waiting for a send from another one.
+    async fn send_next_bulk_pull(&mut self) -> anyhow::Result<()> {
+        let pull = match self.pulls.pop() {
+            Some(pull) => pull,
+            None => return self.retry_gap_pulls().await,
+        };
+        let bulk_pull = BulkPull::new(pull.head.clone(), pull.end.clone(), None);
+        self.send_header(MessageType::BulkPull, bulk_pull.extensions())
+            .await?;
+        self.send(&bulk_pull).await?;
+        self.current_pull = Some(pull);
+        Ok(())
+    }
+
+    /// The pull stream has ended. Check that the blocks form a chain from the head we asked for
+    /// down to our frontier, then add them oldest first.
+    async fn finish_pull(&mut self) -> anyhow::Result<()> {
+        let pull = self
+            .current_pull
+            .take()
+            .expect("finish_pull without a pull");
+        let holders: Vec<BlockHolder> = self.pulled_blocks.drain(..).collect();
+        debug!("Pulled {} blocks for {:?}", holders.len(), &pull);
+
+        match self.add_pulled_chain(&pull, holders).await {
+            Ok(Some(gap)) => {
+                debug!("Pulled chain {:?} waits for a send at {:?}", &pull, &gap.0);
+                self.gap_pulls.push(gap);
+            }
+            Ok(None) => {}
+            Err(err) => warn!("Could not add pulled chain {:?}: {:?}", &pull, err),
+        }
+        self.send_next_bulk_pull().await
+    }
+
+    /// Add what we can of the chains waiting for sends, until none of them gets further.
+    async fn retry_gap_pulls(&mut self) -> anyhow::Result<()> {
+        loop {
+            let mut progress = false;
+            for (pull, holders) in std::mem::take(&mut self.gap_pulls) {
+                let waiting = holders.len();
+                match self.add_pulled_chain(&pull, holders).await {
+                    Ok(Some(gap)) => {
+                        progress |= gap.1.len() < waiting;
+                        self.gap_pulls.push(gap);
+                    }
+                    Ok(None) => progress = true,
+                    Err(err) => warn!("Could not add pulled chain {:?}: {:?}", &pull, err),
+                }
+            }
+            if !progress {
+                break;
+            }
+        }
+        for (pull, _) in self.gap_pulls.drain(..) {
+            warn!("Pulled chain {:?} is missing a send", &pull);
+        }
+        Ok(())
+    }
+
+    /// `holders` are newest first, as sent by the peer. If a block receives a send we don't have,
+    /// the blocks before it are still added, and the rest of the chain is returned with the
+    /// pull from there to retry once we might have the send.
+    async fn add_pulled_chain(
+        &mut self,
+        pull: &Pull,
+        mut holders: Vec<BlockHolder>,
+    ) -> anyhow::Result<Option<(Pull, Vec<BlockHolder>)>> {
+        let mut previous = if pull.end == BlockHash::zero() {
+            Previous::Open
+        } else {
+            Previous::Block(pull.end.clone())
+        };
+
+        while let Some(holder) = holders.pop() {
+            match self.add_pulled_block(&previous, holder.clone()).await {
+                Ok(hash) => previous = Previous::Block(hash),
+                Err(err) if matches!(err.downcast_ref(), Some(BlockRejection::GapSource(_))) => {
+                    holders.push(holder);
+                    let end = match previous {
+                        Previous::Block(hash) => hash,
+                        Previous::Open => BlockHash::zero(),
+                    };
+                    let pull = Pull {
+                        end,
+                        ..pull.to_owned()
+                    };
+                    return Ok(Some((pull, holders)));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        if previous != Previous::Block(pull.head.clone()) {
+            return Err(anyhow!("Pulled chain ended at {:?}", previous));
+        }
+        Ok(None)
+    }
+
+    async fn add_pulled_block(
+        &mut self,
+        previous: &Previous,
+        holder: BlockHolder,
+    ) -> anyhow::Result<BlockHash> {
+        let block = self.block_from_holder(holder).await?;
+        let hash = block.hash()?.to_owned();
+        if block.previous() != previous {
+            return Err(anyhow!(
+                "Pulled block {:?} does not follow {:?}",
+                hash,
+                previous
+            ));
+        }
+        self.add_elected_block(&block).await?;
+        Ok(hash)
+    }
+
+    /// Send our frontiers in account order, followed by the zero frontier.
+    #[instrument(skip(self, _header))]
+    pub async fn handle_frontier_req(
+        &mut self,
+        _header: &Header,
+        frontier_req: FrontierReq,
+    ) -> anyhow::Result<()> {
+        let now = unix_seconds();
+        let max_age = frontier_req.age();
+        let mut remaining = frontier_req.count();
+        let mut start = frontier_req.start().to_owned();
+        let mut skip_start = false;
+
+        'batches: while remaining > 0 {
+            let frontiers = self
+                .state
+                .lock()
+                .await
+                .frontiers(&start, FRONTIER_BATCH)
+                .await?;
+            let last_batch = frontiers.len() < FRONTIER_BATCH;
+
+            for frontier in frontiers {
+                // Move on even past frontiers we skip, so the next batch starts after them.
+                let is_start = skip_start && frontier.account == start;
+                start = frontier.account.clone();
+                if is_start {
+                    continue;
+                }
+                if max_age != u32::MAX && now.saturating_sub(frontier.modified) > max_age as u64 {
+                    continue;
+                }
+                self.send(&FrontierResp::new(frontier.account, frontier.hash))
+                    .await?;
+                remaining -= 1;
+                if remaining == 0 {
+                    break 'batches;
+                }
+            }
+
+            if last_batch {
+                break;
+            }
+            skip_start = true;
+        }
+
+        self.send(&FrontierResp::end()).await
+    }
+
+    /// Send the blocks of a chain from `start` back to, but not including, `end`.
+    #[instrument(skip(self, _header))]
+    pub async fn handle_bulk_pull(
+        &mut self,
+        _header: &Header,
+        bulk_pull: BulkPull,
+    ) -> anyhow::Result<()> {
+        // The start is either a block hash, or an account to start from its frontier.
+        let mut next = if self.block_by_hash(bulk_pull.start()).await?.is_some() {
+            Some(bulk_pull.start().to_owned())
+        } else {
+            let account = Public::try_from(bulk_pull.start().as_bytes())?;
+            self.state
+                .lock()
+                .await
+                .get_latest_block_hash_for_account(&account)
+                .await?
+        };
+
+        // A count of zero means no limit.
+        let mut remaining = match bulk_pull.count() {
+            Some(0) | None => u32::MAX,
+            Some(count) => count,
+        };
+
+        while let Some(hash) = next {
+            if &hash == bulk_pull.end() || remaining == 0 {
+                break;
+            }
+            let block = match self.block_by_hash(&hash).await? {
+                Some(block) => block,
+                None => break,
+            };
+            let holder = match block.to_holder() {
+                Ok(holder) => holder,
+                Err(err) => {
+                    warn!("Can not send {:?} in bulk pull: {:?}", &hash, err);
+                    break;
+                }
+            };
+            self.send(&BulkPullResp::Block(Box::new(holder))).await?;
+            remaining -= 1;
+            next = match block.previous() {
+                Previous::Block(previous) => Some(previous.to_owned()),
+                Previous::Open => None,
+            };
+        }
+
+        self.send(&BulkPullResp::End).await
+    }
+
+    pub(crate) fn bootstrap_finished(&self) -> bool {
+        !self.in_bootstrap_stream() && self.pulls.is_empty()
+    }
+
+    /// Accounts found to be missing blocks by the frontier stream.
+    pub fn pulls(&self) -> &[Pull] {
+        &self.pulls
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{Block, SendBlock};
+    use crate::network::Network;
+    use crate::node::peer::Packet;
+    use crate::node::state::MemoryState;
+    use crate::node::wire::Wire;
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    fn gen_send() -> SendBlock {
+        serde_json::from_str(
+            r#"{
+                "type": "send",
+                "previous": "991CF190094C00F0B68E2E5F75F6BEE95A2E0BD93CEAA4A6734DB9F19B728948",
+                "destination": "nano_13ezf4od79h1tgj9aiu4djzcmmguendtjfuhwfukhuucboua8cpoihmh8byo",
+                "balance": "FD89D89D89D89D89D89D89D89D89D89D",
+                "work": "3c82cc724905ee95",
+                "signature": "5B11B17DB9C8FE0CC58CAC6A6EECEF9CB122DA8A81C6D3DB1B5EE3AB065AA8F8CB1D6765C8EB91B58530C5FF5987AD95E6D34BB57F44257E20795EE412E61600"
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn frontier_req_skips_old_batches() {
+        use crate::blocks::{BlockType, Link, ValidationState};
+        use crate::node::state::{LedgerChange, State};
+        use crate::Raw;
+        use std::time::Duration;
+
+        let network = Network::Test;
+        let state = Arc::new(Mutex::new(MemoryState::new(network)));
+        let addr = SocketAddr::from_str("127.0.0.1:7075").unwrap();
+        let (mut peer, _tx, mut rx) = Peer::new_with_channels(network, state.clone(), addr);
+        let open = |account: Public| {
+            Block::new(
+                BlockType::State,
+                account.to_owned(),
+                Previous::Open,
+                account,
+                Raw::from(1),
+                Link::Nothing,
+                ValidationState::Valid,
+            )
+        };
+        let account = |n: u32| {
+            let mut bytes = [0u8; Public::LEN];
+            bytes[..4].copy_from_slice(&n.to_be_bytes());
+            Public::try_from(bytes.as_ref()).unwrap()
+        };
+
+        // More than a batch of accounts too old to be sent, then one that is recent enough.
+        for n in 0..FRONTIER_BATCH as u32 + 10 {
+            let block = open(account(n));
+            state
+                .lock()
+                .await
+                .add_block(&block, &LedgerChange::default())
+                .await
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(2100)).await;
+        let recent = open(account(u32::MAX));
+        state
+            .lock()
+            .await
+            .add_block(&recent, &LedgerChange::default())
+            .await
+            .unwrap();
+
+        let header = Header::new(network, MessageType::FrontierReq, Extensions::new());
+        let req = FrontierReq::new(Public::zero(), 1, u32::MAX);
+        // Without progress through skipped frontiers the handler never yields, so it runs on
+        // its own thread where a timeout can still catch it.
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let result = runtime.block_on(async move {
+                peer.handle_frontier_req(&header, req).await.map(|_| peer)
+            });
+            let _ = done_tx.send(result);
+        });
+        let peer = done_rx
+            .recv_timeout(Duration::from_secs(10))
+            .expect("Frontier req never finished")
+            .unwrap();
+
+        drop(peer);
+        let mut sent = vec![];
+        while let Some(packet) = rx.recv().await {
+            sent.push(FrontierResp::deserialize(None, &packet.data).unwrap());
+        }
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].account(), &account(u32::MAX));
+        assert_eq!(sent[0].frontier_hash(), recent.hash().unwrap());
+        assert!(sent[1].is_end());
+    }
+
+    #[tokio::test]
+    async fn pull_stream() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let state = Arc::new(Mutex::new(MemoryState::new(network)));
+        let addr = SocketAddr::from_str("127.0.0.1:7075").unwrap();
+        let (mut peer, _tx, _rx) = Peer::new_with_channels(network, state, addr);
+        peer.init().await.unwrap();
+
+        let send = gen_send();
+        let send_hash = Block::from_send_block(&send, genesis.account(), genesis.representative())
+            .hash()
+            .unwrap()
+            .to_owned();
+        peer.current_pull = Some(Pull {
+            account: genesis.account().to_owned(),
+            head: send_hash.clone(),
+            end: genesis.hash().unwrap().to_owned(),
+        });
+
+        let mut data = BulkPullResp::Block(Box::new(BlockHolder::Send(send))).serialize();
+        data.extend(BulkPullResp::End.serialize());
+
+        peer.handle_packet(Packet::new(data)).await.unwrap();
+        assert!(peer.bootstrap_finished());
+        assert_eq!(
+            peer.get_latest_block(genesis.account())
+                .await
+                .unwrap()
+                .unwrap()
+                .hash()
+                .unwrap(),
+            &send_hash
+        );
+    }
+
+    #[tokio::test]
+    async fn pulled_chains_wait_for_their_sends() {
+        use crate::blocks::Link;
+        use crate::node::peer::test_blocks::{previous, state_block, NETWORK};
+        use crate::Private;
+
+        let state = Arc::new(Mutex::new(MemoryState::new(NETWORK)));
+        let addr = SocketAddr::from_str("127.0.0.1:7075").unwrap();
+        let (mut peer, _tx, _rx) = Peer::new_with_channels(NETWORK, state, addr);
+        let sender = Private::random();
+        let open = state_block(&sender, Previous::Open, 1000, Link::Nothing);
+        peer.add_unvalidated_block(&open).await.unwrap();
+
+        let receiver = Private::random();
+        let receiver_public = receiver.to_public().unwrap();
+        let send = state_block(
+            &sender,
+            previous(&open),
+            700,
+            Link::DestinationAccount(receiver_public.to_owned()),
+        );
+        let receive = state_block(
+            &receiver,
+            Previous::Open,
+            300,
+            Link::Source(send.hash().unwrap().to_owned()),
+        );
+        let change = state_block(&receiver, previous(&receive), 300, Link::Nothing);
+
+        // The receiving chain is pulled before the chain with the send it receives.
+        peer.pulls = vec![Pull {
+            account: open.account().to_owned(),
+            head: send.hash().unwrap().to_owned(),
+            end: open.hash().unwrap().to_owned(),
+        }];
+        peer.current_pull = Some(Pull {
+            account: receiver_public.to_owned(),
+            head: change.hash().unwrap().to_owned(),
+            end: BlockHash::zero(),
+        });
+        for chain in &[vec![&change, &receive], vec![&send]] {
+            let mut data = vec![];
+            for block in chain {
+                let holder = Box::new(block.to_holder().unwrap());
+                data.extend(BulkPullResp::Block(holder).serialize());
+            }
+            data.extend(BulkPullResp::End.serialize());
+            peer.handle_packet(Packet::new(data)).await.unwrap();
+        }
+
+        assert!(peer.bootstrap_finished());
+        assert_eq!(
+            peer.get_latest_block(&receiver_public)
+                .await
+                .unwrap()
+                .unwrap(),
+            change
+        );
+    }
+
+    #[tokio::test]
+    async fn frontier_stream() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let state = Arc::new(Mutex::new(MemoryState::new(network)));
+        let addr = SocketAddr::from_str("127.0.0.1:7075").unwrap();
+        let (mut peer, _tx, _rx) = Peer::new_with_channels(network, state, addr);
+        peer.init().await.unwrap();
+        peer.frontier_stream = true;
+
+        let other_account =
+            Public::from_str("2A8B4F53D5AB03E1A9F1D63CBC44B11C6DD85E3A3AAEC55C1F0F2CE1FB4A2A46")
+                .unwrap();
+        let other_head =
+            BlockHash::from_str("90D0C16AC92DD35814E84BFBCC739A039615D0A42A76EF44ADAEF1D99E9F8A35")
+                .unwrap();
+        let genesis_head =
+            BlockHash::from_str("A170D51B94E00371ACE76E35AC81DC9405D5D04D4CEBC399AEACE07AE05DD293")
+                .unwrap();
+
+        let mut data = vec![];
+        data.extend(
+            FrontierResp::new(genesis.account().to_owned(), genesis_head.clone()).serialize(),
+        );
+        data.extend(FrontierResp::new(other_account.clone(), other_head.clone()).serialize());
+        data.extend(FrontierResp::end().serialize());
+
+        // Split in the middle of an entry to make sure partial frontiers are buffered.
+        let (first, second) = data.split_at(100);
+        peer.handle_packet(Packet::new(first.to_vec()))
+            .await
+            .unwrap();
+        assert!(peer.frontier_stream);
+        peer.handle_packet(Packet::new(second.to_vec()))
+            .await
+            .unwrap();
+        assert!(!peer.frontier_stream);
+
+        // The last account is pulled first.
+        assert_eq!(
+            peer.current_pull,
+            Some(Pull {
+                account: other_account,
+                head: other_head,
+                end: BlockHash::zero(),
+            })
+        );
+        assert_eq!(
+            peer.pulls(),
+            &[Pull {
+                account: genesis.account().to_owned(),
+                head: genesis_head,
+                end: genesis.hash().unwrap().to_owned(),
+            }]
+        );
+    }
+}
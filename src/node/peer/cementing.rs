@@ -0,0 +1,208 @@
+//! Cementing confirmed blocks.
+//!
+//! When an election confirms a block, the block and everything it depends on can no longer be
+//! rolled back. That's the blocks before it in its account chain, and for every receive among
+//! them, the send it received, along with its own dependencies. Each account keeps a
+//! [ConfirmationHeight] with the number of cemented blocks and the latest of them.
+use crate::blocks::{Block, BlockHash, BlockType, Previous};
+use crate::node::peer::Peer;
+use crate::node::state::ConfirmationHeight;
+use crate::Raw;
+use anyhow::anyhow;
+use std::convert::TryFrom;
+use tracing::debug;
+
+impl Peer {
+    /// Cement a block in our ledger along with its dependencies across accounts. Returns the
+    /// number of newly cemented blocks.
+    pub async fn cement(&mut self, hash: &BlockHash) -> anyhow::Result<u64> {
+        let mut cemented = 0;
+        let mut targets = vec![hash.to_owned()];
+        'targets: while let Some(target) = targets.last() {
+            let uncemented = self.uncemented_chain(target).await?;
+            if uncemented.is_empty() {
+                targets.pop();
+                continue;
+            }
+
+            // Dependencies are cemented first, oldest first, so cementing this chain afterwards
+            // doesn't need to check them again.
+            for (block, previous_balance) in uncemented.iter().rev() {
+                let source = match received_send(block, previous_balance)? {
+                    Some(source) => source,
+                    None => continue,
+                };
+                let send = self
+                    .block_by_hash(&source)
+                    .await?
+                    .ok_or_else(|| anyhow!("Source {:?} is not in the ledger", source))?;
+                if !self.state.lock().await.is_cemented(&send).await? {
+                    targets.push(source);
+                    continue 'targets;
+                }
+            }
+
+            let (head, _) = &uncemented[0];
+            let account = head.account();
+            let mut state = self.state.lock().await;
+            let height = state
+                .confirmation_height(account)
+                .await?
+                .map(|c| c.height)
+                .unwrap_or(0);
+            let confirmation_height = ConfirmationHeight {
+                height: height + uncemented.len() as u64,
+                frontier: head.hash()?.to_owned(),
+            };
+            debug!("Cemented {:?} up to {:?}", account, confirmation_height);
+            state
+                .set_confirmation_height(account, &confirmation_height)
+                .await?;
+            cemented += uncemented.len() as u64;
+            targets.pop();
+        }
+        Ok(cemented)
+    }
+
+    /// The blocks from `hash` back to the account's confirmation frontier, newest first, each
+    /// with the balance before it. Empty if `hash` is already cemented.
+    async fn uncemented_chain(&self, hash: &BlockHash) -> anyhow::Result<Vec<(Block, Raw)>> {
+        let mut block = self
+            .block_by_hash(hash)
+            .await?
+            .ok_or_else(|| anyhow!("Block {:?} is not in the ledger", hash))?;
+        let frontier = self
+            .state
+            .lock()
+            .await
+            .confirmation_height(block.account())
+            .await?
+            .map(|c| c.frontier);
+
+        let mut chain = vec![];
+        loop {
+            if Some(block.hash()?) == frontier.as_ref() {
+                return Ok(chain);
+            }
+            let previous = match block.previous() {
+                Previous::Block(previous) => Some(
+                    self.block_by_hash(previous)
+                        .await?
+                        .ok_or_else(|| anyhow!("Previous block {:?} not found", previous))?,
+                ),
+                Previous::Open => None,
+            };
+            let previous_balance = previous
+                .as_ref()
+                .map(|p| p.balance().to_owned())
+                .unwrap_or_else(Raw::zero);
+            chain.push((block, previous_balance));
+            block = match previous {
+                Some(previous) => previous,
+                // Reaching the open block without passing the frontier means `hash` is below it.
+                None if frontier.is_some() => return Ok(vec![]),
+                None => return Ok(chain),
+            };
+        }
+    }
+}
+
+/// The send a block received, if it's a receive or open block.
+fn received_send(block: &Block, previous_balance: &Raw) -> anyhow::Result<Option<BlockHash>> {
+    Ok(match block.block_type() {
+        BlockType::Open | BlockType::Receive => Some(block.source()?.to_owned()),
+        BlockType::State if block.balance() > previous_balance => {
+            Some(BlockHash::try_from(block.link().as_bytes())?)
+        }
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::Link;
+    use crate::node::peer::test_blocks::{previous, state_block, NETWORK};
+    use crate::node::state::MemoryState;
+    use crate::Private;
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    #[tokio::test]
+    async fn cements_dependencies() {
+        let state = Arc::new(Mutex::new(MemoryState::new(NETWORK)));
+        let (mut peer, _, _) =
+            Peer::new_with_channels(NETWORK, state, SocketAddr::from_str("[::1]:1").unwrap());
+
+        // An account holding 1000 raw, standing in for a cemented genesis block.
+        let key = Private::random();
+        let account = key.to_public().unwrap();
+        let open = state_block(&key, Previous::Open, 1000, Link::Nothing);
+        let open_hash = open.hash().unwrap().to_owned();
+        peer.add_unvalidated_block(&open).await.unwrap();
+        let genesis_height = ConfirmationHeight {
+            height: 1,
+            frontier: open_hash.to_owned(),
+        };
+        peer.state
+            .lock()
+            .await
+            .set_confirmation_height(&account, &genesis_height)
+            .await
+            .unwrap();
+
+        let other = Private::random();
+        let other_public = other.to_public().unwrap();
+        let send = state_block(
+            &key,
+            previous(&open),
+            700,
+            Link::DestinationAccount(other_public.to_owned()),
+        );
+        peer.add_elected_block(&send).await.unwrap();
+        let send_hash = send.hash().unwrap().to_owned();
+        let receive = state_block(
+            &other,
+            Previous::Open,
+            300,
+            Link::Source(send_hash.to_owned()),
+        );
+        peer.add_elected_block(&receive).await.unwrap();
+
+        // Cementing the receive cements the send it received first.
+        assert_eq!(peer.cement(receive.hash().unwrap()).await.unwrap(), 2);
+        let state = peer.state.clone();
+        let confirmation_height = |account| {
+            let state = state.clone();
+            async move {
+                state
+                    .lock()
+                    .await
+                    .confirmation_height(&account)
+                    .await
+                    .unwrap()
+            }
+        };
+        assert_eq!(
+            confirmation_height(account).await,
+            Some(ConfirmationHeight {
+                height: 2,
+                frontier: send_hash.to_owned(),
+            })
+        );
+        assert_eq!(
+            confirmation_height(other_public).await,
+            Some(ConfirmationHeight {
+                height: 1,
+                frontier: receive.hash().unwrap().to_owned(),
+            })
+        );
+        assert_eq!(state.lock().await.cemented_count().await.unwrap(), 3);
+
+        assert_eq!(peer.cement(&send_hash).await.unwrap(), 0);
+        assert!(peer.rollback(&send_hash).await.is_err());
+        assert!(peer.block_by_hash(&send_hash).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,397 @@
+//! Blocks competing for the same root.
+//!
+//! A fork is noticed when a block arrives for a root that already has a different block, either
+//! in our ledger or in an active election. The competing blocks are put in the same election and
+//! we ask our peers to vote on them. When the winner is confirmed, a block of ours that lost is
+//! rolled back together with every block that depends on it.
+use crate::blocks::{Block, BlockHash, BlockType, Epoch, Previous, Subtype};
+use crate::node::elections::Root;
+use crate::node::header::{Header, MessageType};
+use crate::node::messages::confirm_req::{ConfirmReq, RootHashPair};
+use crate::node::peer::ledger::{state_subtype, BlockRejection};
+use crate::node::peer::{Packet, Peer};
+use crate::node::state::{LedgerChange, Receivable, ReceivableChange, WeightChange};
+use crate::node::wire::Wire;
+use crate::{Public, Raw};
+use anyhow::{anyhow, Context};
+use std::convert::TryFrom;
+use tracing::{debug, warn};
+
+impl Peer {
+    /// The block in our ledger for the same root as `block`, if it's a different block.
+    pub async fn ledger_fork(&self, block: &Block) -> anyhow::Result<Option<Block>> {
+        let hash = block.hash()?;
+        let head = self
+            .state
+            .lock()
+            .await
+            .get_latest_block_hash_for_account(block.account())
+            .await?;
+        let head = match head {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+        match block.previous() {
+            Previous::Block(previous) if previous == &head => return Ok(None),
+            Previous::Block(previous) => {
+                // A previous we don't have is a gap, not a fork.
+                if self.block_by_hash(previous).await?.is_none() {
+                    return Ok(None);
+                }
+            }
+            Previous::Open => {}
+        }
+
+        let existing = self
+            .block_at_root(block.account(), &Root::of(block))
+            .await?;
+        Ok(existing.filter(|existing| existing.hash().ok() != Some(hash)))
+    }
+
+    /// The block in `account`'s chain with `root`, found by walking back from the head.
+    pub async fn block_at_root(
+        &self,
+        account: &Public,
+        root: &Root,
+    ) -> anyhow::Result<Option<Block>> {
+        let mut block = match self.get_latest_block(account).await? {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+        loop {
+            if &Root::of(&block) == root {
+                return Ok(Some(block));
+            }
+            block = match block.previous() {
+                Previous::Block(previous) => self
+                    .block_by_hash(previous)
+                    .await?
+                    .ok_or_else(|| anyhow!("Chain of {:?} is missing {:?}", account, previous))?,
+                Previous::Open => return Ok(None),
+            };
+        }
+    }
+
+    /// Put a block we received in an election. If it competes with a block in our ledger, that
+    /// block joins the election too, and we ask our peers to vote on a fork.
+    pub async fn elect_block(&mut self, block: &Block) -> anyhow::Result<()> {
+        let elections = match &self.elections {
+            Some(elections) => elections.clone(),
+            None => return Err(anyhow!("No elections to start")),
+        };
+        if let Some(existing) = self.ledger_fork(block).await? {
+            warn!(
+                "Fork for {:?}: {:?} competes with {:?} in our ledger",
+                Root::of(block),
+                block.hash()?,
+                existing.hash()?
+            );
+            self.start_election(existing).await?;
+        }
+        self.start_election(block.to_owned()).await?;
+
+        let competing: Option<Vec<Block>> = elections
+            .lock()
+            .await
+            .election_for_block(block.hash()?)
+            .filter(|election| election.is_fork())
+            .map(|election| election.blocks().cloned().collect());
+        if let Some(competing) = competing {
+            self.request_confirmation(&competing).await?;
+        }
+        Ok(())
+    }
+
+    /// Ask this peer and some of our other peers to vote on `blocks`.
+    pub async fn request_confirmation(&mut self, blocks: &[Block]) -> anyhow::Result<()> {
+        let mut pairs = vec![];
+        for block in blocks {
+            pairs.push(RootHashPair {
+                hash: block.hash()?.to_owned(),
+                root: BlockHash::try_from(Root::of(block).as_bytes())?,
+            });
+        }
+
+        let targets = match &self.peer_manager {
+            Some(peer_manager) => peer_manager.lock().await.flood_targets(&self.peer_addr),
+            None => vec![],
+        };
+        for chunk in pairs.chunks(ConfirmReq::MAX_PAIRS) {
+            let request = ConfirmReq::ConfirmReqByHash(chunk.to_vec());
+            self.send_confirm_req(&request).await?;
+
+            let mut data = Header::new(self.network, MessageType::ConfirmReq, request.extensions())
+                .serialize();
+            data.extend(request.serialize());
+            for target in &targets {
+                if target.try_send(Packet::new(data.clone())).is_err() {
+                    debug!("Could not send confirm req to a peer");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the winner of an election to the ledger. If we had already added another block for
+    /// its root, that block lost, so it's rolled back first along with everything depending on
+    /// it.
+    pub async fn add_confirmed_block(&mut self, block: &Block) -> anyhow::Result<()> {
+        let err = match self.add_elected_block(block).await {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+        let root = match err.downcast_ref::<BlockRejection>() {
+            Some(BlockRejection::Old) => return Ok(()),
+            Some(BlockRejection::Fork(root)) => root.to_owned(),
+            _ => return Err(err),
+        };
+
+        let loser = self
+            .block_at_root(block.account(), &root)
+            .await?
+            .ok_or_else(|| anyhow!("No block in our ledger for {:?}", root))?;
+        let rolled_back = self.rollback(loser.hash()?).await?;
+        warn!(
+            "Fork for {:?} resolved: {:?} won, rolled back {} blocks",
+            root,
+            block.hash()?,
+            rolled_back.len()
+        );
+        self.add_elected_block(block).await
+    }
+
+    /// Remove `hash` from the ledger, along with the blocks after it in its account chain and
+    /// the receives of any sends among them, recursively. Returns the removed blocks in the
+    /// order they were removed.
+    ///
+    /// Fails without changing anything if `hash` is cemented. Everything depending on a block
+    /// that isn't cemented is also not cemented, so the rest can always be rolled back.
+    pub async fn rollback(&mut self, hash: &BlockHash) -> anyhow::Result<Vec<Block>> {
+        if let Some(block) = self.block_by_hash(hash).await? {
+            if self.state.lock().await.is_cemented(&block).await? {
+                return Err(anyhow!("Can't roll back cemented block {:?}", hash));
+            }
+        }
+
+        let mut rolled_back = vec![];
+        let mut targets = vec![hash.to_owned()];
+        while let Some(target) = targets.last() {
+            let block = match self.block_by_hash(target).await? {
+                Some(block) => block,
+                None => {
+                    targets.pop();
+                    continue;
+                }
+            };
+            let head = self
+                .get_latest_block(block.account())
+                .await?
+                .context("Rolling back a block of an account without a head")?;
+
+            match self.rollback_head(&head).await? {
+                Some(receive) => targets.push(receive),
+                None => {
+                    debug!("Rolled back {:?}", head.hash()?);
+                    rolled_back.push(head);
+                }
+            }
+        }
+        Ok(rolled_back)
+    }
+
+    /// Undo adding `head`: its receivable entries, epoch and representative weights.
+    ///
+    /// If `head` is a send that has already been received, nothing is changed and the hash of
+    /// the receive is returned, since it has to be rolled back first.
+    async fn rollback_head(&mut self, head: &Block) -> anyhow::Result<Option<BlockHash>> {
+        let hash = head.hash()?;
+        let account = head.account();
+        let previous = match head.previous() {
+            Previous::Block(previous) => Some(
+                self.block_by_hash(previous)
+                    .await?
+                    .ok_or_else(|| anyhow!("Previous block {:?} not found", previous))?,
+            ),
+            Previous::Open => None,
+        };
+        let previous_balance = previous
+            .as_ref()
+            .map(|p| p.balance().to_owned())
+            .unwrap_or_else(Raw::zero);
+        let epoch_link = match head.block_type() {
+            BlockType::State => self.network.epoch_for_link(head.link()),
+            _ => None,
+        };
+        let subtype = match head.block_type() {
+            BlockType::Send => Subtype::Send,
+            BlockType::Receive => Subtype::Receive,
+            BlockType::Open => Subtype::Open,
+            BlockType::Change => Subtype::Change,
+            _ => state_subtype(head, &previous_balance, epoch_link.is_some()),
+        };
+
+        let mut state = self.state.lock().await;
+        // The account goes back to the epoch it had before `head`, whether `head` is an epoch
+        // block or a receive that upgraded it.
+        let epoch = match previous.as_ref() {
+            Some(previous) => state.block_epoch(previous.hash()?).await?,
+            None => Epoch::Epoch0,
+        };
+        let mut change = LedgerChange {
+            epoch: Some(epoch),
+            weights: WeightChange::for_block(head, previous.as_ref())
+                .iter()
+                .map(WeightChange::inverse)
+                .collect(),
+            ..Default::default()
+        };
+        match subtype {
+            Subtype::Send => {
+                let destination = Public::try_from(head.link().as_bytes())?;
+                if state.receivable(&destination, hash).await?.is_none() {
+                    drop(state);
+                    let receive = self.receive_of(&destination, hash).await?;
+                    return Ok(Some(receive));
+                }
+                change.receivable = ReceivableChange::Remove {
+                    destination,
+                    send_hash: hash.to_owned(),
+                };
+            }
+            Subtype::Receive | Subtype::Open => {
+                let send_hash = BlockHash::try_from(head.link().as_bytes())?;
+                let source = state
+                    .account_for_block_hash(&send_hash)
+                    .await?
+                    .ok_or_else(|| anyhow!("Source block {:?} not found", send_hash))?;
+                let amount = head
+                    .balance()
+                    .checked_sub(&previous_balance)
+                    .ok_or_else(|| anyhow!("Receive {:?} lowers the balance", hash))?;
+                let epoch = state.block_epoch(&send_hash).await?;
+                change.receivable = ReceivableChange::Add {
+                    destination: account.to_owned(),
+                    send_hash,
+                    receivable: Receivable {
+                        amount,
+                        source,
+                        epoch,
+                    },
+                };
+            }
+            Subtype::Change | Subtype::Epoch => {}
+        }
+        state.remove_block(head, &change).await?;
+
+        Ok(None)
+    }
+
+    /// The block in `destination`'s chain that received `send_hash`.
+    async fn receive_of(
+        &self,
+        destination: &Public,
+        send_hash: &BlockHash,
+    ) -> anyhow::Result<BlockHash> {
+        let mut block = self.get_latest_block(destination).await?;
+        while let Some(current) = block {
+            if current.link().as_bytes() == send_hash.as_bytes() {
+                return Ok(current.hash()?.to_owned());
+            }
+            block = match current.previous() {
+                Previous::Block(previous) => self.block_by_hash(previous).await?,
+                Previous::Open => None,
+            };
+        }
+        Err(anyhow!(
+            "Send {:?} is neither receivable nor received by {:?}",
+            send_hash,
+            destination
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::Link;
+    use crate::node::peer::test_blocks::{previous, state_block, NETWORK};
+    use crate::node::state::MemoryState;
+    use crate::Private;
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    #[tokio::test]
+    async fn rollback_removes_dependents() {
+        let state = Arc::new(Mutex::new(MemoryState::new(NETWORK)));
+        let (mut peer, _, _) =
+            Peer::new_with_channels(NETWORK, state, SocketAddr::from_str("[::1]:1").unwrap());
+        let key = Private::random();
+        let account = key.to_public().unwrap();
+        let open = state_block(&key, Previous::Open, 1000, Link::Nothing);
+        peer.add_unvalidated_block(&open).await.unwrap();
+
+        let other = Private::random();
+        let other_public = other.to_public().unwrap();
+        let send = state_block(
+            &key,
+            previous(&open),
+            700,
+            Link::DestinationAccount(other_public.to_owned()),
+        );
+        peer.add_elected_block(&send).await.unwrap();
+        let send_hash = send.hash().unwrap().to_owned();
+        let receive = state_block(
+            &other,
+            Previous::Open,
+            300,
+            Link::Source(send_hash.to_owned()),
+        );
+        peer.add_elected_block(&receive).await.unwrap();
+        assert_eq!(
+            peer.representative_weight(&other_public).await.unwrap(),
+            Raw::from(300)
+        );
+
+        // The receive depends on the send, so it goes first.
+        let rolled_back = peer.rollback(&send_hash).await.unwrap();
+        assert_eq!(rolled_back, vec![receive.clone(), send.clone()]);
+        assert!(peer.block_by_hash(&send_hash).await.unwrap().is_none());
+        assert!(peer
+            .block_by_hash(receive.hash().unwrap())
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            peer.get_latest_block(&account).await.unwrap(),
+            Some(open.clone())
+        );
+        assert!(peer
+            .get_latest_block(&other_public)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(peer
+            .state
+            .lock()
+            .await
+            .receivable(&other_public, &send_hash)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            peer.representative_weight(&account).await.unwrap(),
+            Raw::from(1000)
+        );
+        assert_eq!(
+            peer.representative_weight(&other_public).await.unwrap(),
+            Raw::zero()
+        );
+
+        // Another send for the same root fits the ledger again.
+        let fork = state_block(&key, previous(&open), 600, Link::Nothing);
+        peer.add_confirmed_block(&fork).await.unwrap();
+        assert_eq!(peer.get_latest_block(&account).await.unwrap(), Some(fork));
+    }
+}
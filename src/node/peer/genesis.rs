@@ -1,4 +1,5 @@
 use crate::node::peer::Peer;
+use crate::node::state::ConfirmationHeight;
 
 use anyhow::Context;
 use tracing::info;
@@ -6,12 +7,28 @@ use tracing::info;
 impl Peer {
     pub async fn ensure_genesis(&mut self) -> anyhow::Result<()> {
         info!("Ensuring genesis");
-        let mut block = self.network.genesis_block();
+        let block = self.network.genesis_block();
+        if self.block_by_hash(block.hash()?).await?.is_some() {
+            return Ok(());
+        }
 
-        self.add_elected_block(&mut block)
+        // The genesis block creates the supply, so there's no ledger to validate it against.
+        self.add_unvalidated_block(&block)
             .await
             .context("Adding genesis block")?;
 
+        // Nothing can replace the genesis block, so it starts out cemented.
+        let confirmation_height = ConfirmationHeight {
+            height: 1,
+            frontier: block.hash()?.to_owned(),
+        };
+        self.state
+            .lock()
+            .await
+            .set_confirmation_height(block.account(), &confirmation_height)
+            .await
+            .context("Cementing genesis block")?;
+
         Ok(())
     }
 }
@@ -0,0 +1,791 @@
+use crate::blocks::{Block, BlockHash, BlockType, Epoch, Link, Previous, Subtype};
+use crate::node::elections::Root;
+use crate::node::peer::Peer;
+use crate::node::state::{LedgerChange, Receivable, ReceivableChange, WeightChange};
+use crate::{Difficulty, Public, Raw};
+use anyhow::anyhow;
+use std::convert::TryFrom;
+
+/// Why a block can't be added to the ledger. Attached to the error from [Peer::validate_block].
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum BlockRejection {
+    #[error("Block already exists")]
+    Old,
+
+    #[error("Invalid signature")]
+    BadSignature,
+
+    #[error("Work is missing")]
+    MissingWork,
+
+    #[error("Work difficulty {difficulty:?} is below the threshold {threshold:?}")]
+    InsufficientWork {
+        difficulty: Difficulty,
+        threshold: Difficulty,
+    },
+
+    #[error("{0:?} blocks can't be added to the ledger")]
+    InvalidType(BlockType),
+
+    #[error("Previous block {0:?} is not in the ledger")]
+    GapPrevious(BlockHash),
+
+    #[error("Source block {0:?} is not in the ledger")]
+    GapSource(BlockHash),
+
+    #[error("Another block already exists for {0:?}")]
+    Fork(Root),
+
+    #[error("The previous block belongs to another account")]
+    AccountMismatch,
+
+    #[error("Legacy blocks can't follow a state block")]
+    BlockPosition,
+
+    #[error("A send can't increase the balance")]
+    NegativeSpend,
+
+    #[error("Balance is {actual} but should be {expected}")]
+    BalanceMismatch { expected: Raw, actual: Raw },
+
+    #[error("Source {0:?} is not a send to this account that hasn't been received")]
+    Unreceivable(BlockHash),
+
+    #[error("The account can't be upgraded to {0:?}")]
+    EpochPosition(Epoch),
+
+    #[error("Epoch blocks can't change the representative")]
+    EpochRepresentative,
+}
+
+impl Peer {
+    /// Check that `block` can be added on top of our ledger. Fails with a [BlockRejection] if
+    /// it can't, otherwise returns how adding it changes the ledger.
+    pub async fn validate_block(&self, block: &Block) -> anyhow::Result<LedgerChange> {
+        let hash = block.hash()?;
+        if self.block_by_hash(hash).await?.is_some() {
+            return Err(BlockRejection::Old.into());
+        }
+
+        // Epoch blocks are signed by the epoch signer, which is checked once we know the block
+        // really is an epoch block.
+        let signed_by_account = block.verify_signature(block.account()).is_ok();
+        let epoch_link = match block.block_type() {
+            BlockType::State => self.network.epoch_for_link(block.link()),
+            _ => None,
+        };
+        if !signed_by_account && epoch_link.is_none() {
+            return Err(BlockRejection::BadSignature.into());
+        }
+
+        let previous = self.validate_previous(block).await?;
+        let previous_balance = previous
+            .as_ref()
+            .map(|p| p.balance().to_owned())
+            .unwrap_or_else(Raw::zero);
+
+        let subtype = match block.block_type() {
+            BlockType::State => state_subtype(block, &previous_balance, epoch_link.is_some()),
+            t => legacy_subtype(t)?,
+        };
+
+        if subtype != Subtype::Epoch && !signed_by_account {
+            return Err(BlockRejection::BadSignature.into());
+        }
+        let account_epoch = self
+            .state
+            .lock()
+            .await
+            .account_epoch(block.account())
+            .await?;
+        let (receivable, received_epoch) = self
+            .validate_balance(block, &subtype, &previous_balance, &account_epoch)
+            .await?;
+
+        // Receiving from an account of a later epoch upgrades this one to it.
+        let epoch = match subtype {
+            Subtype::Epoch => self.validate_epoch(block, previous.as_ref()).await?,
+            _ => account_epoch.max(received_epoch),
+        };
+        self.validate_work(block, &subtype, &epoch)?;
+
+        Ok(LedgerChange {
+            receivable,
+            epoch: Some(epoch).filter(|e| e != &account_epoch),
+            weights: WeightChange::for_block(block, previous.as_ref()),
+        })
+    }
+
+    /// Check the balance follows from the previous balance and what the block does, returning
+    /// how the block changes the receivable entries, and the epoch of the send it receives.
+    /// `epoch` is the account's epoch before the block.
+    async fn validate_balance(
+        &self,
+        block: &Block,
+        subtype: &Subtype,
+        previous_balance: &Raw,
+        epoch: &Epoch,
+    ) -> anyhow::Result<(ReceivableChange, Epoch)> {
+        let mut change = ReceivableChange::None;
+        let mut received_epoch = Epoch::Epoch0;
+        let expected = match subtype {
+            Subtype::Send => {
+                let amount = previous_balance
+                    .checked_sub(block.balance())
+                    .ok_or(BlockRejection::NegativeSpend)?;
+                let destination = match block.link() {
+                    Link::DestinationAccount(destination) => destination.to_owned(),
+                    link => Public::try_from(link.as_bytes())?,
+                };
+                let change = ReceivableChange::Add {
+                    destination,
+                    send_hash: block.hash()?.to_owned(),
+                    receivable: Receivable {
+                        amount,
+                        source: block.account().to_owned(),
+                        epoch: *epoch,
+                    },
+                };
+                return Ok((change, received_epoch));
+            }
+            Subtype::Receive | Subtype::Open => {
+                let source = match block.link() {
+                    Link::Source(source) => source.to_owned(),
+                    link => BlockHash::try_from(link.as_bytes())?,
+                };
+                let receivable = self.receivable(block.account(), &source).await?;
+                // Legacy blocks can only receive sends from accounts that haven't been upgraded.
+                if *block.block_type() != BlockType::State && receivable.epoch != Epoch::Epoch0 {
+                    return Err(BlockRejection::Unreceivable(source).into());
+                }
+                received_epoch = receivable.epoch;
+                change = ReceivableChange::Remove {
+                    destination: block.account().to_owned(),
+                    send_hash: source,
+                };
+                previous_balance
+                    .checked_add(&receivable.amount)
+                    .ok_or_else(|| anyhow!("Balance overflow"))?
+            }
+            Subtype::Change | Subtype::Epoch => previous_balance.to_owned(),
+        };
+        if block.balance() != &expected {
+            return Err(BlockRejection::BalanceMismatch {
+                expected,
+                actual: block.balance().to_owned(),
+            }
+            .into());
+        }
+        Ok((change, received_epoch))
+    }
+
+    /// Check the block follows the head of its account, returning the previous block if there is
+    /// one.
+    async fn validate_previous(&self, block: &Block) -> anyhow::Result<Option<Block>> {
+        let account = block.account();
+        let head = self
+            .state
+            .lock()
+            .await
+            .get_latest_block_hash_for_account(account)
+            .await?;
+
+        let previous_hash = match block.previous() {
+            Previous::Open => {
+                return match (block.block_type(), head) {
+                    (_, Some(_)) => {
+                        Err(BlockRejection::Fork(Root::Account(account.to_owned())).into())
+                    }
+                    (BlockType::Open, None) | (BlockType::State, None) => Ok(None),
+                    (t, None) => Err(BlockRejection::InvalidType(t.to_owned()).into()),
+                };
+            }
+            Previous::Block(hash) => hash,
+        };
+        if *block.block_type() == BlockType::Open {
+            return Err(BlockRejection::InvalidType(BlockType::Open).into());
+        }
+
+        let previous = self
+            .block_by_hash(previous_hash)
+            .await?
+            .ok_or_else(|| BlockRejection::GapPrevious(previous_hash.to_owned()))?;
+        if previous.account() != account {
+            return Err(BlockRejection::AccountMismatch.into());
+        }
+        if head.as_ref() != Some(previous_hash) {
+            return Err(BlockRejection::Fork(Root::Previous(previous_hash.to_owned())).into());
+        }
+        if *block.block_type() != BlockType::State && *previous.block_type() == BlockType::State {
+            return Err(BlockRejection::BlockPosition.into());
+        }
+        Ok(Some(previous))
+    }
+
+    /// Check an epoch block is signed by the epoch signer, upgrades the account by one epoch and
+    /// leaves the representative alone. Returns the epoch it upgrades to.
+    async fn validate_epoch(
+        &self,
+        block: &Block,
+        previous: Option<&Block>,
+    ) -> anyhow::Result<Epoch> {
+        let epoch = self
+            .network
+            .epoch_for_link(block.link())
+            .ok_or_else(|| anyhow!("Not an epoch link"))?;
+        let signer = self
+            .network
+            .epoch_signer(&epoch)
+            .ok_or(BlockRejection::BadSignature)?;
+        if block.verify_signature(&signer).is_err() {
+            return Err(BlockRejection::BadSignature.into());
+        }
+
+        let state = self.state.lock().await;
+        let current = state.account_epoch(block.account()).await?;
+        if current.next() != Some(epoch) {
+            return Err(BlockRejection::EpochPosition(epoch).into());
+        }
+        match previous {
+            Some(previous) => {
+                if previous.representative() != block.representative() {
+                    return Err(BlockRejection::EpochRepresentative.into());
+                }
+            }
+            None => {
+                // An account can only be opened by an epoch block when it has something to
+                // receive, and it has no representative yet.
+                if block.representative() != &Public::zero() {
+                    return Err(BlockRejection::EpochRepresentative.into());
+                }
+                if state.receivables(block.account(), 1).await?.is_empty() {
+                    return Err(BlockRejection::EpochPosition(epoch).into());
+                }
+            }
+        }
+        Ok(epoch)
+    }
+
+    pub(crate) fn validate_work(
+        &self,
+        block: &Block,
+        subtype: &Subtype,
+        epoch: &Epoch,
+    ) -> anyhow::Result<()> {
+        let work = block.work().ok_or(BlockRejection::MissingWork)?;
+        let thresholds = self.network.work_thresholds();
+        let threshold = match block.block_type() {
+            BlockType::State => thresholds.threshold(epoch, subtype),
+            _ => thresholds.epoch_1,
+        };
+        let difficulty = work.difficulty(&block.work_subject())?;
+        if difficulty < threshold {
+            return Err(BlockRejection::InsufficientWork {
+                difficulty,
+                threshold,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// What `account` can receive from the send block `source`.
+    async fn receivable(&self, account: &Public, source: &BlockHash) -> anyhow::Result<Receivable> {
+        let receivable = self.state.lock().await.receivable(account, source).await?;
+        if let Some(receivable) = receivable {
+            return Ok(receivable);
+        }
+        Err(match self.block_by_hash(source).await? {
+            Some(_) => BlockRejection::Unreceivable(source.to_owned()),
+            None => BlockRejection::GapSource(source.to_owned()),
+        }
+        .into())
+    }
+}
+
+/// What a legacy block does, which its type says.
+pub(crate) fn legacy_subtype(block_type: &BlockType) -> anyhow::Result<Subtype> {
+    Ok(match block_type {
+        BlockType::Send => Subtype::Send,
+        BlockType::Receive => Subtype::Receive,
+        BlockType::Open => Subtype::Open,
+        BlockType::Change => Subtype::Change,
+        t => return Err(BlockRejection::InvalidType(t.to_owned()).into()),
+    })
+}
+
+/// What a state block does, from the change in balance and its link.
+pub(crate) fn state_subtype(block: &Block, previous_balance: &Raw, is_epoch_link: bool) -> Subtype {
+    if block.balance() < previous_balance {
+        Subtype::Send
+    } else if is_epoch_link && block.balance() == previous_balance {
+        Subtype::Epoch
+    } else if block.link().as_bytes().iter().all(|&b| b == 0) {
+        Subtype::Change
+    } else if *block.previous() == Previous::Open {
+        Subtype::Open
+    } else {
+        Subtype::Receive
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{BlockHolder, ValidationState};
+    use crate::node::peer::test_blocks::{finish, legacy_block, previous, state_block, NETWORK};
+    use crate::node::state::MemoryState;
+    use crate::node::Wire;
+    use crate::{Private, Work};
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    fn rejection<T: std::fmt::Debug>(result: anyhow::Result<T>) -> BlockRejection {
+        result
+            .unwrap_err()
+            .downcast::<BlockRejection>()
+            .expect("Not a rejection")
+    }
+
+    /// A peer with an account holding 1000 raw, standing in for a genesis block.
+    async fn peer_with_funds() -> (Peer, Private, Block) {
+        let state = Arc::new(Mutex::new(MemoryState::new(NETWORK)));
+        let (peer, _, _) =
+            Peer::new_with_channels(NETWORK, state, SocketAddr::from_str("[::1]:1").unwrap());
+        let key = Private::random();
+        let open = state_block(&key, Previous::Open, 1000, Link::Nothing);
+        peer.add_unvalidated_block(&open).await.unwrap();
+        (peer, key, open)
+    }
+
+    #[tokio::test]
+    async fn send_and_receive() {
+        let (mut peer, key, open) = peer_with_funds().await;
+        let other = Private::random();
+        let other_public = other.to_public().unwrap();
+
+        let send = state_block(
+            &key,
+            previous(&open),
+            700,
+            Link::DestinationAccount(other_public.to_owned()),
+        );
+        let mut tampered = send.clone();
+        tampered.set_work(Work::zero());
+        assert!(matches!(
+            rejection(peer.validate_block(&tampered).await),
+            BlockRejection::InsufficientWork { .. }
+        ));
+        peer.add_elected_block(&send).await.unwrap();
+        assert_eq!(
+            rejection(peer.validate_block(&send).await),
+            BlockRejection::Old
+        );
+
+        // Another block on the same previous.
+        let fork = state_block(&key, previous(&open), 600, Link::Nothing);
+        assert_eq!(
+            rejection(peer.validate_block(&fork).await),
+            BlockRejection::Fork(Root::Previous(open.hash().unwrap().to_owned()))
+        );
+
+        let send_hash = send.hash().unwrap().to_owned();
+        let state = peer.state.clone();
+        let receivable = || async {
+            state
+                .lock()
+                .await
+                .receivable(&other_public, &send_hash)
+                .await
+                .unwrap()
+        };
+        assert_eq!(
+            receivable().await,
+            Some(Receivable {
+                amount: Raw::from(300),
+                source: key.to_public().unwrap(),
+                epoch: Epoch::Epoch0,
+            })
+        );
+
+        let source = Link::Source(send.hash().unwrap().to_owned());
+        let greedy = state_block(&other, Previous::Open, 301, source.clone());
+        assert_eq!(
+            rejection(peer.validate_block(&greedy).await),
+            BlockRejection::BalanceMismatch {
+                expected: Raw::from(300),
+                actual: Raw::from(301)
+            }
+        );
+        let receive = state_block(&other, Previous::Open, 300, source.clone());
+        peer.add_elected_block(&receive).await.unwrap();
+        assert_eq!(peer.account_balance(&other_public).await.unwrap(), 300);
+        assert_eq!(receivable().await, None);
+
+        // The send can only be received once.
+        let again = state_block(&other, previous(&receive), 600, source);
+        assert_eq!(
+            rejection(peer.validate_block(&again).await),
+            BlockRejection::Unreceivable(send.hash().unwrap().to_owned())
+        );
+
+        // Only the destination can receive.
+        let thief = state_block(
+            &key,
+            previous(&send),
+            1000,
+            Link::Source(send.hash().unwrap().to_owned()),
+        );
+        assert_eq!(
+            rejection(peer.validate_block(&thief).await),
+            BlockRejection::Unreceivable(send.hash().unwrap().to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn change() {
+        let (mut peer, key, open) = peer_with_funds().await;
+        let rep = Private::random().to_public().unwrap();
+        let mut change = Block::new(
+            BlockType::State,
+            key.to_public().unwrap(),
+            previous(&open),
+            rep.to_owned(),
+            Raw::from(1000),
+            Link::Nothing,
+            ValidationState::Valid,
+        );
+        change = finish(change, &key, &NETWORK.work_thresholds().epoch_1);
+        peer.add_elected_block(&change).await.unwrap();
+        assert_eq!(peer.representative_weight(&rep).await.unwrap(), 1000);
+        assert_eq!(
+            peer.representative_weight(open.representative())
+                .await
+                .unwrap(),
+            0
+        );
+
+        // A legacy block can't follow a state block.
+        let legacy = Block::new(
+            BlockType::Change,
+            key.to_public().unwrap(),
+            previous(&change),
+            rep,
+            Raw::from(1000),
+            Link::Nothing,
+            ValidationState::Valid,
+        );
+        let legacy = finish(legacy, &key, &NETWORK.work_thresholds().epoch_1);
+        assert_eq!(
+            rejection(peer.validate_block(&legacy).await),
+            BlockRejection::BlockPosition
+        );
+    }
+
+    #[tokio::test]
+    async fn gaps_and_signatures() {
+        let (peer, key, _) = peer_with_funds().await;
+        let missing =
+            BlockHash::from_str("991CF190094C00F0B68E2E5F75F6BEE95A2E0BD93CEAA4A6734DB9F19B728948")
+                .unwrap();
+        let gap = state_block(
+            &key,
+            Previous::Block(missing.to_owned()),
+            1000,
+            Link::Nothing,
+        );
+        assert_eq!(
+            rejection(peer.validate_block(&gap).await),
+            BlockRejection::GapPrevious(missing.to_owned())
+        );
+
+        let other = Private::random();
+        let gap_source = state_block(&other, Previous::Open, 1, Link::Source(missing.to_owned()));
+        assert_eq!(
+            rejection(peer.validate_block(&gap_source).await),
+            BlockRejection::GapSource(missing)
+        );
+
+        let mut forged = state_block(&other, Previous::Open, 1, Link::Nothing);
+        forged.sign(Private::random()).unwrap();
+        assert_eq!(
+            rejection(peer.validate_block(&forged).await),
+            BlockRejection::BadSignature
+        );
+    }
+
+    /// Send the block through its wire format and back, like a published block.
+    async fn over_the_wire(peer: &Peer, block: &Block) -> Block {
+        let holder = block.to_holder().unwrap();
+        let data = Wire::serialize(&holder);
+        assert_eq!(
+            data.len(),
+            BlockHolder::len_for_type(block.block_type()).unwrap()
+        );
+        let decoded = BlockHolder::deserialize_typed(block.block_type(), &data).unwrap();
+        assert_eq!(decoded, holder);
+        let decoded = peer.block_from_holder(decoded).await.unwrap();
+        assert_eq!(&decoded, block);
+        decoded
+    }
+
+    #[tokio::test]
+    async fn legacy_chain() {
+        let state = Arc::new(Mutex::new(MemoryState::new(NETWORK)));
+        let (mut peer, _, _) =
+            Peer::new_with_channels(NETWORK, state, SocketAddr::from_str("[::1]:1").unwrap());
+        let key = Private::random();
+        let public = key.to_public().unwrap();
+        let open = legacy_block(
+            BlockType::Open,
+            &key,
+            Previous::Open,
+            public.to_owned(),
+            1000,
+            Link::Source(BlockHash::zero()),
+        );
+        peer.add_unvalidated_block(&open).await.unwrap();
+
+        let other = Private::random();
+        let other_public = other.to_public().unwrap();
+        let destination = Link::DestinationAccount(other_public.to_owned());
+        let send = legacy_block(
+            BlockType::Send,
+            &key,
+            previous(&open),
+            public.to_owned(),
+            700,
+            destination.clone(),
+        );
+        peer.add_elected_block(&over_the_wire(&peer, &send).await)
+            .await
+            .unwrap();
+
+        let other_open = legacy_block(
+            BlockType::Open,
+            &other,
+            Previous::Open,
+            other_public.to_owned(),
+            300,
+            Link::Source(send.hash().unwrap().to_owned()),
+        );
+        peer.add_elected_block(&over_the_wire(&peer, &other_open).await)
+            .await
+            .unwrap();
+
+        let send_again = legacy_block(
+            BlockType::Send,
+            &key,
+            previous(&send),
+            public.to_owned(),
+            500,
+            destination,
+        );
+        peer.add_elected_block(&over_the_wire(&peer, &send_again).await)
+            .await
+            .unwrap();
+
+        let receive = legacy_block(
+            BlockType::Receive,
+            &other,
+            previous(&other_open),
+            other_public.to_owned(),
+            500,
+            Link::Source(send_again.hash().unwrap().to_owned()),
+        );
+        peer.add_elected_block(&over_the_wire(&peer, &receive).await)
+            .await
+            .unwrap();
+        assert_eq!(peer.account_balance(&other_public).await.unwrap(), 500);
+
+        let rep = Private::random().to_public().unwrap();
+        let change = legacy_block(
+            BlockType::Change,
+            &other,
+            previous(&receive),
+            rep.to_owned(),
+            500,
+            Link::Nothing,
+        );
+        peer.add_elected_block(&over_the_wire(&peer, &change).await)
+            .await
+            .unwrap();
+        assert_eq!(peer.representative_weight(&rep).await.unwrap(), 500);
+        assert_eq!(peer.representative_weight(&public).await.unwrap(), 500);
+        assert_eq!(peer.representative_weight(&other_public).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn epochs() {
+        let (mut peer, key, open) = peer_with_funds().await;
+        let account = key.to_public().unwrap();
+        let signer = Private::from_str(crate::network::TEST_GENESIS_KEY).unwrap();
+        let thresholds = NETWORK.work_thresholds();
+        let epoch_block = |previous: &Block, representative: &Public, epoch: &Epoch| {
+            Block::new(
+                BlockType::State,
+                account.to_owned(),
+                Previous::Block(previous.hash().unwrap().to_owned()),
+                representative.to_owned(),
+                Raw::from(1000),
+                NETWORK.epoch_link(epoch).unwrap(),
+                ValidationState::Valid,
+            )
+        };
+        let epoch_of = |peer: &Peer| {
+            let state = peer.state.clone();
+            let account = account.to_owned();
+            async move { state.lock().await.account_epoch(&account).await.unwrap() }
+        };
+
+        let skipped = epoch_block(&open, &account, &Epoch::Epoch2);
+        let skipped = finish(skipped, &signer, &thresholds.epoch_2);
+        assert_eq!(
+            rejection(peer.validate_block(&skipped).await),
+            BlockRejection::EpochPosition(Epoch::Epoch2)
+        );
+
+        let by_owner = epoch_block(&open, &account, &Epoch::Epoch1);
+        let by_owner = finish(by_owner, &key, &thresholds.epoch_1);
+        assert_eq!(
+            rejection(peer.validate_block(&by_owner).await),
+            BlockRejection::BadSignature
+        );
+
+        let new_rep = epoch_block(&open, &Public::zero(), &Epoch::Epoch1);
+        let new_rep = finish(new_rep, &signer, &thresholds.epoch_1);
+        assert_eq!(
+            rejection(peer.validate_block(&new_rep).await),
+            BlockRejection::EpochRepresentative
+        );
+
+        let epoch_1 = finish(
+            epoch_block(&open, &account, &Epoch::Epoch1),
+            &signer,
+            &thresholds.epoch_1,
+        );
+        peer.add_elected_block(&epoch_1).await.unwrap();
+        assert_eq!(epoch_of(&peer).await, Epoch::Epoch1);
+
+        let epoch_2 = finish(
+            epoch_block(&epoch_1, &account, &Epoch::Epoch2),
+            &signer,
+            &thresholds.epoch_2,
+        );
+        peer.add_elected_block(&epoch_2).await.unwrap();
+        assert_eq!(epoch_of(&peer).await, Epoch::Epoch2);
+        assert_eq!(
+            peer.representative_weight(&account).await.unwrap(),
+            Raw::from(1000)
+        );
+
+        // Epoch 2 accounts need more work for a send.
+        let mut send = state_block(&key, previous(&epoch_2), 900, Link::Nothing);
+        send.set_work(Work::zero());
+        assert_eq!(
+            rejection(peer.validate_block(&send).await),
+            BlockRejection::InsufficientWork {
+                difficulty: Work::zero().difficulty(&send.work_subject()).unwrap(),
+                threshold: thresholds.epoch_2,
+            }
+        );
+
+        // Nothing to receive, so an epoch block can't open the account.
+        let other = Private::random().to_public().unwrap();
+        let mut epoch_open = Block::new(
+            BlockType::State,
+            other,
+            Previous::Open,
+            Public::zero(),
+            Raw::zero(),
+            NETWORK.epoch_link(&Epoch::Epoch1).unwrap(),
+            ValidationState::Valid,
+        );
+        epoch_open = finish(epoch_open, &signer, &thresholds.epoch_1);
+        assert_eq!(
+            rejection(peer.validate_block(&epoch_open).await),
+            BlockRejection::EpochPosition(Epoch::Epoch1)
+        );
+    }
+
+    #[tokio::test]
+    async fn open_from_epoch_2_send() {
+        let (mut peer, key, open) = peer_with_funds().await;
+        let account = key.to_public().unwrap();
+        let signer = Private::from_str(crate::network::TEST_GENESIS_KEY).unwrap();
+        let thresholds = NETWORK.work_thresholds();
+        let mut head = open;
+        for (epoch, threshold) in [
+            (Epoch::Epoch1, &thresholds.epoch_1),
+            (Epoch::Epoch2, &thresholds.epoch_2),
+        ] {
+            let upgrade = Block::new(
+                BlockType::State,
+                account.to_owned(),
+                previous(&head),
+                account.to_owned(),
+                Raw::from(1000),
+                NETWORK.epoch_link(&epoch).unwrap(),
+                ValidationState::Valid,
+            );
+            head = finish(upgrade, &signer, threshold);
+            peer.add_elected_block(&head).await.unwrap();
+        }
+
+        let other = Private::random();
+        let other_public = other.to_public().unwrap();
+        let mut send = Block::new(
+            BlockType::State,
+            account.to_owned(),
+            previous(&head),
+            account.to_owned(),
+            Raw::from(600),
+            Link::DestinationAccount(other_public.to_owned()),
+            ValidationState::Valid,
+        );
+        send = finish(send, &key, &thresholds.epoch_2);
+        peer.add_elected_block(&send).await.unwrap();
+        let source = Link::Source(send.hash().unwrap().to_owned());
+
+        // A legacy open can't receive from an upgraded account.
+        let legacy = legacy_block(
+            BlockType::Open,
+            &other,
+            Previous::Open,
+            other_public.to_owned(),
+            400,
+            source.clone(),
+        );
+        assert_eq!(
+            rejection(peer.validate_block(&legacy).await),
+            BlockRejection::Unreceivable(send.hash().unwrap().to_owned())
+        );
+
+        // The open is an epoch 2 receive, so it only needs the lower receive threshold.
+        let mut other_open = Block::new(
+            BlockType::State,
+            other_public.to_owned(),
+            Previous::Open,
+            other_public.to_owned(),
+            Raw::from(400),
+            source,
+            ValidationState::Valid,
+        );
+        other_open = finish(other_open, &other, &thresholds.epoch_2_receive);
+        let change = peer.validate_block(&other_open).await.unwrap();
+        assert_eq!(change.epoch, Some(Epoch::Epoch2));
+        peer.add_elected_block(&other_open).await.unwrap();
+
+        let state = peer.state.lock().await;
+        assert_eq!(
+            state.account_epoch(&other_public).await.unwrap(),
+            Epoch::Epoch2
+        );
+        assert_eq!(
+            state.block_epoch(other_open.hash().unwrap()).await.unwrap(),
+            Epoch::Epoch2
+        );
+    }
+}
@@ -1,40 +1,54 @@
+use super::ledger::legacy_subtype;
 use super::Peer;
-use crate::blocks::{Block, BlockHash, BlockHolder, BlockType, Link, Previous, StateBlock};
+use crate::blocks::{
+    Block, BlockHash, BlockHolder, BlockType, Epoch, Link, Previous, StateBlock, Subtype,
+};
+use crate::node::command::NodeCommand;
 use crate::node::cookie::Cookie;
 use crate::node::header::{Extensions, Header, MessageType};
 use crate::node::messages::confirm_ack::ConfirmAck;
 use crate::node::messages::confirm_req::ConfirmReq;
-use crate::node::messages::frontier_req::FrontierReq;
-use crate::node::messages::frontier_resp::FrontierResp;
-use crate::node::messages::handshake::{Handshake, HandshakeQuery, HandshakeResponse};
+use crate::node::messages::handshake::{Handshake, HandshakeQuery, HandshakeResponse, HandshakeV2};
 use crate::node::messages::keepalive::Keepalive;
 use crate::node::messages::publish::Publish;
-use crate::node::messages::telemetry_ack::TelemetryAck;
+use crate::node::messages::telemetry_ack::{TelemetryAck, MAKER};
 use crate::node::messages::telemetry_req::TelemetryReq;
-use crate::{Difficulty, Public, Seed, Signature};
+use crate::node::peer_info::PeerInfo;
+use crate::node::peer_manager::InvalidMessage;
+use crate::node::wire::Wire;
+use crate::version::Version;
 use anyhow::anyhow;
 use anyhow::Context;
+use rand::seq::IteratorRandom;
 use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::{debug, info, instrument, trace, warn};
 
 impl Peer {
     #[instrument(skip(self))]
     pub async fn send_handshake(&mut self) -> anyhow::Result<()> {
         trace!("Sending handshake");
-        self.send_header(MessageType::Handshake, *Extensions::new().query())
+        // Older peers ignore the v2 flag and answer with a v1 response.
+        self.send_header(MessageType::Handshake, *Extensions::new().query().v2())
             .await?;
 
-        // TODO: Track our own cookie?
+        let handshake_query = self.new_handshake_query().await?;
+        self.send(&handshake_query).await?;
+
+        Ok(())
+    }
+
+    /// Create a new cookie for this peer, which they will need to sign in their response.
+    async fn new_handshake_query(&mut self) -> anyhow::Result<HandshakeQuery> {
         let cookie = Cookie::random();
         self.state
             .lock()
             .await
             .set_cookie(self.peer_addr, cookie.clone())
             .await?;
-        let handshake_query = HandshakeQuery::new(cookie);
-        self.send(&handshake_query).await?;
-
-        Ok(())
+        self.sent_handshake_query = true;
+        Ok(HandshakeQuery::new(cookie))
     }
 
     #[instrument(skip(self, header, handshake))]
@@ -45,7 +59,7 @@ impl Peer {
     ) -> anyhow::Result<()> {
         enum ShouldRespond {
             No,
-            Yes(Public, Signature),
+            Yes(HandshakeResponse),
         }
 
         let mut should_respond = ShouldRespond::No;
@@ -53,18 +67,25 @@ impl Peer {
             // This would probably be a programming error if it panicked.
             let query = handshake.query.expect("query is None but is_query is True");
 
-            // XXX: Hacky code here just to see if it works!
-            // TODO: Move into state
-            let seed = Seed::random();
-            let private = seed.derive(0);
-            let public = private.to_public()?;
-            let signature = private.sign(query.cookie().as_bytes())?;
-            public
-                .verify(query.cookie().as_bytes(), &signature)
-                .context("Verify recv handshake signature.")?;
+            // Only answer in the v2 format when asked to, and the peer's version has it.
+            let v2 = if header.ext().is_v2()
+                && self.version.is_some_and(|v| v.supports_handshake_v2())
+            {
+                Some(HandshakeV2::new(self.network.genesis_hash()))
+            } else {
+                None
+            };
+
+            let public = self.node_key.to_public()?;
+            let signature = self
+                .node_key
+                .sign(&HandshakeResponse::signed_data(query.cookie(), v2.as_ref()))?;
 
             // Respond at the end because we mess with the header buffer.
-            should_respond = ShouldRespond::Yes(public, signature);
+            should_respond = ShouldRespond::Yes(match v2 {
+                Some(v2) => HandshakeResponse::new_v2(public, signature, v2),
+                None => HandshakeResponse::new(public, signature),
+            });
         }
 
         if header.ext().is_response() {
@@ -90,19 +111,56 @@ impl Peer {
             }
             let cookie = cookie.as_ref().unwrap();
 
+            if let Some(v2) = &response.v2 {
+                let genesis = self.network.genesis_hash();
+                if v2.genesis != genesis {
+                    return Err(anyhow!(
+                        "Peer follows genesis {:?}, but ours is {:?}",
+                        v2.genesis,
+                        genesis
+                    ))
+                    .context(InvalidMessage);
+                }
+            }
+
             if self.validate_handshakes {
                 public
-                    .verify(&cookie.as_bytes(), &signature)
+                    .verify(
+                        &HandshakeResponse::signed_data(cookie, response.v2.as_ref()),
+                        &signature,
+                    )
                     .context("Invalid signature in handshake response")?;
             }
+
+            if let Some(peer_manager) = &self.peer_manager {
+                peer_manager
+                    .lock()
+                    .await
+                    .identified(&self.peer_addr, public, header);
+            }
         }
 
-        if let ShouldRespond::Yes(public, signature) = should_respond {
-            self.send_header(MessageType::Handshake, *Extensions::new().response())
-                .await?;
+        if let ShouldRespond::Yes(response) = should_respond {
+            // If they connected to us, we haven't asked them for their node ID yet, so we
+            // send our query along with the response. The v2 flag covers both: a v2 response,
+            // and asking for one in return.
+            let mut ext = *Extensions::new().response();
+            if response.v2.is_some() {
+                ext.v2();
+            }
+            let query = if self.sent_handshake_query {
+                None
+            } else {
+                ext.query();
+                Some(self.new_handshake_query().await?)
+            };
+            self.send_header(MessageType::Handshake, ext).await?;
 
-            let response = HandshakeResponse::new(public, signature);
-            self.send(&response)
+            let handshake = Handshake {
+                query,
+                response: Some(response),
+            };
+            self.send(&handshake)
                 .await
                 .context("Could not send response to peer.")?;
         }
@@ -110,32 +168,166 @@ impl Peer {
         Ok(())
     }
 
+    /// Send a random selection of our known peers, excluding the one we're talking to.
+    #[instrument(skip(self))]
+    pub async fn send_keepalive(&mut self) -> anyhow::Result<()> {
+        let peers = self.state.lock().await.peers().await?;
+        let peers: Vec<PeerInfo> = peers
+            .into_iter()
+            .filter(|addr| addr != &self.peer_addr)
+            .choose_multiple(&mut rand::thread_rng(), Keepalive::PEERS)
+            .into_iter()
+            .map(PeerInfo::from)
+            .collect();
+
+        self.send_header(MessageType::Keepalive, Extensions::new())
+            .await?;
+        self.send(&Keepalive::new(peers)).await?;
+        Ok(())
+    }
+
+    /// Remember the peers we were told about and ask the node to connect to them.
     #[instrument(skip(self, _header, keepalive))]
     pub async fn handle_keepalive(
         &mut self,
         _header: &Header,
         keepalive: Keepalive,
     ) -> anyhow::Result<()> {
-        // dbg!(keepalive);
         debug!("{:?}", keepalive);
+        let addresses: Vec<SocketAddr> = keepalive
+            .peers()
+            .iter()
+            .map(|p| p.socket_addr())
+            .filter(|addr| !addr.ip().is_unspecified() && addr.port() != 0)
+            .collect();
+        if addresses.is_empty() {
+            return Ok(());
+        }
+
+        self.state.lock().await.add_peers(&addresses).await?;
+        if let Some(node_tx) = &self.node_tx {
+            node_tx
+                .send(NodeCommand::ConnectPeers(addresses))
+                .await
+                .context("Sending peers to node")?;
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    pub async fn send_telemetry_req(&mut self) -> anyhow::Result<()> {
+        trace!("Sending telemetry request");
+        self.send_header(MessageType::TelemetryReq, Extensions::new())
+            .await?;
+        self.send(&TelemetryReq).await?;
         Ok(())
     }
 
+    /// Answer with our own signed telemetry.
     pub async fn handle_telemetry_req(
         &mut self,
         _header: &Header,
         _telemetry_req: TelemetryReq,
     ) -> anyhow::Result<()> {
-        // dbg!(telemetry_req);
+        let telemetry_ack = self.telemetry().await?;
+        let data = telemetry_ack.serialize();
+        self.send_header(
+            MessageType::TelemetryAck,
+            *Extensions::new().set_telemetry_size(data.len()),
+        )
+        .await?;
+        self.send(&telemetry_ack).await?;
         Ok(())
     }
 
+    /// Verify the telemetry is signed by the node ID the peer gave us in the handshake, then
+    /// store it. An empty ack is ignored.
     pub async fn handle_telemetry_ack(
         &mut self,
-        _header: &Header,
-        _telemetry_ack: TelemetryAck,
+        header: &Header,
+        telemetry_ack: TelemetryAck,
     ) -> anyhow::Result<()> {
-        // dbg!(telemetry_ack);
+        if header.ext().telemetry_size() == 0 {
+            debug!("Peer {:?} has no telemetry", self.peer_addr);
+            return Ok(());
+        }
+        telemetry_ack.verify().context(InvalidMessage)?;
+        if telemetry_ack.genesis_block != self.network.genesis_hash() {
+            return Err(anyhow!(
+                "Telemetry genesis block mismatch: {:?}",
+                telemetry_ack.genesis_block
+            ))
+            .context(InvalidMessage);
+        }
+
+        let peer_manager = match &self.peer_manager {
+            Some(peer_manager) => peer_manager,
+            None => return Ok(()),
+        };
+        let mut peer_manager = peer_manager.lock().await;
+        if let Some(node_id) = peer_manager
+            .status(&self.peer_addr)
+            .and_then(|status| status.node_id.as_ref())
+        {
+            if node_id != telemetry_ack.node_id() {
+                return Err(anyhow!(
+                    "Telemetry from {:?} but the handshake was from {:?}",
+                    telemetry_ack.node_id(),
+                    node_id
+                ))
+                .context(InvalidMessage);
+            }
+        }
+        peer_manager.set_telemetry(&self.peer_addr, telemetry_ack);
+        Ok(())
+    }
+
+    /// Our telemetry, signed with our node ID.
+    async fn telemetry(&self) -> anyhow::Result<TelemetryAck> {
+        let mut telemetry = TelemetryAck::new(self.network.genesis_hash());
+        {
+            let state = self.state.lock().await;
+            telemetry.block_count = state.block_count().await?;
+            telemetry.cemented_count = state.cemented_count().await?;
+            telemetry.account_count = state.account_count().await?;
+            if self.peer_manager.is_none() {
+                telemetry.peer_count = state.peers().await?.len() as u32;
+            }
+        }
+        if let Some(peer_manager) = &self.peer_manager {
+            telemetry.peer_count = peer_manager.lock().await.connected_peers().count() as u32;
+        }
+        if let Some(unchecked) = &self.unchecked {
+            let mut unchecked = unchecked.lock().await;
+            unchecked.expire(std::time::Instant::now());
+            telemetry.unchecked_count = unchecked.len() as u64;
+        }
+
+        telemetry.protocol_version = self.version.unwrap_or(Version::MAX) as u8;
+        telemetry.uptime = self.node_started.elapsed().as_secs();
+        telemetry.major_version = env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap_or(0);
+        telemetry.minor_version = env!("CARGO_PKG_VERSION_MINOR").parse().unwrap_or(0);
+        telemetry.patch_version = env!("CARGO_PKG_VERSION_PATCH").parse().unwrap_or(0);
+        telemetry.maker = MAKER;
+        telemetry.timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        telemetry.active_difficulty = self
+            .network
+            .work_thresholds()
+            .threshold(&Epoch::Epoch2, &Subtype::Send)
+            .as_u64();
+        telemetry.sign(&self.node_key)?;
+        Ok(telemetry)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn send_publish(&mut self, block: &Block) -> anyhow::Result<()> {
+        let publish = Publish::new(block.to_holder()?);
+        self.send_header(MessageType::Publish, publish.extensions())
+            .await?;
+        self.send(&publish).await?;
         Ok(())
     }
 
@@ -144,18 +336,38 @@ impl Peer {
         _header: &Header,
         publish: Publish,
     ) -> anyhow::Result<()> {
-        let _block = match publish.0 {
-            BlockHolder::Send(_) => {
-                todo!("Received a send block")
+        let payload = publish.serialize();
+        let result = self.handle_block(publish.0).await;
+        if result.is_err() {
+            // Let the block through again, from this peer or another.
+            if let Some(filter) = &self.duplicate_filter {
+                filter.lock().await.clear(&payload);
             }
-            BlockHolder::Receive(_) => {
-                todo!("Received a receive block")
-            }
-            BlockHolder::Open(_) => {
-                todo!("Received an open block")
+        }
+        result
+    }
+
+    /// Handle a block from a publish, or one that was waiting for a block it depends on.
+    pub(crate) async fn handle_block(&mut self, holder: BlockHolder) -> anyhow::Result<()> {
+        if let Err(err) = self.precheck(&holder) {
+            info!("Ignoring block {:?}: {:?}", holder.hash(), err);
+            return Ok(());
+        }
+        if self.queue_unchecked(&holder).await? {
+            return Ok(());
+        }
+
+        match holder {
+            holder @ BlockHolder::Send(_)
+            | holder @ BlockHolder::Open(_)
+            | holder @ BlockHolder::Receive(_)
+            | holder @ BlockHolder::Change(_) => {
+                self.legacy_block_handler(holder).await?;
             }
-            BlockHolder::Change(_) => {
-                todo!("Received a change block")
+            BlockHolder::State(state_block)
+                if self.network.epoch_for_link(&state_block.link).is_some() =>
+            {
+                self.epoch_block_handler(state_block).await?;
             }
             BlockHolder::State(state_block) => {
                 self.state_block_handler(state_block).await?;
@@ -165,43 +377,114 @@ impl Peer {
         Ok(())
     }
 
-    pub async fn handle_confirm_req(
-        &mut self,
-        _header: &Header,
-        _confirm_req: ConfirmReq,
-    ) -> anyhow::Result<()> {
-        // dbg!(confirm_req);
+    /// Validate a legacy block against our ledger and store it.
+    async fn legacy_block_handler(&mut self, holder: BlockHolder) -> anyhow::Result<()> {
+        let block = match self.block_from_holder(holder).await {
+            Ok(block) => block,
+            Err(err) => {
+                info!("Ignoring published block: {:?}", err);
+                return Ok(());
+            }
+        };
+        let hash = block.hash()?;
+        if self.block_existed(hash).await? {
+            info!("Block {:?} already exists!", hash)
+        } else if block.verify_signature(block.account()).is_err() {
+            info!("Block {:?} has invalid signature!", hash)
+        } else if let Err(err) = legacy_subtype(block.block_type())
+            // Legacy blocks are only ever in epoch 0 accounts.
+            .and_then(|subtype| self.validate_work(&block, &subtype, &Epoch::Epoch0))
+        {
+            info!("Block {:?} has invalid work: {:?}", hash, err)
+        } else {
+            self.store_block(&block).await?
+        }
         Ok(())
     }
 
-    pub async fn handle_confirm_ack(
-        &mut self,
-        _header: &Header,
-        _confirm_ack: ConfirmAck,
-    ) -> anyhow::Result<()> {
-        // dbg!(confirm_ack);
+    /// Epoch blocks aren't signed by the account, so they are checked in full against our
+    /// ledger before being stored.
+    async fn epoch_block_handler(&mut self, state_block: StateBlock) -> anyhow::Result<()> {
+        let block = self
+            .block_from_holder(BlockHolder::State(state_block))
+            .await;
+        let block = match block {
+            Ok(block) => block,
+            Err(err) => {
+                info!("Ignoring published epoch block: {:?}", err);
+                return Ok(());
+            }
+        };
+        if let Err(err) = self.validate_block(&block).await {
+            info!("Epoch block {:?} is invalid: {:?}", block.hash()?, err);
+            return Ok(());
+        }
+        if self.elections.is_some() {
+            self.store_block(&block).await
+        } else {
+            self.add_elected_block(&block).await?;
+            self.flood_block(&block).await
+        }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn send_confirm_req(&mut self, confirm_req: &ConfirmReq) -> anyhow::Result<()> {
+        self.send_header(MessageType::ConfirmReq, confirm_req.extensions())
+            .await?;
+        self.send(confirm_req).await?;
         Ok(())
     }
 
-    pub async fn handle_frontier_req(
+    /// Vote for the requested blocks if we're a representative.
+    pub async fn handle_confirm_req(
         &mut self,
         _header: &Header,
-        _frontier_req: FrontierReq,
+        confirm_req: ConfirmReq,
     ) -> anyhow::Result<()> {
-        // The rest of this connection will be a bunch of frontiers without any headers.
-        self.frontier_stream = true;
+        let voter = match &self.voter {
+            Some(voter) => voter.clone(),
+            None => return Ok(()),
+        };
+
+        let requested = match confirm_req {
+            ConfirmReq::ConfirmReqByHash(pairs) => pairs
+                .into_iter()
+                .map(|pair| (pair.hash, Some(pair.root)))
+                .collect(),
+            ConfirmReq::BlockSelector(block) => vec![(block.hash(), None)],
+        };
+        let mut candidates = vec![];
+        for (hash, root) in requested {
+            match self.vote_candidate(&hash, root.as_ref()).await? {
+                Some(candidate) => candidates.push(candidate),
+                None => debug!("Not voting for unknown block {:?}", hash),
+            }
+        }
+        if candidates.is_empty() {
+            return Ok(());
+        }
+
+        let votes = voter.lock().await.votes(candidates)?;
+        for vote in &votes {
+            self.send_confirm_ack(vote).await?;
+        }
+        Ok(())
+    }
 
+    #[instrument(skip(self))]
+    pub async fn send_confirm_ack(&mut self, confirm_ack: &ConfirmAck) -> anyhow::Result<()> {
+        self.send_header(MessageType::ConfirmAck, confirm_ack.extensions())
+            .await?;
+        self.send(confirm_ack).await?;
         Ok(())
     }
 
-    pub async fn handle_frontier_resp(
+    pub async fn handle_confirm_ack(
         &mut self,
-        _frontier_resp: FrontierResp,
+        _header: &Header,
+        confirm_ack: ConfirmAck,
     ) -> anyhow::Result<()> {
-        // dbg!(frontier_resp);
-        // dbg!("----------------------------------------------------------------------");
-
-        Ok(())
+        self.queue_vote(confirm_ack).await
     }
 
     /// Returns the previous block if is a head block AND is a state_block
@@ -239,14 +522,16 @@ impl Peer {
     }
 
     /// Shorthand for waiting a lock on the state and getting a block by hash
-    async fn block_by_hash(&self, block_hash: &BlockHash) -> anyhow::Result<Option<Block>> {
+    pub(crate) async fn block_by_hash(
+        &self,
+        block_hash: &BlockHash,
+    ) -> anyhow::Result<Option<Block>> {
         self.state.lock().await.get_block_by_hash(block_hash).await
     }
 
-    /// Actions to be performed to validate and store a state block
-    /// TODO: this assumes we will never get a live epoch block
-    async fn state_block_handler(&self, state_block: StateBlock) -> anyhow::Result<()> {
-        // TODO: here there should be a check for epoch blocks
+    /// Actions to be performed to validate and store a state block. Epoch blocks are handled by
+    /// [Self::epoch_block_handler] instead.
+    async fn state_block_handler(&mut self, state_block: StateBlock) -> anyhow::Result<()> {
         if self.block_existed(&state_block.hash).await? {
             info!("Block {} already exists!", state_block)
         } else if state_block.verify_self_signature().is_err() {
@@ -258,7 +543,7 @@ impl Peer {
     }
 
     async fn process_valid_existing_state_block(
-        &self,
+        &mut self,
         state_block: StateBlock,
     ) -> anyhow::Result<()> {
         match &state_block.previous {
@@ -273,14 +558,15 @@ impl Peer {
                 }
             }
             Previous::Open => {
-                todo!("Received an open sub-block")
+                self.process_good_sub_block(state_block, Subtype::Open)
+                    .await?
             }
         }
         Ok(())
     }
 
     async fn process_block_with_previous(
-        &self,
+        &mut self,
         mut state_block: StateBlock,
         previous_state_block: StateBlock,
     ) -> anyhow::Result<()> {
@@ -300,12 +586,12 @@ impl Peer {
             .context("Could not decide link type!")?;
         match state_block.link {
             Link::Nothing => {
-                let _live_epoch_2_change_threshold = 0xfffffff800000000u64;
-                todo!("Received a change sub-block")
+                self.process_good_sub_block(state_block, Subtype::Change)
+                    .await
             }
             Link::Source(_) => {
-                let _live_epoch_2_receive_threshold = 0xfffffe0000000000u64;
-                todo!("Received a receive sub-block")
+                self.process_good_sub_block(state_block, Subtype::Receive)
+                    .await
             }
             Link::DestinationAccount(_) => self.process_good_send_sub_block(state_block).await,
             Link::Unsure(_) => {
@@ -314,37 +600,67 @@ impl Peer {
         }
     }
 
-    async fn process_good_send_sub_block(&self, send_block: StateBlock) -> anyhow::Result<()> {
-        let live_epoch_2_send_threshold = 0xfffffff800000000u64;
-        let block_difficulty = send_block
+    async fn process_good_send_sub_block(&mut self, send_block: StateBlock) -> anyhow::Result<()> {
+        self.process_good_sub_block(send_block, Subtype::Send).await
+    }
+
+    /// Store a state block if it has enough work for its subtype. The threshold can depend on
+    /// the epoch the block upgrades its account to, so receives and epoch blocks only need the
+    /// lowest one here. The ledger checks the exact threshold once the block is confirmed.
+    async fn process_good_sub_block(
+        &mut self,
+        state_block: StateBlock,
+        subtype: Subtype,
+    ) -> anyhow::Result<()> {
+        let thresholds = self.network.work_thresholds();
+        let threshold = match subtype {
+            Subtype::Receive | Subtype::Open | Subtype::Epoch => thresholds.epoch_2_receive,
+            _ => {
+                let epoch = self
+                    .state
+                    .lock()
+                    .await
+                    .account_epoch(&state_block.account)
+                    .await?;
+                thresholds.threshold(&epoch, &subtype)
+            }
+        };
+        let block_difficulty = state_block
             .work
             .as_ref()
-            .ok_or(anyhow!("Send sub-block {} has no work!", &send_block))?
-            .difficulty_block_hash(&send_block.hash)?;
-        let work_ok = block_difficulty >= Difficulty::new(live_epoch_2_send_threshold);
-        if !work_ok {
-            info!("Send sub-block {} has insufficient difficulty!", send_block);
+            .ok_or_else(|| anyhow!("{:?} sub-block {} has no work!", subtype, &state_block))?
+            .difficulty_block_hash(&state_block.hash)?;
+        if block_difficulty < threshold {
+            info!(
+                "{:?} sub-block {} has insufficient difficulty!",
+                subtype, state_block
+            );
             debug!(
-                "Send sub-block {} had difficulty {}",
-                send_block,
+                "{:?} sub-block {} had difficulty {}",
+                subtype,
+                state_block,
                 block_difficulty.as_u64()
             );
         } else {
-            self.store_block(&Block::from_state_block(&send_block))
+            self.store_block(&Block::from_state_block(&state_block))
                 .await?
-            // TODO: Update rep weight cache
-            // TODO: Add to pending transactions
         }
         Ok(())
     }
 
-    async fn store_block(&self, block: &Block) -> anyhow::Result<()> {
+    async fn store_block(&mut self, block: &Block) -> anyhow::Result<()> {
         // 1. if this block already exists, this operation is idempotent (but incurs in resource waste)
         // 2. if this block was added and rolled back this could generate an invalid state
         // 3. if we got a rollback request for this block and it didn't go through because it was missing
         //    this could generate an invalid state
         // 4. ???
-        self.state.lock().await.add_block(block).await
+        if self.elections.is_some() {
+            self.elect_block(block).await?;
+        } else {
+            self.add_unvalidated_block(block).await?;
+            self.arrived.push(block.hash()?.to_owned());
+        }
+        self.flood_block(block).await
     }
 
     /// Checks if the block exists in the database _or_ if it existed but was pruned
@@ -370,9 +686,9 @@ impl Peer {
 mod tests {
     use super::*;
     use crate::blocks::{Link, Previous, StateBlock};
-    use crate::network::Network;
-    use crate::node::state::State;
+    use crate::node::peer::test_blocks::{previous, NETWORK};
     use crate::node::MemoryState;
+    use crate::Public;
     use crate::{Raw, Work};
     use std::net::SocketAddr;
     use std::str::FromStr;
@@ -404,7 +720,7 @@ mod tests {
         );
         let mut frontier = StateBlock::new(
             root_block.account().clone(),
-            Previous::Block(root_block.hash().unwrap().clone()),
+            previous(&root_block),
             root_block.representative().clone(),
             root_block.balance().checked_sub(&Raw(200)).unwrap(),
             destination,
@@ -426,14 +742,12 @@ mod tests {
     }
 
     async fn test_peer_with_blocks(blocks: &[&Block]) -> Peer {
-        let network = Network::Test;
-        let mut state_raw = MemoryState::new(network);
+        let test_socket_addr = SocketAddr::from_str("[::1]:1").unwrap();
+        let state = Arc::new(Mutex::new(MemoryState::new(NETWORK)));
+        let (peer, _, _) = Peer::new_with_channels(NETWORK, state, test_socket_addr);
         for block in blocks {
-            state_raw.add_block(*block).await.unwrap();
+            peer.add_unvalidated_block(block).await.unwrap();
         }
-        let test_socket_addr = SocketAddr::from_str("[::1]:1").unwrap();
-        let state = Arc::new(Mutex::new(state_raw));
-        let (peer, _, _) = Peer::new_with_channels(network, state, test_socket_addr);
         peer
     }
 
@@ -464,9 +778,10 @@ mod tests {
 
     #[tokio::test]
     async fn should_retrieve_previous_as_account() {
+        let (_, root_block) = root_block();
         let (_, frontier_block) = frontier_block();
         let frontier = StateBlock::from(frontier_block.clone());
-        let peer = test_peer_with_blocks(&[&frontier_block]).await;
+        let peer = test_peer_with_blocks(&[&root_block, &frontier_block]).await;
 
         let frontier_result = Peer::previous_as_account_info(&peer, &frontier.hash)
             .await
@@ -477,11 +792,12 @@ mod tests {
 
     #[tokio::test]
     async fn should_process_good_send_sub_block_when_block_is_good() {
-        let peer = test_peer_with_blocks(&[]).await;
+        let (_, root_block) = root_block();
+        let mut peer = test_peer_with_blocks(&[&root_block]).await;
         let good_send_block = good_send_block();
         let good_send_block_hash = good_send_block.hash.clone();
 
-        Peer::process_good_send_sub_block(&peer, good_send_block)
+        Peer::process_good_send_sub_block(&mut peer, good_send_block)
             .await
             .unwrap();
 
@@ -493,11 +809,11 @@ mod tests {
 
     #[tokio::test]
     async fn should_not_process_bad_send_sub_block_when_block_is_bad() {
-        let peer = test_peer_with_blocks(&[]).await;
+        let mut peer = test_peer_with_blocks(&[]).await;
         let bad_send_block = bad_send_block();
         let bad_send_block_hash = bad_send_block.hash.clone();
 
-        Peer::process_good_send_sub_block(&peer, bad_send_block)
+        Peer::process_good_send_sub_block(&mut peer, bad_send_block)
             .await
             .unwrap();
 
@@ -511,9 +827,9 @@ mod tests {
     async fn should_process_send_with_previous() {
         let (root, root_block) = root_block();
         let (frontier, _) = frontier_block();
-        let peer = test_peer_with_blocks(&[&root_block]).await;
+        let mut peer = test_peer_with_blocks(&[&root_block]).await;
 
-        Peer::process_block_with_previous(&peer, frontier.clone(), root)
+        Peer::process_block_with_previous(&mut peer, frontier.clone(), root)
             .await
             .unwrap();
 
@@ -524,9 +840,9 @@ mod tests {
     #[tokio::test]
     async fn should_not_process_send_without_previous() {
         let (frontier, _) = frontier_block();
-        let peer = test_peer_with_blocks(&[]).await;
+        let mut peer = test_peer_with_blocks(&[]).await;
 
-        Peer::process_valid_existing_state_block(&peer, frontier.clone())
+        Peer::process_valid_existing_state_block(&mut peer, frontier.clone())
             .await
             .unwrap();
 
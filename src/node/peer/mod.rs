@@ -1,20 +1,40 @@
 mod blocks;
+mod bootstrap;
+mod cementing;
+mod forks;
 mod genesis;
+mod ledger;
 mod messages;
+#[cfg(test)]
+mod test_blocks;
 
-use crate::blocks::Block;
+use crate::blocks::{Block, BlockHash, BlockHolder};
 use crate::encoding::to_hex;
 use crate::network::Network;
+use crate::node::command::NodeCommandSender;
+use crate::node::duplicate_filter::ArcDuplicateFilter;
+use crate::node::elections::ArcElections;
 use crate::node::header::{Extensions, Header, MessageType};
+use crate::node::messages::confirm_ack::ConfirmAck;
+use crate::node::messages::publish::Publish;
+use crate::node::peer_manager::{ArcPeerManager, InvalidMessage};
 use crate::node::state::ArcState;
+use crate::node::unchecked::ArcUnchecked;
+use crate::node::voter::ArcVoter;
 use crate::node::wire::Wire;
-use crate::{Public, Raw};
+use crate::version::Version;
+use crate::{Private, Public, Raw};
 use anyhow::{anyhow, Context};
 use std::fmt::Debug;
 use std::net::SocketAddr;
+use std::time::Duration;
 use tokio::sync::mpsc;
+use tokio::time::{interval_at, Instant};
 use tracing::{debug, info, instrument, trace};
 
+pub use bootstrap::Pull;
+pub(crate) use ledger::state_subtype;
+
 /// A message sent between channels that contains a peer's network data.
 #[derive(Debug)]
 pub struct Packet {
@@ -49,20 +69,93 @@ enum RecvState {
     Payload(Header),
 }
 
+/// How often we send a keepalive containing some of our known peers.
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often we ask the peer for its telemetry.
+pub const TELEMETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Votes are verified once this many are queued...
+pub const VOTE_BATCH_SIZE: usize = 64;
+
+/// ...or when this much time has passed, whichever comes first.
+pub const VOTE_BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
 /// Handles the logic of one peer. It handles and emits messages, as well as time
 /// based actions, management of other peers, etc.
 pub struct Peer {
     /// Disable when used for pcap dump, where might have our own different cookie.
     pub validate_handshakes: bool,
 
+    /// Send a handshake query as soon as we start. This is what the side that opened the
+    /// connection does. The accepting side waits for the query and answers it.
+    pub initiate_handshake: bool,
+
+    /// Have we sent our own cookie to this peer yet?
+    sent_handshake_query: bool,
+
+    /// Used to ask the node to connect to peers learned from keepalives. Without it, learned
+    /// peers are only stored.
+    pub node_tx: Option<NodeCommandSender>,
+
+    /// Told about the node ID and version of this peer once the handshake completes, and
+    /// stores the telemetry it sends.
+    pub peer_manager: Option<ArcPeerManager>,
+
+    /// Blocks from this peer are voted on here before they are added to the ledger. Without it,
+    /// blocks are added as soon as they are received.
+    pub elections: Option<ArcElections>,
+
+    /// Publishes and votes already received, from this or any other peer, are dropped before
+    /// they are deserialized.
+    pub duplicate_filter: Option<ArcDuplicateFilter>,
+
+    /// Blocks waiting for a missing previous or source block, handled again once it's added to
+    /// the ledger. Without it, such blocks are dropped.
+    pub unchecked: Option<ArcUnchecked>,
+
+    /// Blocks added to the ledger since waiting blocks were last retried.
+    arrived: Vec<BlockHash>,
+
+    /// Our node ID. Signs handshake responses and telemetry.
+    pub node_key: Private,
+
+    /// Answers confirm requests with votes. Without it, confirm requests are ignored.
+    pub voter: Option<ArcVoter>,
+
+    /// When our node started, to report uptime in telemetry.
+    pub node_started: std::time::Instant,
+
     network: Network,
     state: ArcState,
     peer_addr: SocketAddr,
     recv_state: RecvState,
 
-    /// Are we doing a frontier req stream? (Bootstrap?)
+    /// Negotiated from the latest header the peer sent. Decides which message formats we use.
+    version: Option<Version>,
+
+    /// Use this connection to bootstrap from the peer, instead of a handshake and live traffic.
+    pub bootstrap: bool,
+
+    /// Are we receiving a frontier stream? Set after sending a frontier req.
     frontier_stream: bool,
 
+    /// Account chains to pull, found from the frontier stream.
+    pulls: Vec<Pull>,
+
+    /// The chain being pulled, while receiving a bulk pull stream.
+    current_pull: Option<Pull>,
+
+    /// Blocks received so far for `current_pull`, newest first.
+    pulled_blocks: Vec<BlockHolder>,
+
+    /// The rest of pulled chains that receive from a send we didn't have yet, newest first,
+    /// retried once every chain has been pulled.
+    gap_pulls: Vec<(Pull, Vec<BlockHolder>)>,
+
+    /// Votes waiting for their signatures to be verified in a batch.
+    pending_votes: Vec<ConfirmAck>,
+
     /// Internal buffer for incoming data.
     incoming_buffer: Vec<u8>,
 
@@ -88,11 +181,29 @@ impl Peer {
 
         let s = Self {
             validate_handshakes: true,
+            initiate_handshake: true,
+            sent_handshake_query: false,
+            node_tx: None,
+            peer_manager: None,
+            elections: None,
+            duplicate_filter: None,
+            unchecked: None,
+            arrived: vec![],
+            node_key: Private::random(),
+            voter: None,
+            node_started: std::time::Instant::now(),
             network,
             state,
             peer_addr,
             recv_state: RecvState::Header,
+            version: None,
+            bootstrap: false,
             frontier_stream: false,
+            pulls: vec![],
+            current_pull: None,
+            pulled_blocks: vec![],
+            gap_pulls: vec![],
+            pending_votes: vec![],
             incoming_buffer: Vec::with_capacity(10_000),
             peer_rx: incoming_rx,
             peer_tx: outgoing_tx,
@@ -106,15 +217,33 @@ impl Peer {
     /// is closed.
     #[instrument(name = "node", skip(self), fields(address = %self.peer_addr))]
     pub async fn run(mut self) -> anyhow::Result<()> {
-        trace!("Initial handshake");
-        self.send_handshake().await?;
+        if self.bootstrap {
+            trace!("Bootstrap");
+            self.send_frontier_req().await?;
+        } else if self.initiate_handshake {
+            trace!("Initial handshake");
+            self.send_handshake().await?;
+        }
 
-        // TODO: Send and handle telemetry
-        // trace!("Initial telemetry request");
-        // self.send_telemetry_req().await?;
+        let mut keepalive = interval_at(Instant::now() + KEEPALIVE_INTERVAL, KEEPALIVE_INTERVAL);
+        let mut telemetry = interval_at(Instant::now() + TELEMETRY_INTERVAL, TELEMETRY_INTERVAL);
+        let mut vote_batch = interval_at(Instant::now() + VOTE_BATCH_INTERVAL, VOTE_BATCH_INTERVAL);
+        loop {
+            tokio::select! {
+                packet = self.peer_rx.recv() => match packet {
+                    Some(packet) => self.handle_packet(packet).await?,
+                    None => break,
+                },
+                _ = keepalive.tick(), if !self.bootstrap => self.send_keepalive().await?,
+                _ = telemetry.tick(), if !self.bootstrap => self.send_telemetry_req().await?,
+                _ = vote_batch.tick(), if !self.pending_votes.is_empty() => self.process_votes().await?,
+            }
+            self.retry_unchecked().await?;
 
-        while let Some(packet) = self.peer_rx.recv().await {
-            self.handle_packet(packet).await?;
+            if self.bootstrap && self.bootstrap_finished() {
+                debug!("Bootstrap finished");
+                break;
+            }
         }
         trace!("Disconnecting peer");
 
@@ -142,9 +271,11 @@ impl Peer {
                         .$fun(&$header, payload)
                         .await
                         .with_context(|| format!("Handling payload for {:?}", $header))?;
+                    true
                 } else {
+                    false
                 }
-            };};
+            }};
         }
 
         if let Some(annotation) = packet.annotation {
@@ -152,17 +283,22 @@ impl Peer {
         }
         self.incoming_buffer.extend(packet.data);
 
-        // TODO: Handle frontier stream
-        // if self.frontier_stream {
-        //     let payload = self.recv::<FrontierResp>(None).await?;
-        //     self.handle_frontier_resp(payload).await?;
-        // } else {
+        if self.in_bootstrap_stream() {
+            self.recv_bootstrap_stream().await?;
+            if self.in_bootstrap_stream() {
+                // Waiting for more of the stream.
+                return Ok(());
+            }
+        }
 
         loop {
             let (new_state, process) = match self.recv_state {
                 RecvState::Header => {
                     if let Some(header) = self.recv::<Header>(None)? {
-                        header.validate(&self.network)?;
+                        header.validate(&self.network).context(InvalidMessage)?;
+                        // A peer with versions we can't talk to isn't misbehaving, so this only
+                        // disconnects it, without a ban.
+                        self.version = Some(header.negotiate()?);
                         (RecvState::Payload(header), true)
                     } else {
                         (RecvState::Header, false)
@@ -173,21 +309,35 @@ impl Peer {
                         "Attempt to handle message of type: {:?}",
                         header.message_type()
                     );
-                    match header.message_type() {
-                        MessageType::Keepalive => handle!(self, handle_keepalive, header),
-                        MessageType::Publish => handle!(self, handle_publish, header),
-                        MessageType::ConfirmReq => handle!(self, handle_confirm_req, header),
-                        MessageType::ConfirmAck => handle!(self, handle_confirm_ack, header),
-                        MessageType::FrontierReq => handle!(self, handle_frontier_req, header),
-                        MessageType::Handshake => handle!(self, handle_handshake, header),
-                        MessageType::TelemetryReq => handle!(self, handle_telemetry_req, header),
-                        MessageType::TelemetryAck => handle!(self, handle_telemetry_ack, header),
-                        // MessageType::BulkPull => {}
-                        // MessageType::BulkPush => {}
-                        // MessageType::BulkPullAccount => {}
-                        _ => return Err(anyhow!("Unhandled message: {:?}", header)),
-                    };
-                    (RecvState::Header, false)
+                    if self.skip_duplicate(&header).await? {
+                        (RecvState::Header, true)
+                    } else {
+                        let handled = match header.message_type() {
+                            MessageType::Keepalive => handle!(self, handle_keepalive, header),
+                            MessageType::Publish => handle!(self, handle_publish, header),
+                            MessageType::ConfirmReq => handle!(self, handle_confirm_req, header),
+                            MessageType::ConfirmAck => handle!(self, handle_confirm_ack, header),
+                            MessageType::FrontierReq => handle!(self, handle_frontier_req, header),
+                            MessageType::Handshake => handle!(self, handle_handshake, header),
+                            MessageType::TelemetryReq => {
+                                handle!(self, handle_telemetry_req, header)
+                            }
+                            MessageType::TelemetryAck => {
+                                handle!(self, handle_telemetry_ack, header)
+                            }
+                            MessageType::BulkPull => handle!(self, handle_bulk_pull, header),
+                            // MessageType::BulkPush => {}
+                            // MessageType::BulkPullAccount => {}
+                            _ => return Err(anyhow!("Unhandled message: {:?}", header)),
+                        };
+                        if handled {
+                            // There might be another message in the buffer.
+                            (RecvState::Header, true)
+                        } else {
+                            // Wait for the rest of the payload.
+                            (RecvState::Payload(header), false)
+                        }
+                    }
                 }
             };
             self.recv_state = new_state;
@@ -199,6 +349,31 @@ impl Peer {
         Ok(())
     }
 
+    /// Drop the payload of a publish or vote if it's been seen recently, returning true. False if
+    /// it should be handled, or if the whole payload hasn't arrived yet.
+    async fn skip_duplicate(&mut self, header: &Header) -> anyhow::Result<bool> {
+        let filter = match &self.duplicate_filter {
+            Some(filter) => filter,
+            None => return Ok(false),
+        };
+        let len = match header.message_type() {
+            MessageType::Publish => Publish::len(Some(header)),
+            MessageType::ConfirmAck => ConfirmAck::len(Some(header)),
+            _ => return Ok(false),
+        }
+        .context(InvalidMessage)?;
+        if self.incoming_buffer.len() < len {
+            return Ok(false);
+        }
+
+        if !filter.lock().await.apply(&self.incoming_buffer[0..len]) {
+            return Ok(false);
+        }
+        trace!("Dropping duplicate {:?}", header.message_type());
+        self.recv_immediate(len)?;
+        Ok(true)
+    }
+
     /// Receive from the incoming buffer for type `T`. Will return None if there aren't enough
     /// bytes available.
     #[instrument(skip(self, header))]
@@ -216,7 +391,7 @@ impl Peer {
         let buffer = self.incoming_buffer[0..bytes].to_owned();
         self.incoming_buffer = Vec::from(&self.incoming_buffer[bytes..]);
         trace!("HEX: {}", to_hex(&buffer));
-        let result = T::deserialize(header, &buffer)?;
+        let result = T::deserialize(header, &buffer).context(InvalidMessage)?;
         Ok(Some(result))
     }
 
@@ -250,22 +425,48 @@ impl Peer {
         message_type: MessageType,
         ext: Extensions,
     ) -> anyhow::Result<()> {
-        let header = Header::new(self.network, message_type, ext);
+        let mut header = Header::new(self.network, message_type, ext);
+        if let Some(version) = self.version {
+            header.set_version_using(version);
+        }
         trace!("{:?}", header);
         Ok(self.send(&header).await.context("Sending header")?)
     }
 
+    /// Packets sent here are sent to the peer.
+    pub fn sender(&self) -> mpsc::Sender<Packet> {
+        self.peer_tx.clone()
+    }
+
+    /// Relay a block we've accepted to some of our other peers.
+    async fn flood_block(&self, block: &Block) -> anyhow::Result<()> {
+        let peer_manager = match &self.peer_manager {
+            Some(peer_manager) => peer_manager,
+            None => return Ok(()),
+        };
+        let publish = Publish::new(block.to_holder()?);
+        let mut data =
+            Header::new(self.network, MessageType::Publish, publish.extensions()).serialize();
+        data.extend(publish.serialize());
+
+        let targets = peer_manager.lock().await.flood_targets(&self.peer_addr);
+        debug!("Flooding {:?} to {} peers", block.hash()?, targets.len());
+        for target in targets {
+            // Don't wait for a slow peer. It will get the block from someone else, or
+            // bootstrap it later.
+            if target.try_send(Packet::new(data.clone())).is_err() {
+                debug!("Could not relay block to a peer");
+            }
+        }
+        Ok(())
+    }
+
     /// Set up the genesis block if it hasn't already.
     pub async fn init(&mut self) -> anyhow::Result<()> {
         self.ensure_genesis().await.context("Ensuring genesis")?;
         Ok(())
     }
 
-    /// Update the representative weights based on this block being added to the network.
-    pub async fn balance_rep_weights(&mut self, _full_block: &Block) -> anyhow::Result<()> {
-        todo!()
-    }
-
     pub async fn account_balance(&self, account: &Public) -> anyhow::Result<Raw> {
         let context = || anyhow!("Account balance for {:?}", account);
         let block = self.get_latest_block(account).await.with_context(context)?;
@@ -290,8 +491,9 @@ mod tests {
     use super::*;
     use crate::blocks::{Block, BlockHash, OpenBlock, Previous, SendBlock};
     use crate::network::DEFAULT_PORT;
+    use crate::node::peer::test_blocks::{legacy_block, previous};
     use crate::node::state::MemoryState;
-    use crate::Address;
+    use crate::{Address, Work};
     use std::net::{Ipv4Addr, SocketAddrV4};
     use std::str::FromStr;
     use std::sync::Arc;
@@ -308,6 +510,606 @@ mod tests {
         peer
     }
 
+    #[tokio::test]
+    async fn keepalive_peers_are_learned() {
+        use crate::node::command::NodeCommand;
+        use crate::node::messages::keepalive::Keepalive;
+        use crate::node::peer_info::PeerInfo;
+
+        let mut peer = empty_lattice(Network::Live).await;
+        let (node_tx, mut node_rx) = mpsc::channel(10);
+        peer.node_tx = Some(node_tx);
+
+        let learned = SocketAddr::from_str("1.2.3.4:7075").unwrap();
+        let keepalive = Keepalive::new(vec![
+            PeerInfo::from(learned),
+            PeerInfo::from_str("[::]:0").unwrap(),
+        ]);
+        let header = Header::new(Network::Live, MessageType::Keepalive, Extensions::new());
+        peer.handle_keepalive(&header, keepalive).await.unwrap();
+
+        let peers = peer.state.lock().await.peers().await.unwrap();
+        assert_eq!(peers.len(), 1);
+        assert!(peers.contains(&learned));
+        match node_rx.recv().await.unwrap() {
+            NodeCommand::ConnectPeers(addresses) => assert_eq!(addresses, vec![learned]),
+            cmd => panic!("Unexpected command: {:?}", cmd),
+        }
+    }
+
+    #[tokio::test]
+    async fn header_mismatches() {
+        let network = Network::Live;
+        let header = |network: u8, max: u8, using: u8, min: u8| {
+            Packet::new(vec![0x52, network, max, using, min, 2, 0, 0])
+        };
+
+        // Another network is a bad message, so the peer is banned.
+        let mut peer = empty_lattice(network).await;
+        let result = peer
+            .handle_packet(header(Network::Test.magic(), 20, 20, 18))
+            .await;
+        assert!(result
+            .unwrap_err()
+            .downcast_ref::<InvalidMessage>()
+            .is_some());
+
+        // Versions we can't talk to only end the connection.
+        let mut peer = empty_lattice(network).await;
+        let result = peer
+            .handle_packet(header(network.magic(), 17, 17, 16))
+            .await;
+        let err = result.unwrap_err();
+        assert!(err.downcast_ref::<InvalidMessage>().is_none());
+        assert!(format!("{:?}", err).contains("version mismatch"));
+        assert_eq!(peer.version, None);
+    }
+
+    #[tokio::test]
+    async fn telemetry_exchange() {
+        use crate::node::messages::telemetry_ack::TelemetryAck;
+        use crate::node::messages::telemetry_req::TelemetryReq;
+        use crate::node::peer_manager::PeerManager;
+
+        let network = Network::Live;
+        let addr = SocketAddr::from_str("1.2.3.4:7075").unwrap();
+
+        let state = Arc::new(Mutex::new(MemoryState::new(network)));
+        let (mut server, _tx, mut rx) = Peer::new_with_channels(network, state, addr);
+        server.init().await.unwrap();
+        server.version = Some(Version::V19);
+        let header = Header::new(network, MessageType::TelemetryReq, Extensions::new());
+        server
+            .handle_telemetry_req(&header, TelemetryReq)
+            .await
+            .unwrap();
+
+        let header = Header::deserialize(None, &rx.recv().await.unwrap().data).unwrap();
+        assert_eq!(header.message_type(), MessageType::TelemetryAck);
+        let data = rx.recv().await.unwrap().data;
+        assert_eq!(TelemetryAck::len(Some(&header)).unwrap(), data.len());
+        let ack = TelemetryAck::deserialize(Some(&header), &data).unwrap();
+        assert_eq!(ack.node_id(), &server.node_key.to_public().unwrap());
+        assert_eq!(ack.block_count, 1);
+        assert_eq!(ack.account_count, 1);
+        assert_eq!(ack.protocol_version, Version::V19 as u8);
+
+        let mut client = empty_lattice(network).await;
+        let peer_manager = Arc::new(Mutex::new(PeerManager::default()));
+        peer_manager
+            .lock()
+            .await
+            .try_accept(addr, std::time::Instant::now());
+        client.peer_addr = addr;
+        client.peer_manager = Some(peer_manager.clone());
+        client
+            .handle_telemetry_ack(&header, ack.clone())
+            .await
+            .unwrap();
+        let stored = peer_manager
+            .lock()
+            .await
+            .status(&addr)
+            .unwrap()
+            .telemetry
+            .clone()
+            .unwrap();
+        assert_eq!(stored.node_id(), ack.node_id());
+
+        let mut forged = ack;
+        forged.block_count = 1_000_000;
+        assert!(client.handle_telemetry_ack(&header, forged).await.is_err());
+
+        // A node without telemetry sends an empty ack, which is ignored.
+        let empty = Header::new(network, MessageType::TelemetryAck, Extensions::new());
+        assert_eq!(TelemetryAck::len(Some(&empty)).unwrap(), 0);
+        let ack = TelemetryAck::deserialize(Some(&empty), &[]).unwrap();
+        client.handle_telemetry_ack(&empty, ack).await.unwrap();
+        let status = peer_manager.lock().await.status(&addr).unwrap().to_owned();
+        assert_eq!(status.telemetry.unwrap().node_id(), stored.node_id());
+    }
+
+    #[tokio::test]
+    async fn votes_are_verified() {
+        use crate::node::messages::confirm_ack::{Confirm, ConfirmAck};
+        use crate::node::timestamp::Timestamp;
+        use crate::Signature;
+
+        let network = Network::Live;
+        let hash = network.genesis_hash();
+        let rep = Private::random();
+        let vote = |timestamp: u64| {
+            let mut vote = ConfirmAck::new(
+                Public::zero(),
+                Signature::zero(),
+                Timestamp::from_u64(timestamp),
+                Confirm::VoteByHash(vec![hash.clone()]),
+            );
+            vote.sign(&rep).unwrap();
+            vote
+        };
+
+        let mut peer = empty_lattice(network).await;
+        let mut forged = vote(5);
+        forged.timestamp = Timestamp::from_u64(50);
+        peer.queue_vote(vote(10)).await.unwrap();
+        peer.queue_vote(forged).await.unwrap();
+        peer.queue_vote(vote(u64::MAX - 1)).await.unwrap();
+        peer.process_votes().await.unwrap();
+
+        let rep_public = rep.to_public().unwrap();
+        let state = peer.state.clone();
+        let latest = || async {
+            state
+                .lock()
+                .await
+                .vote_timestamp(&hash, &rep_public)
+                .await
+                .unwrap()
+        };
+        assert_eq!(latest().await, Some(10));
+
+        // Replayed and older votes are ignored.
+        peer.queue_vote(vote(10)).await.unwrap();
+        peer.queue_vote(vote(9)).await.unwrap();
+        peer.queue_vote(vote(11)).await.unwrap();
+        peer.process_votes().await.unwrap();
+        assert_eq!(latest().await, Some(11));
+    }
+
+    #[tokio::test]
+    async fn send_is_added_once_confirmed() {
+        use crate::blocks::{BlockType, Link, ValidationState};
+        use crate::node::elections::{ElectionConfig, ElectionStatus, Elections};
+        use crate::node::messages::confirm_ack::{Confirm, ConfirmAck};
+        use crate::node::messages::confirm_req::{ConfirmReq, RootHashPair};
+        use crate::node::timestamp::Timestamp;
+        use crate::node::voter::Voter;
+        use crate::Signature;
+
+        // Work is quick to generate on the test network.
+        let network = Network::Test;
+        let state = Arc::new(Mutex::new(MemoryState::new(network)));
+        let (mut peer, _tx, mut rx) = Peer::new_with_channels(
+            network,
+            state,
+            SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, DEFAULT_PORT)),
+        );
+        let elections = Arc::new(Mutex::new(Elections::new(ElectionConfig {
+            quorum_percent: 67,
+            online_weight_minimum: Raw::zero(),
+        })));
+        peer.elections = Some(elections.clone());
+        let local_rep = Private::random();
+        peer.voter = Some(Arc::new(Mutex::new(
+            Voter::new(local_rep.to_owned()).unwrap(),
+        )));
+
+        // An account delegating 1000 raw to `rep`.
+        let account = Private::random();
+        let rep = Private::random();
+        let open = Block::new(
+            BlockType::Open,
+            account.to_public().unwrap(),
+            Previous::Open,
+            rep.to_public().unwrap(),
+            Raw::from(1000),
+            Link::Source(BlockHash::zero()),
+            ValidationState::Valid,
+        );
+        peer.add_unvalidated_block(&open).await.unwrap();
+
+        let send = legacy_block(
+            BlockType::Send,
+            &account,
+            previous(&open),
+            rep.to_public().unwrap(),
+            400,
+            Link::DestinationAccount(Public::zero()),
+        );
+        let hash = send.hash().unwrap().to_owned();
+        peer.start_election(send).await.unwrap();
+        assert!(peer.block_by_hash(&hash).await.unwrap().is_none());
+        assert_eq!(
+            elections.lock().await.status(&hash),
+            Some(ElectionStatus::Active)
+        );
+
+        // As a representative, we vote for blocks we're asked about, finally once confirmed.
+        let request = ConfirmReq::ConfirmReqByHash(vec![RootHashPair {
+            hash: hash.clone(),
+            root: open.hash().unwrap().to_owned(),
+        }]);
+        let header = Header::new(network, MessageType::ConfirmReq, request.extensions());
+        async fn next_vote(rx: &mut mpsc::Receiver<Packet>) -> ConfirmAck {
+            let header = Header::deserialize(None, &rx.recv().await.unwrap().data).unwrap();
+            let data = rx.recv().await.unwrap().data;
+            ConfirmAck::deserialize(Some(&header), &data).unwrap()
+        }
+        peer.handle_confirm_req(&header, request.clone())
+            .await
+            .unwrap();
+        let our_vote = next_vote(&mut rx).await;
+        assert_eq!(our_vote.account, local_rep.to_public().unwrap());
+        assert_eq!(our_vote.confirm.hashes(), vec![hash.clone()]);
+        assert!(our_vote.verify_signature().is_ok());
+        assert!(!our_vote.timestamp.is_final());
+
+        let vote = |key: &Private| {
+            let mut vote = ConfirmAck::new(
+                Public::zero(),
+                Signature::zero(),
+                Timestamp::from_u64(1),
+                Confirm::VoteByHash(vec![hash.clone()]),
+            );
+            vote.sign(key).unwrap();
+            vote
+        };
+
+        // A representative without weight can't confirm anything.
+        peer.queue_vote(vote(&Private::random())).await.unwrap();
+        peer.process_votes().await.unwrap();
+        assert!(peer.block_by_hash(&hash).await.unwrap().is_none());
+
+        peer.queue_vote(vote(&rep)).await.unwrap();
+        peer.process_votes().await.unwrap();
+        assert_eq!(
+            elections.lock().await.status(&hash),
+            Some(ElectionStatus::Confirmed)
+        );
+        assert_eq!(
+            peer.block_by_hash(&hash).await.unwrap().unwrap().balance(),
+            &Raw::from(400)
+        );
+
+        peer.handle_confirm_req(&header, request).await.unwrap();
+        let our_vote = next_vote(&mut rx).await;
+        assert_eq!(our_vote.confirm.hashes(), vec![hash.clone()]);
+        assert!(our_vote.timestamp.is_final());
+    }
+
+    #[tokio::test]
+    async fn forks_are_resolved_by_votes() {
+        use crate::blocks::{BlockType, Link, ValidationState};
+        use crate::node::elections::{ElectionConfig, Elections};
+        use crate::node::messages::confirm_ack::{Confirm, ConfirmAck};
+        use crate::node::messages::confirm_req::ConfirmReq;
+        use crate::node::timestamp::Timestamp;
+        use crate::Signature;
+
+        let network = Network::Test;
+        let state = Arc::new(Mutex::new(MemoryState::new(network)));
+        let from = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, DEFAULT_PORT));
+        let (mut peer, _tx, mut rx) = Peer::new_with_channels(network, state, from);
+        let elections = Arc::new(Mutex::new(Elections::new(ElectionConfig {
+            quorum_percent: 67,
+            online_weight_minimum: Raw::zero(),
+        })));
+        peer.elections = Some(elections.clone());
+
+        let account = Private::random();
+        let public = account.to_public().unwrap();
+        let rep = Private::random();
+        let open = Block::new(
+            BlockType::Open,
+            public.to_owned(),
+            Previous::Open,
+            rep.to_public().unwrap(),
+            Raw::from(1000),
+            Link::Source(BlockHash::zero()),
+            ValidationState::Valid,
+        );
+        peer.add_unvalidated_block(&open).await.unwrap();
+
+        let send = |balance: u128| {
+            legacy_block(
+                BlockType::Send,
+                &account,
+                previous(&open),
+                rep.to_public().unwrap(),
+                balance,
+                Link::DestinationAccount(Public::zero()),
+            )
+        };
+        let ours = send(700);
+        let theirs = send(600);
+        peer.add_elected_block(&ours).await.unwrap();
+
+        // The fork puts both blocks up for election, and we ask for votes on them.
+        peer.handle_block(theirs.to_holder().unwrap())
+            .await
+            .unwrap();
+        let election_blocks = elections
+            .lock()
+            .await
+            .election_for_block(theirs.hash().unwrap())
+            .unwrap()
+            .blocks()
+            .count();
+        assert_eq!(election_blocks, 2);
+        let header = Header::deserialize(None, &rx.recv().await.unwrap().data).unwrap();
+        assert_eq!(header.message_type(), MessageType::ConfirmReq);
+        let data = rx.recv().await.unwrap().data;
+        match ConfirmReq::deserialize(Some(&header), &data).unwrap() {
+            ConfirmReq::ConfirmReqByHash(pairs) => {
+                let hashes: Vec<_> = pairs.into_iter().map(|pair| pair.hash).collect();
+                assert_eq!(hashes.len(), 2);
+                assert!(hashes.contains(ours.hash().unwrap()));
+                assert!(hashes.contains(theirs.hash().unwrap()));
+            }
+            req => panic!("Unexpected request: {:?}", req),
+        }
+
+        let mut vote = ConfirmAck::new(
+            Public::zero(),
+            Signature::zero(),
+            Timestamp::from_u64(1),
+            Confirm::VoteByHash(vec![theirs.hash().unwrap().to_owned()]),
+        );
+        vote.sign(&rep).unwrap();
+        peer.queue_vote(vote).await.unwrap();
+        peer.process_votes().await.unwrap();
+
+        assert!(peer
+            .block_by_hash(ours.hash().unwrap())
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(peer.get_latest_block(&public).await.unwrap(), Some(theirs));
+        assert_eq!(
+            peer.representative_weight(&rep.to_public().unwrap())
+                .await
+                .unwrap(),
+            Raw::from(600)
+        );
+    }
+
+    /// A peer with four others to relay to, and an account it has with its key.
+    async fn relaying_peer() -> (
+        Peer,
+        Vec<mpsc::Receiver<Packet>>,
+        mpsc::Receiver<Packet>,
+        Private,
+        Block,
+    ) {
+        use crate::blocks::{BlockType, Link, ValidationState};
+        use crate::node::duplicate_filter::DuplicateFilter;
+        use crate::node::peer_manager::PeerManager;
+
+        let network = Network::Test;
+        let state = Arc::new(Mutex::new(MemoryState::new(network)));
+        let from = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, DEFAULT_PORT));
+        let (mut peer, _rx, from_rx) = Peer::new_with_channels(network, state, from);
+        peer.duplicate_filter = Some(Arc::new(Mutex::new(DuplicateFilter::default())));
+
+        let now = std::time::Instant::now();
+        let mut peer_manager = PeerManager::default();
+        peer_manager.try_accept(from, now);
+        peer_manager.set_sender(&from, peer.sender());
+        let mut receivers = vec![];
+        for i in 1..=4 {
+            let addr = SocketAddr::from_str(&format!("10.0.0.{}:7075", i)).unwrap();
+            peer_manager.try_accept(addr, now);
+            let (tx, rx) = mpsc::channel(10);
+            peer_manager.set_sender(&addr, tx);
+            receivers.push(rx);
+        }
+        peer.peer_manager = Some(Arc::new(Mutex::new(peer_manager)));
+
+        let account = Private::random();
+        let public = account.to_public().unwrap();
+        let open = Block::new(
+            BlockType::Open,
+            public.to_owned(),
+            Previous::Open,
+            public.to_owned(),
+            Raw::from(1000),
+            Link::Source(BlockHash::zero()),
+            ValidationState::Valid,
+        );
+        peer.add_unvalidated_block(&open).await.unwrap();
+        (peer, receivers, from_rx, account, open)
+    }
+
+    fn publish_packet(block: &Block) -> Vec<u8> {
+        let publish = Publish::new(block.to_holder().unwrap());
+        let mut data =
+            Header::new(Network::Test, MessageType::Publish, publish.extensions()).serialize();
+        data.extend(publish.serialize());
+        data
+    }
+
+    /// Dropping the peer closes the channels, so everything that was sent can be collected.
+    async fn relayed(peer: Peer, receivers: &mut [mpsc::Receiver<Packet>]) -> Vec<Packet> {
+        drop(peer);
+        let mut relayed = vec![];
+        for rx in receivers.iter_mut() {
+            while let Some(packet) = rx.recv().await {
+                relayed.push(packet);
+            }
+        }
+        relayed
+    }
+
+    #[tokio::test]
+    async fn published_blocks_are_relayed_once() {
+        use crate::blocks::{BlockType, Link};
+
+        let (mut peer, mut receivers, mut from_rx, account, open) = relaying_peer().await;
+        let send = legacy_block(
+            BlockType::Send,
+            &account,
+            previous(&open),
+            account.to_public().unwrap(),
+            400,
+            Link::DestinationAccount(Public::zero()),
+        );
+
+        let data = publish_packet(&send);
+        for _ in 0..2 {
+            peer.handle_packet(Packet::new(data.clone())).await.unwrap();
+        }
+        assert!(peer
+            .block_by_hash(send.hash().unwrap())
+            .await
+            .unwrap()
+            .is_some());
+
+        // Relayed once, to the square root of the other four peers, in the form we got it.
+        let relayed = relayed(peer, &mut receivers).await;
+        assert_eq!(relayed.len(), 2);
+        assert!(relayed.iter().all(|packet| packet.data == data));
+        assert!(from_rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn published_blocks_without_work_are_dropped() {
+        use crate::blocks::{BlockType, Link, ValidationState};
+
+        let (mut peer, mut receivers, _from_rx, account, open) = relaying_peer().await;
+        let public = account.to_public().unwrap();
+        let mut send = Block::new(
+            BlockType::Send,
+            public.to_owned(),
+            previous(&open),
+            public,
+            Raw::from(400),
+            Link::DestinationAccount(Public::zero()),
+            ValidationState::Valid,
+        );
+        send.sign(account).unwrap();
+
+        let data = publish_packet(&send);
+        peer.handle_packet(Packet::new(data)).await.unwrap();
+        assert!(peer
+            .block_by_hash(send.hash().unwrap())
+            .await
+            .unwrap()
+            .is_none());
+        assert!(relayed(peer, &mut receivers).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn blocks_wait_for_their_previous_block() {
+        use crate::blocks::{BlockType, Link, ValidationState};
+        use crate::node::unchecked::Unchecked;
+
+        let network = Network::Test;
+        let state = Arc::new(Mutex::new(MemoryState::new(network)));
+        let from = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, DEFAULT_PORT));
+        let (mut peer, _rx, _tx) = Peer::new_with_channels(network, state, from);
+        let unchecked = Arc::new(Mutex::new(Unchecked::new()));
+        peer.unchecked = Some(unchecked.clone());
+
+        let account = Private::random();
+        let public = account.to_public().unwrap();
+        let open = Block::new(
+            BlockType::Open,
+            public.to_owned(),
+            Previous::Open,
+            public.to_owned(),
+            Raw::from(1000),
+            Link::Source(BlockHash::zero()),
+            ValidationState::Valid,
+        );
+        peer.add_unvalidated_block(&open).await.unwrap();
+
+        let send = |after: &Block, balance: u128| {
+            legacy_block(
+                BlockType::Send,
+                &account,
+                previous(after),
+                public.to_owned(),
+                balance,
+                Link::DestinationAccount(Public::zero()),
+            )
+        };
+        let first = send(&open, 700);
+        let second = send(&first, 400);
+
+        peer.handle_block(second.to_holder().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(unchecked.lock().await.len(), 1);
+        assert!(peer
+            .block_by_hash(second.hash().unwrap())
+            .await
+            .unwrap()
+            .is_none());
+
+        peer.handle_block(first.to_holder().unwrap()).await.unwrap();
+        peer.retry_unchecked().await.unwrap();
+        assert!(unchecked.lock().await.is_empty());
+        assert_eq!(
+            peer.get_latest_block(&public).await.unwrap().unwrap(),
+            second
+        );
+    }
+
+    #[tokio::test]
+    async fn only_worked_and_signed_blocks_wait() {
+        use crate::blocks::{BlockType, Link, ValidationState};
+        use crate::node::unchecked::Unchecked;
+
+        let network = Network::Test;
+        let state = Arc::new(Mutex::new(MemoryState::new(network)));
+        let from = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, DEFAULT_PORT));
+        let (mut peer, _rx, _tx) = Peer::new_with_channels(network, state, from);
+        let unchecked = Arc::new(Mutex::new(Unchecked::new()));
+        peer.unchecked = Some(unchecked.clone());
+
+        let account = Private::random();
+        let public = account.to_public().unwrap();
+        let block = Block::new(
+            BlockType::State,
+            public.to_owned(),
+            Previous::Block(BlockHash::zero()),
+            public.to_owned(),
+            Raw::from(1000),
+            Link::Nothing,
+            ValidationState::Valid,
+        );
+        let threshold = network.work_thresholds().epoch_1;
+        let work = Work::generate(&block.work_subject(), &threshold).unwrap();
+
+        let mut unworked = block.clone();
+        unworked.sign(account.to_owned()).unwrap();
+        let mut unsigned = block.clone();
+        unsigned.set_work(work.to_owned());
+        let mut wrongly_signed = unsigned.clone();
+        wrongly_signed.sign(Private::random()).unwrap();
+        for block in &[unworked, unsigned, wrongly_signed] {
+            peer.handle_block(block.to_holder().unwrap()).await.unwrap();
+        }
+        assert!(unchecked.lock().await.is_empty());
+
+        let mut good = block;
+        good.set_work(work);
+        good.sign(account).unwrap();
+        peer.handle_block(good.to_holder().unwrap()).await.unwrap();
+        assert_eq!(unchecked.lock().await.len(), 1);
+    }
+
     #[tokio::test]
     async fn genesis() {
         let network = Network::Live;
@@ -416,11 +1218,26 @@ mod tests {
 
         peer.add_elected_block(&land_send).await.unwrap();
 
+        let land_balance = given
+            .checked_sub(&Raw::from(324518553658426726783156020576256))
+            .unwrap();
         assert_eq!(
             peer.account_balance(&landing_account).await.unwrap(),
-            given
-                .checked_sub(&Raw::from(324518553658426726783156020576256))
-                .unwrap()
+            land_balance
+        );
+
+        // Each representative has the balance of the account delegating to it.
+        assert_eq!(
+            peer.representative_weight(genesis.representative())
+                .await
+                .unwrap(),
+            genesis_balance
+        );
+        assert_eq!(
+            peer.representative_weight(land_open.representative())
+                .await
+                .unwrap(),
+            land_balance
         );
     }
 }
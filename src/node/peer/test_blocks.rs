@@ -0,0 +1,55 @@
+//! Blocks for tests, signed and with enough work to be added on [NETWORK].
+
+use crate::blocks::{Block, BlockType, Link, Previous, ValidationState};
+use crate::network::Network;
+use crate::{Difficulty, Private, Public, Raw, Work};
+
+pub(crate) const NETWORK: Network = Network::Test;
+
+/// Sign and generate work for a block.
+pub(crate) fn finish(mut block: Block, key: &Private, threshold: &Difficulty) -> Block {
+    block.sign(key.to_owned()).unwrap();
+    block.set_work(Work::generate(&block.work_subject(), threshold).unwrap());
+    block
+}
+
+/// A state block of `key`'s account that represents itself, with epoch 1 work.
+pub(crate) fn state_block(key: &Private, previous: Previous, balance: u128, link: Link) -> Block {
+    let account = key.to_public().unwrap();
+    let block = Block::new(
+        BlockType::State,
+        account.to_owned(),
+        previous,
+        account,
+        Raw::from(balance),
+        link,
+        ValidationState::Valid,
+    );
+    finish(block, key, &NETWORK.work_thresholds().epoch_1)
+}
+
+/// A legacy block of `key`'s account, with the epoch 1 work every legacy block needs.
+pub(crate) fn legacy_block(
+    block_type: BlockType,
+    key: &Private,
+    previous: Previous,
+    representative: Public,
+    balance: u128,
+    link: Link,
+) -> Block {
+    let block = Block::new(
+        block_type,
+        key.to_public().unwrap(),
+        previous,
+        representative,
+        Raw::from(balance),
+        link,
+        ValidationState::Valid,
+    );
+    finish(block, key, &NETWORK.work_thresholds().epoch_1)
+}
+
+/// The previous of a block that follows `block`.
+pub(crate) fn previous(block: &Block) -> Previous {
+    Previous::Block(block.hash().unwrap().to_owned())
+}
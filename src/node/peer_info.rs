@@ -1,7 +1,7 @@
 use crate::encoding::expect_len;
 use crate::node::header::Header;
 use crate::node::wire::Wire;
-use std::net::{Ipv6Addr, SocketAddrV6};
+use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
 use std::str::FromStr;
 
 pub struct PeerInfo(SocketAddrV6);
@@ -13,6 +13,26 @@ impl PeerInfo {
     pub fn socket_addr_v6(&self) -> SocketAddrV6 {
         self.0
     }
+
+    /// IPv4 mapped addresses are converted back to IPv4.
+    pub fn socket_addr(&self) -> SocketAddr {
+        match self.0.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(ip.into(), self.0.port()),
+            None => SocketAddr::V6(self.0),
+        }
+    }
+}
+
+impl From<SocketAddr> for PeerInfo {
+    /// IPv4 addresses are sent as IPv4 mapped IPv6 addresses.
+    fn from(addr: SocketAddr) -> Self {
+        match addr {
+            SocketAddr::V4(v4) => {
+                PeerInfo(SocketAddrV6::new(v4.ip().to_ipv6_mapped(), v4.port(), 0, 0))
+            }
+            SocketAddr::V6(v6) => PeerInfo(v6),
+        }
+    }
 }
 
 impl FromStr for PeerInfo {
@@ -69,4 +89,12 @@ mod tests {
         let addr2 = peer2.socket_addr_v6().to_string();
         assert_eq!(addr, addr2);
     }
+
+    #[test]
+    fn ipv4_mapped() {
+        let addr = SocketAddr::from_str("1.2.3.4:7075").unwrap();
+        let peer = PeerInfo::from(addr);
+        assert_eq!(peer.socket_addr_v6().to_string(), "[::ffff:1.2.3.4]:7075");
+        assert_eq!(peer.socket_addr(), addr);
+    }
 }
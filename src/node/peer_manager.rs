@@ -0,0 +1,412 @@
+//! Keeps track of the connection state of every peer we know about.
+use crate::node::header::Header;
+use crate::node::messages::telemetry_ack::TelemetryAck;
+use crate::node::node_id::node_id_string;
+use crate::node::peer::Packet;
+use crate::{Public, Version};
+use rand::seq::IteratorRandom;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Mutex};
+
+pub type ArcPeerManager = Arc<Mutex<PeerManager>>;
+
+/// The maximum number of peers we will connect to ourselves.
+pub const MAX_OUTGOING_CONNECTIONS: usize = 32;
+
+/// The maximum number of peers that can connect to us.
+pub const MAX_INCOMING_CONNECTIONS: usize = 64;
+
+/// Delay before the first reconnect attempt. Doubles on each consecutive failure.
+pub const RECONNECT_BASE_DELAY: Duration = Duration::from_secs(5);
+
+/// Upper bound of the reconnect delay.
+pub const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(10 * 60);
+
+/// Give up reconnecting after this many consecutive failures.
+pub const MAX_RECONNECT_ATTEMPTS: u32 = 8;
+
+/// How long a misbehaving peer is refused.
+pub const BAN_DURATION: Duration = Duration::from_secs(30 * 60);
+
+/// Attached as context to errors caused by a peer sending us something we can't accept, e.g.
+/// a header for another network or a payload that doesn't deserialize. The peer is banned when
+/// its connection ends with this error.
+#[derive(Debug, thiserror::Error)]
+#[error("Peer sent an invalid message")]
+pub struct InvalidMessage;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    /// They connected to us.
+    Incoming,
+
+    /// We connected to them.
+    Outgoing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionState {
+    /// We're dialing the peer.
+    Connecting,
+
+    Connected(Direction),
+
+    /// The connection failed and we'll try again after `until`.
+    Backoff {
+        until: Instant,
+    },
+
+    /// Not connected and no reconnect is scheduled.
+    Disconnected,
+}
+
+#[derive(Debug, Clone)]
+pub struct PeerStatus {
+    pub state: ConnectionState,
+
+    /// Consecutive failed connections. Reset when a connection is established.
+    pub failures: u32,
+
+    /// Known after a successful handshake.
+    pub node_id: Option<Public>,
+
+    /// The version the peer says it's using, which may be newer than we know about.
+    pub version_using: Option<u8>,
+
+    /// The newest version we both support, negotiated from the peer's header.
+    pub version: Option<Version>,
+
+    /// The latest verified telemetry the peer sent us.
+    pub telemetry: Option<TelemetryAck>,
+
+    /// Sends packets over the connection, e.g. to relay blocks from other peers.
+    pub sender: Option<mpsc::Sender<Packet>>,
+}
+
+impl PeerStatus {
+    fn new(state: ConnectionState) -> Self {
+        Self {
+            state,
+            failures: 0,
+            node_id: None,
+            version_using: None,
+            version: None,
+            telemetry: None,
+            sender: None,
+        }
+    }
+
+    /// The node ID in the form used by the RPC, e.g. `node_1y7j...`.
+    pub fn node_id_string(&self) -> Option<String> {
+        self.node_id.as_ref().map(node_id_string)
+    }
+}
+
+/// Connection state per [SocketAddr], connection limits, reconnect backoff and bans.
+///
+/// Bans are per IP address, since the port of an incoming connection is different every time.
+#[derive(Debug)]
+pub struct PeerManager {
+    peers: HashMap<SocketAddr, PeerStatus>,
+    bans: HashMap<IpAddr, Instant>,
+    max_outgoing: usize,
+    max_incoming: usize,
+}
+
+impl Default for PeerManager {
+    fn default() -> Self {
+        Self::new(MAX_OUTGOING_CONNECTIONS, MAX_INCOMING_CONNECTIONS)
+    }
+}
+
+impl PeerManager {
+    pub fn new(max_outgoing: usize, max_incoming: usize) -> Self {
+        Self {
+            peers: HashMap::new(),
+            bans: HashMap::new(),
+            max_outgoing,
+            max_incoming,
+        }
+    }
+
+    pub fn status(&self, addr: &SocketAddr) -> Option<&PeerStatus> {
+        self.peers.get(addr)
+    }
+
+    /// Also forgets bans that have expired.
+    pub fn is_banned(&mut self, ip: &IpAddr, now: Instant) -> bool {
+        match self.bans.get(ip) {
+            Some(until) if *until > now => true,
+            Some(_) => {
+                self.bans.remove(ip);
+                false
+            }
+            None => false,
+        }
+    }
+
+    pub fn ban(&mut self, ip: IpAddr, now: Instant) {
+        self.bans.insert(ip, now + BAN_DURATION);
+    }
+
+    fn count(&self, direction: Direction) -> usize {
+        self.peers
+            .values()
+            .filter(|status| match status.state {
+                ConnectionState::Connecting => direction == Direction::Outgoing,
+                ConnectionState::Connected(d) => d == direction,
+                _ => false,
+            })
+            .count()
+    }
+
+    /// Reserve an outgoing connection to `addr`. Returns false if we shouldn't dial it, because
+    /// we're already connected, it's banned, waiting for a backoff, or we're at the limit.
+    pub fn try_connect(&mut self, addr: SocketAddr, now: Instant) -> bool {
+        if self.is_banned(&addr.ip(), now) {
+            return false;
+        }
+        if let Some(status) = self.peers.get(&addr) {
+            match status.state {
+                ConnectionState::Connecting | ConnectionState::Connected(_) => return false,
+                ConnectionState::Backoff { until } if until > now => return false,
+                _ => {}
+            }
+        }
+        if self.count(Direction::Outgoing) >= self.max_outgoing {
+            return false;
+        }
+
+        self.peers
+            .entry(addr)
+            .or_insert_with(|| PeerStatus::new(ConnectionState::Disconnected))
+            .state = ConnectionState::Connecting;
+        true
+    }
+
+    /// Register a peer connecting to us. Returns false if the connection should be dropped.
+    pub fn try_accept(&mut self, addr: SocketAddr, now: Instant) -> bool {
+        if self.is_banned(&addr.ip(), now) {
+            return false;
+        }
+        if self.count(Direction::Incoming) >= self.max_incoming {
+            return false;
+        }
+        self.peers.insert(
+            addr,
+            PeerStatus::new(ConnectionState::Connected(Direction::Incoming)),
+        );
+        true
+    }
+
+    /// An outgoing connection has been established.
+    pub fn connected(&mut self, addr: SocketAddr) {
+        let status = self
+            .peers
+            .entry(addr)
+            .or_insert_with(|| PeerStatus::new(ConnectionState::Disconnected));
+        status.state = ConnectionState::Connected(Direction::Outgoing);
+        status.failures = 0;
+    }
+
+    /// The peer has completed a handshake.
+    pub fn identified(&mut self, addr: &SocketAddr, node_id: Public, header: &Header) {
+        if let Some(status) = self.peers.get_mut(addr) {
+            status.node_id = Some(node_id);
+            status.version_using = Some(header.version_using());
+            status.version = header.negotiate().ok();
+        }
+    }
+
+    pub fn set_sender(&mut self, addr: &SocketAddr, sender: mpsc::Sender<Packet>) {
+        if let Some(status) = self.peers.get_mut(addr) {
+            status.sender = Some(sender);
+        }
+    }
+
+    /// Stop relaying to a peer whose handler has finished, so its connection can close.
+    pub fn remove_sender(&mut self, addr: &SocketAddr) {
+        if let Some(status) = self.peers.get_mut(addr) {
+            status.sender = None;
+        }
+    }
+
+    pub fn set_telemetry(&mut self, addr: &SocketAddr, telemetry: TelemetryAck) {
+        if let Some(status) = self.peers.get_mut(addr) {
+            status.telemetry = Some(telemetry);
+        }
+    }
+
+    /// A connection has ended, or failed to be established. Returns how long to wait before
+    /// reconnecting, or None if we shouldn't. Only errors count as failures, so a connection
+    /// that closed cleanly is retried after the base delay.
+    pub fn disconnected(
+        &mut self,
+        addr: SocketAddr,
+        result: &anyhow::Result<()>,
+        now: Instant,
+    ) -> Option<Duration> {
+        if let Err(err) = result {
+            if err.downcast_ref::<InvalidMessage>().is_some() {
+                self.ban(addr.ip(), now);
+            }
+        }
+
+        let status = self.peers.get_mut(&addr)?;
+        status.sender = None;
+        if status.state == ConnectionState::Connected(Direction::Incoming) {
+            // We can't reconnect to the ephemeral port they connected from.
+            self.peers.remove(&addr);
+            return None;
+        }
+
+        if result.is_err() {
+            status.failures += 1;
+        }
+        if self.bans.contains_key(&addr.ip()) || status.failures > MAX_RECONNECT_ATTEMPTS {
+            status.state = ConnectionState::Disconnected;
+            return None;
+        }
+
+        let delay = RECONNECT_BASE_DELAY
+            .checked_mul(1 << status.failures.saturating_sub(1))
+            .unwrap_or(RECONNECT_MAX_DELAY)
+            .min(RECONNECT_MAX_DELAY);
+        status.state = ConnectionState::Backoff { until: now + delay };
+        Some(delay)
+    }
+
+    /// All peers with an established connection.
+    pub fn connected_peers(&self) -> impl Iterator<Item = (&SocketAddr, &PeerStatus)> {
+        self.peers
+            .iter()
+            .filter(|(_, status)| matches!(status.state, ConnectionState::Connected(_)))
+    }
+
+    /// A random square root sized subset of connected peers to relay a message to, leaving out
+    /// the peer we got it from. Enough for a message to reach the whole network in a few hops,
+    /// without every peer receiving it from every other peer.
+    pub fn flood_targets(&self, except: &SocketAddr) -> Vec<mpsc::Sender<Packet>> {
+        let senders: Vec<&mpsc::Sender<Packet>> = self
+            .connected_peers()
+            .filter(|(addr, _)| *addr != except)
+            .filter_map(|(_, status)| status.sender.as_ref())
+            .collect();
+        let count = (senders.len() as f64).sqrt().ceil() as usize;
+        senders
+            .into_iter()
+            .choose_multiple(&mut rand::thread_rng(), count)
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+    use std::str::FromStr;
+
+    fn addr(s: &str) -> SocketAddr {
+        SocketAddr::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn connection_limit() {
+        let now = Instant::now();
+        let mut manager = PeerManager::new(1, 1);
+        assert!(manager.try_connect(addr("1.1.1.1:7075"), now));
+        assert!(!manager.try_connect(addr("1.1.1.1:7075"), now));
+        assert!(!manager.try_connect(addr("2.2.2.2:7075"), now));
+
+        assert!(manager.try_accept(addr("3.3.3.3:1234"), now));
+        assert!(!manager.try_accept(addr("4.4.4.4:1234"), now));
+        assert_eq!(manager.connected_peers().count(), 1);
+    }
+
+    #[test]
+    fn backoff() {
+        let now = Instant::now();
+        let peer = addr("1.1.1.1:7075");
+        let mut manager = PeerManager::default();
+        let failed = Err(anyhow!("Connection refused"));
+
+        assert!(manager.try_connect(peer, now));
+        assert_eq!(
+            manager.disconnected(peer, &failed, now),
+            Some(RECONNECT_BASE_DELAY)
+        );
+        assert!(!manager.try_connect(peer, now));
+
+        let later = now + RECONNECT_BASE_DELAY;
+        assert!(manager.try_connect(peer, later));
+        assert_eq!(
+            manager.disconnected(peer, &failed, later),
+            Some(RECONNECT_BASE_DELAY * 2)
+        );
+
+        // A successful connection resets the delay, and closing cleanly isn't a failure.
+        manager.connected(peer);
+        for _ in 0..=MAX_RECONNECT_ATTEMPTS {
+            assert_eq!(
+                manager.disconnected(peer, &Ok(()), later),
+                Some(RECONNECT_BASE_DELAY)
+            );
+        }
+
+        for _ in 1..=MAX_RECONNECT_ATTEMPTS {
+            assert!(manager.disconnected(peer, &failed, later).is_some());
+        }
+        assert_eq!(manager.disconnected(peer, &failed, later), None);
+        assert_eq!(
+            manager.status(&peer).unwrap().state,
+            ConnectionState::Disconnected
+        );
+    }
+
+    #[test]
+    fn flood_targets() {
+        let now = Instant::now();
+        let mut manager = PeerManager::default();
+        let mut receivers = vec![];
+        for i in 1..=10 {
+            let peer = addr(&format!("1.1.1.{}:7075", i));
+            assert!(manager.try_accept(peer, now));
+            let (tx, rx) = mpsc::channel(1);
+            manager.set_sender(&peer, tx);
+            receivers.push(rx);
+        }
+        let except = addr("1.1.1.1:7075");
+        assert_eq!(manager.flood_targets(&except).len(), 3);
+
+        // Disconnected peers aren't relayed to.
+        for i in 2..=10 {
+            manager.disconnected(addr(&format!("1.1.1.{}:7075", i)), &Ok(()), now);
+        }
+        assert!(manager.flood_targets(&except).is_empty());
+    }
+
+    #[test]
+    fn ban_on_invalid_message() {
+        let now = Instant::now();
+        let peer = addr("1.1.1.1:7075");
+        let mut manager = PeerManager::default();
+
+        assert!(manager.try_accept(addr("1.1.1.1:50000"), now));
+        let invalid = Err(anyhow!("Bad header"))
+            .context(InvalidMessage)
+            .context("Handling packet");
+        assert_eq!(
+            manager.disconnected(addr("1.1.1.1:50000"), &invalid, now),
+            None
+        );
+
+        assert!(!manager.try_connect(peer, now));
+        assert!(!manager.try_accept(addr("1.1.1.1:50001"), now));
+        assert!(manager.try_connect(peer, now + BAN_DURATION));
+    }
+}
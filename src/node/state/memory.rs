@@ -1,11 +1,13 @@
-use crate::blocks::{Block, BlockHash};
+use crate::blocks::{Block, BlockHash, Epoch, Previous};
 use crate::network::Network;
 use crate::node::cookie::Cookie;
-use crate::node::state::State;
-use crate::Public;
-use anyhow::Context;
+use crate::node::state::{
+    unix_seconds, ConfirmationHeight, Frontier, LedgerChange, Receivable, ReceivableChange, State,
+};
+use crate::{Public, Raw};
+use anyhow::{anyhow, Context};
 use async_trait::async_trait;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::net::SocketAddr;
 
 #[derive(Debug)]
@@ -14,8 +16,14 @@ pub struct MemoryState {
     cookies: HashMap<SocketAddr, Cookie>,
     blocks: HashMap<BlockHash, Block>,
     block_hash_to_account: HashMap<BlockHash, Public>,
-    latest_block_hash: HashMap<Public, BlockHash>,
-    votes: HashMap<BlockHash, HashSet<Public>>,
+    latest_block_hash: BTreeMap<Public, (BlockHash, u64)>,
+    representative_weights: HashMap<Public, Raw>,
+    account_epochs: HashMap<Public, Epoch>,
+    block_epochs: HashMap<BlockHash, Epoch>,
+    block_heights: HashMap<BlockHash, u64>,
+    confirmation_heights: HashMap<Public, ConfirmationHeight>,
+    receivables: BTreeMap<(Public, BlockHash), Receivable>,
+    votes: HashMap<BlockHash, HashMap<Public, u64>>,
     peers: HashSet<SocketAddr>,
 }
 
@@ -26,27 +34,190 @@ impl MemoryState {
             cookies: HashMap::new(),
             blocks: HashMap::new(),
             block_hash_to_account: HashMap::new(),
-            latest_block_hash: HashMap::new(),
+            latest_block_hash: BTreeMap::new(),
+            representative_weights: HashMap::new(),
+            account_epochs: HashMap::new(),
+            block_epochs: HashMap::new(),
+            block_heights: HashMap::new(),
+            confirmation_heights: HashMap::new(),
+            receivables: BTreeMap::new(),
             votes: HashMap::new(),
             peers: HashSet::new(),
         }
     }
 }
 
+impl MemoryState {
+    /// Make sure the ledger is still how `change` was worked out: the block follows the
+    /// head of its account, and anything it receives hasn't been received yet.
+    fn check_add(
+        &self,
+        block: &Block,
+        hash: &BlockHash,
+        change: &LedgerChange,
+    ) -> anyhow::Result<()> {
+        let head = self.latest_block_hash.get(block.account()).map(|(h, _)| h);
+        let follows = match block.previous() {
+            Previous::Open => head.is_none(),
+            Previous::Block(previous) => head == Some(previous),
+        };
+        if !follows {
+            return Err(anyhow!(
+                "Block {:?} doesn't follow the head {:?} of its account",
+                hash,
+                head
+            ));
+        }
+        if let ReceivableChange::Remove {
+            destination,
+            send_hash,
+        } = &change.receivable
+        {
+            let key = (destination.to_owned(), send_hash.to_owned());
+            if !self.receivables.contains_key(&key) {
+                return Err(anyhow!(
+                    "Block {:?} receives {:?}, which isn't receivable",
+                    hash,
+                    send_hash
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply everything in `change` but the block itself. The new weights are worked out
+    /// first, so nothing changes if one of them is invalid.
+    fn apply_change(&mut self, account: &Public, change: &LedgerChange) -> anyhow::Result<()> {
+        let mut weights: HashMap<Public, Raw> = HashMap::new();
+        for weight_change in &change.weights {
+            let rep = weight_change.representative();
+            let weight = match weights.get(rep) {
+                Some(weight) => weight.to_owned(),
+                None => self
+                    .representative_weights
+                    .get(rep)
+                    .cloned()
+                    .unwrap_or_else(Raw::zero),
+            };
+            weights.insert(rep.to_owned(), weight_change.apply(&weight)?);
+        }
+
+        for (rep, weight) in weights {
+            if weight == Raw::zero() {
+                self.representative_weights.remove(&rep);
+            } else {
+                self.representative_weights.insert(rep, weight);
+            }
+        }
+        match change.epoch {
+            Some(Epoch::Epoch0) => {
+                self.account_epochs.remove(account);
+            }
+            Some(epoch) => {
+                self.account_epochs.insert(account.to_owned(), epoch);
+            }
+            None => {}
+        }
+        self.apply_receivable_change(&change.receivable);
+        Ok(())
+    }
+
+    fn apply_receivable_change(&mut self, change: &ReceivableChange) {
+        match change {
+            ReceivableChange::None => {}
+            ReceivableChange::Add {
+                destination,
+                send_hash,
+                receivable,
+            } => {
+                self.receivables.insert(
+                    (destination.to_owned(), send_hash.to_owned()),
+                    receivable.to_owned(),
+                );
+            }
+            ReceivableChange::Remove {
+                destination,
+                send_hash,
+            } => {
+                self.receivables
+                    .remove(&(destination.to_owned(), send_hash.to_owned()));
+            }
+        }
+    }
+}
+
 #[async_trait]
 impl State for MemoryState {
-    async fn add_block(&mut self, block: &Block) -> anyhow::Result<()> {
-        self.blocks.insert(
-            block.hash().context("Add block")?.to_owned(),
-            block.to_owned(),
-        );
+    async fn add_block(&mut self, block: &Block, change: &LedgerChange) -> anyhow::Result<()> {
+        let hash = block.hash().context("Add block")?;
+        self.check_add(block, hash, change)?;
+        self.apply_change(block.account(), change)?;
+        if let Some(epoch) = self.account_epochs.get(block.account()) {
+            self.block_epochs.insert(hash.to_owned(), *epoch);
+        }
+        let height = match block.previous() {
+            Previous::Block(previous) => self.block_heights.get(previous).map(|h| h + 1),
+            Previous::Open => Some(1),
+        };
+        if let Some(height) = height {
+            self.block_heights.insert(hash.to_owned(), height);
+        }
+        self.blocks.insert(hash.to_owned(), block.to_owned());
         self.block_hash_to_account
-            .insert(block.hash()?.to_owned(), block.account().to_owned());
-        self.latest_block_hash
-            .insert(block.account().to_owned(), block.hash()?.to_owned());
+            .insert(hash.to_owned(), block.account().to_owned());
+        self.latest_block_hash.insert(
+            block.account().to_owned(),
+            (hash.to_owned(), unix_seconds()),
+        );
         Ok(())
     }
 
+    async fn remove_block(&mut self, block: &Block, change: &LedgerChange) -> anyhow::Result<()> {
+        let hash = block.hash().context("Remove block")?;
+        self.apply_change(block.account(), change)?;
+        self.block_epochs.remove(hash);
+        self.block_heights.remove(hash);
+        self.blocks.remove(hash);
+        self.block_hash_to_account.remove(hash);
+        match block.previous() {
+            Previous::Block(previous) => {
+                self.latest_block_hash.insert(
+                    block.account().to_owned(),
+                    (previous.to_owned(), unix_seconds()),
+                );
+            }
+            Previous::Open => {
+                self.latest_block_hash.remove(block.account());
+            }
+        }
+        Ok(())
+    }
+
+    async fn receivable(
+        &self,
+        destination: &Public,
+        send_hash: &BlockHash,
+    ) -> anyhow::Result<Option<Receivable>> {
+        Ok(self
+            .receivables
+            .get(&(destination.to_owned(), send_hash.to_owned()))
+            .cloned())
+    }
+
+    async fn receivables(
+        &self,
+        destination: &Public,
+        count: usize,
+    ) -> anyhow::Result<Vec<(BlockHash, Receivable)>> {
+        Ok(self
+            .receivables
+            .range((destination.to_owned(), BlockHash::zero())..)
+            .take_while(|((d, _), _)| d == destination)
+            .take(count)
+            .map(|((_, hash), receivable)| (hash.to_owned(), receivable.to_owned()))
+            .collect())
+    }
+
     async fn get_block_by_hash(&self, hash: &BlockHash) -> anyhow::Result<Option<Block>> {
         Ok(self.blocks.get(hash).map(|b| b.to_owned()))
     }
@@ -55,7 +226,31 @@ impl State for MemoryState {
         &self,
         account: &Public,
     ) -> anyhow::Result<Option<BlockHash>> {
-        Ok(self.latest_block_hash.get(account).map(|b| b.to_owned()))
+        Ok(self
+            .latest_block_hash
+            .get(account)
+            .map(|(hash, _)| hash.to_owned()))
+    }
+
+    async fn frontiers(&self, start: &Public, count: usize) -> anyhow::Result<Vec<Frontier>> {
+        Ok(self
+            .latest_block_hash
+            .range(start.to_owned()..)
+            .take(count)
+            .map(|(account, (hash, modified))| Frontier {
+                account: account.to_owned(),
+                hash: hash.to_owned(),
+                modified: *modified,
+            })
+            .collect())
+    }
+
+    async fn block_count(&self) -> anyhow::Result<u64> {
+        Ok(self.blocks.len() as u64)
+    }
+
+    async fn account_count(&self) -> anyhow::Result<u64> {
+        Ok(self.latest_block_hash.len() as u64)
     }
 
     async fn account_for_block_hash(
@@ -68,26 +263,84 @@ impl State for MemoryState {
             .map(|a| a.to_owned()))
     }
 
-    async fn add_vote(&mut self, hash: &BlockHash, representative: &Public) -> anyhow::Result<()> {
-        let entry = self
-            .votes
-            .entry(hash.to_owned())
-            .or_insert_with(|| HashSet::new());
-        entry.insert(representative.to_owned());
+    async fn representative_weight(&self, representative: &Public) -> anyhow::Result<Raw> {
+        Ok(self
+            .representative_weights
+            .get(representative)
+            .cloned()
+            .unwrap_or_else(Raw::zero))
+    }
 
-        // dbg!(&self
-        //     .votes
-        //     .iter()
-        //     .map(|v| format!("{} {}", v.0, v.1.len()))
-        //     .collect::<Vec<_>>());
+    async fn account_epoch(&self, account: &Public) -> anyhow::Result<Epoch> {
+        Ok(self
+            .account_epochs
+            .get(account)
+            .copied()
+            .unwrap_or(Epoch::Epoch0))
+    }
 
-        // dbg!(&self.votes);
+    fn network(&self) -> &Network {
+        &self.network
+    }
 
-        // println!("XXXXXX {:?} {:?}", hash, representative);
+    async fn block_epoch(&self, hash: &BlockHash) -> anyhow::Result<Epoch> {
+        Ok(self
+            .block_epochs
+            .get(hash)
+            .copied()
+            .unwrap_or(Epoch::Epoch0))
+    }
+
+    async fn block_height(&self, hash: &BlockHash) -> anyhow::Result<Option<u64>> {
+        Ok(self.block_heights.get(hash).copied())
+    }
 
+    async fn confirmation_height(
+        &self,
+        account: &Public,
+    ) -> anyhow::Result<Option<ConfirmationHeight>> {
+        Ok(self.confirmation_heights.get(account).cloned())
+    }
+
+    async fn set_confirmation_height(
+        &mut self,
+        account: &Public,
+        confirmation_height: &ConfirmationHeight,
+    ) -> anyhow::Result<()> {
+        self.confirmation_heights
+            .insert(account.to_owned(), confirmation_height.to_owned());
         Ok(())
     }
 
+    async fn cemented_count(&self) -> anyhow::Result<u64> {
+        Ok(self.confirmation_heights.values().map(|c| c.height).sum())
+    }
+
+    async fn add_vote(
+        &mut self,
+        hash: &BlockHash,
+        representative: &Public,
+        timestamp: u64,
+    ) -> anyhow::Result<()> {
+        self.votes
+            .entry(hash.to_owned())
+            .or_default()
+            .insert(representative.to_owned(), timestamp);
+        Ok(())
+    }
+
+    async fn vote_timestamp(
+        &self,
+        hash: &BlockHash,
+        representative: &Public,
+    ) -> anyhow::Result<Option<u64>> {
+        Ok(self
+            .votes
+            .get(hash)
+            .and_then(|votes| votes.get(representative))
+            .copied())
+    }
+
     async fn set_cookie(
         &mut self,
         socket_addr: SocketAddr,
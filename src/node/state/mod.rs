@@ -1,9 +1,10 @@
 mod memory;
 mod sled_disk;
 
-use crate::blocks::{Block, BlockHash};
+use crate::blocks::{Block, BlockHash, Epoch};
 use crate::node::cookie::Cookie;
-use crate::Public;
+use crate::{Network, Public, Raw};
+use anyhow::anyhow;
 use async_trait::async_trait;
 pub use memory::MemoryState;
 pub use sled_disk::SledDiskState;
@@ -11,16 +12,163 @@ use std::collections::HashSet;
 use std::fmt::Debug;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::Mutex;
 
 pub type DynState = dyn State + Send + Sync;
 pub type ArcState = Arc<Mutex<DynState>>;
 
+/// The latest block of an account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frontier {
+    pub account: Public,
+    pub hash: BlockHash,
+
+    /// Seconds since the unix epoch when the frontier last changed.
+    pub modified: u64,
+}
+
+/// How far an account chain is confirmed. The first `height` blocks, up to and including
+/// `frontier`, are cemented and can't be rolled back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfirmationHeight {
+    pub height: u64,
+    pub frontier: BlockHash,
+}
+
+/// A send that hasn't been received yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Receivable {
+    pub amount: Raw,
+
+    /// The account that sent it.
+    pub source: Public,
+
+    /// The epoch of the sending account at the send. Receiving it upgrades the receiving
+    /// account to at least this epoch.
+    pub epoch: Epoch,
+}
+
+/// How adding a block changes the receivable entries.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ReceivableChange {
+    #[default]
+    None,
+
+    /// `send_hash` sent to `destination`.
+    Add {
+        destination: Public,
+        send_hash: BlockHash,
+        receivable: Receivable,
+    },
+
+    /// `destination` received `send_hash`.
+    Remove {
+        destination: Public,
+        send_hash: BlockHash,
+    },
+}
+
+/// Voting weight given to or taken from a representative.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WeightChange {
+    Add(Public, Raw),
+    Subtract(Public, Raw),
+}
+
+impl WeightChange {
+    /// How adding `block` after `previous` moves voting weight.
+    ///
+    /// The balance before the block is taken away from the representative before the block, and
+    /// the balance after it is given to the block's representative. This covers sends, receives
+    /// and changes alike, whether or not they are state blocks.
+    pub fn for_block(block: &Block, previous: Option<&Block>) -> Vec<Self> {
+        let mut changes = vec![];
+        if let Some(previous) = previous {
+            changes.push(Self::Subtract(
+                previous.representative().to_owned(),
+                previous.balance().to_owned(),
+            ));
+        }
+        changes.push(Self::Add(
+            block.representative().to_owned(),
+            block.balance().to_owned(),
+        ));
+        changes
+    }
+
+    /// The change that undoes this one.
+    pub fn inverse(&self) -> Self {
+        match self {
+            Self::Add(rep, amount) => Self::Subtract(rep.to_owned(), amount.to_owned()),
+            Self::Subtract(rep, amount) => Self::Add(rep.to_owned(), amount.to_owned()),
+        }
+    }
+
+    pub fn representative(&self) -> &Public {
+        match self {
+            Self::Add(rep, _) | Self::Subtract(rep, _) => rep,
+        }
+    }
+
+    /// The representative's weight after this change.
+    pub fn apply(&self, weight: &Raw) -> anyhow::Result<Raw> {
+        match self {
+            Self::Add(rep, amount) => weight
+                .checked_add(amount)
+                .ok_or_else(|| anyhow!("Weight of {:?} overflowed", rep)),
+            Self::Subtract(rep, amount) => weight
+                .checked_sub(amount)
+                .ok_or_else(|| anyhow!("Weight of {:?} would be negative", rep)),
+        }
+    }
+}
+
+/// What adding or removing a block changes in the ledger besides the block itself. [State]
+/// applies it together with the block, so nothing sees one without the other.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LedgerChange {
+    pub receivable: ReceivableChange,
+
+    /// The account's epoch afterwards, if it changes.
+    pub epoch: Option<Epoch>,
+
+    pub weights: Vec<WeightChange>,
+}
+
+/// Seconds since the unix epoch, as used in [Frontier::modified].
+pub fn unix_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 /// State contains a state of the Nano block lattice 🥬,
 /// it also contains ephemeral information like peers.
 #[async_trait]
 pub trait State: Debug + Sync + Send + 'static {
-    async fn add_block(&mut self, block: &Block) -> anyhow::Result<()>;
+    /// Add a block and apply `change`, either both or neither. Fails if the block doesn't
+    /// follow the head of its account or what it receives is no longer receivable, since
+    /// `change` was worked out from a ledger that has moved on.
+    async fn add_block(&mut self, block: &Block, change: &LedgerChange) -> anyhow::Result<()>;
+
+    /// Remove the head block of an account and apply `change`, which should undo what adding
+    /// the block did. The block before it becomes the head again.
+    async fn remove_block(&mut self, block: &Block, change: &LedgerChange) -> anyhow::Result<()>;
+
+    async fn receivable(
+        &self,
+        destination: &Public,
+        send_hash: &BlockHash,
+    ) -> anyhow::Result<Option<Receivable>>;
+
+    /// Up to `count` receivable entries for `destination`, in send hash order.
+    async fn receivables(
+        &self,
+        destination: &Public,
+        count: usize,
+    ) -> anyhow::Result<Vec<(BlockHash, Receivable)>>;
 
     async fn get_block_by_hash(&self, hash: &BlockHash) -> anyhow::Result<Option<Block>>;
 
@@ -29,12 +177,77 @@ pub trait State: Debug + Sync + Send + 'static {
         account: &Public,
     ) -> anyhow::Result<Option<BlockHash>>;
 
+    /// Up to `count` frontiers in account order, starting from and including `start`.
+    async fn frontiers(&self, start: &Public, count: usize) -> anyhow::Result<Vec<Frontier>>;
+
+    async fn block_count(&self) -> anyhow::Result<u64>;
+
+    /// The number of accounts with at least one block.
+    async fn account_count(&self) -> anyhow::Result<u64>;
+
     async fn account_for_block_hash(
         &mut self,
         block_hash: &BlockHash,
     ) -> anyhow::Result<Option<Public>>;
 
-    async fn add_vote(&mut self, hash: &BlockHash, representative: &Public) -> anyhow::Result<()>;
+    /// The total balance of accounts that have `representative` as their representative.
+    async fn representative_weight(&self, representative: &Public) -> anyhow::Result<Raw>;
+
+    /// The epoch of `account`, which is [Epoch::Epoch0] until an epoch block upgrades it.
+    async fn account_epoch(&self, account: &Public) -> anyhow::Result<Epoch>;
+
+    /// The network the ledger is for.
+    fn network(&self) -> &Network;
+
+    /// The epoch of a block's account as of that block.
+    async fn block_epoch(&self, hash: &BlockHash) -> anyhow::Result<Epoch>;
+
+    /// How far `account` is confirmed, or `None` if none of its blocks are.
+    async fn confirmation_height(
+        &self,
+        account: &Public,
+    ) -> anyhow::Result<Option<ConfirmationHeight>>;
+
+    async fn set_confirmation_height(
+        &mut self,
+        account: &Public,
+        confirmation_height: &ConfirmationHeight,
+    ) -> anyhow::Result<()>;
+
+    /// The number of cemented blocks across all accounts.
+    async fn cemented_count(&self) -> anyhow::Result<u64>;
+
+    /// The position of a block in its account chain, starting from 1 for the open block. It's
+    /// kept when the block is added, so `None` if the block isn't in the ledger.
+    async fn block_height(&self, hash: &BlockHash) -> anyhow::Result<Option<u64>>;
+
+    /// Whether a block in our ledger is at or below its account's confirmation height.
+    async fn is_cemented(&self, block: &Block) -> anyhow::Result<bool> {
+        let confirmation_height = match self.confirmation_height(block.account()).await? {
+            Some(confirmation_height) => confirmation_height,
+            None => return Ok(false),
+        };
+        let height = self
+            .block_height(block.hash()?)
+            .await?
+            .ok_or_else(|| anyhow!("Block {:?} is not in the ledger", block.hash()))?;
+        Ok(height <= confirmation_height.height)
+    }
+
+    /// Record a vote, replacing any older vote from the same representative for the block.
+    async fn add_vote(
+        &mut self,
+        hash: &BlockHash,
+        representative: &Public,
+        timestamp: u64,
+    ) -> anyhow::Result<()>;
+
+    /// The timestamp (or sequence) of the latest vote we have from `representative` for `hash`.
+    async fn vote_timestamp(
+        &self,
+        hash: &BlockHash,
+        representative: &Public,
+    ) -> anyhow::Result<Option<u64>>;
 
     async fn set_cookie(&mut self, socket_addr: SocketAddr, cookie: Cookie) -> anyhow::Result<()>;
 
@@ -1,68 +1,386 @@
-use crate::blocks::{Block, BlockHash};
+use crate::blocks::{
+    Block, BlockHash, BlockType, Epoch, Link, Previous, UnsureLink, ValidationState,
+};
+use crate::bytes::Bytes;
 use crate::network::Network;
 use crate::node::cookie::Cookie;
-use crate::node::state::State;
-use crate::Public;
+use crate::node::state::{
+    unix_seconds, ConfirmationHeight, Frontier, LedgerChange, Receivable, ReceivableChange, State,
+    WeightChange,
+};
+use crate::paths::Paths;
+use crate::{Public, Raw, Signature, Work};
+use anyhow::{anyhow, Context};
 use async_trait::async_trait;
+use sled::transaction::{
+    ConflictableTransactionError, ConflictableTransactionResult, TransactionError,
+    TransactionalTree, UnabortableTransactionError,
+};
+use sled::Transactional;
 use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::net::SocketAddr;
+use std::str::FromStr;
 
 /// Sled is an on disk key value pair.
+///
+/// Each kind of data lives in its own tree:
+/// * `blocks`: block hash -> encoded [Block].
+/// * `frontiers`: account -> latest block hash, modified time (u64 big endian seconds).
+/// * `block_account`: block hash -> account.
+/// * `representative_weights`: representative -> weight (u128 big endian raw).
+/// * `account_epochs`: account -> epoch (u8), only for accounts past [Epoch::Epoch0].
+/// * `block_epochs`: block hash -> epoch of its account as of the block (u8), only past
+///   [Epoch::Epoch0].
+/// * `block_heights`: block hash -> position in its account chain (u64 big endian).
+/// * `confirmation_heights`: account -> height (u64 big endian), frontier block hash.
+/// * `receivables`: destination + send hash -> amount (u128 big endian raw), source account,
+///   epoch of the send (u8).
+/// * `votes`: block hash + representative -> vote timestamp (u64 big endian).
+/// * `peers`: socket address -> nothing.
+/// * `cookies`: socket address -> cookie.
 #[derive(Clone, Debug)]
 pub struct SledDiskState {
     network: Network,
     db: sled::Db,
-    cookies: sled::Tree,
+    blocks: sled::Tree,
+    frontiers: sled::Tree,
+    block_account: sled::Tree,
+    representative_weights: sled::Tree,
+    account_epochs: sled::Tree,
+    block_epochs: sled::Tree,
+    block_heights: sled::Tree,
+    confirmation_heights: sled::Tree,
+    receivables: sled::Tree,
+    votes: sled::Tree,
     peers: sled::Tree,
+    cookies: sled::Tree,
 }
 
 impl SledDiskState {
-    pub fn new(network: Network) -> Self {
-        let path = format!("{:?}.db", network).to_ascii_lowercase();
+    /// Open (or create) the database in the data directory of `paths`.
+    pub fn new(network: Network, paths: &Paths) -> anyhow::Result<Self> {
+        paths.ensure_data_path()?;
+        let path = paths.db_path();
         let db: sled::Db =
-            sled::open(&path).unwrap_or_else(|_| panic!("Could not open database: {}", &path));
-        let cookies = db.open_tree("cookies").unwrap();
-        let peers = db.open_tree("peers").unwrap();
-        Self {
+            sled::open(&path).with_context(|| format!("Could not open database: {:?}", &path))?;
+        Self::from_db(network, db)
+    }
+
+    /// A database that is removed when dropped. Useful for tests.
+    pub fn temporary(network: Network) -> anyhow::Result<Self> {
+        let db = sled::Config::new()
+            .temporary(true)
+            .open()
+            .context("Could not open temporary database")?;
+        Self::from_db(network, db)
+    }
+
+    fn from_db(network: Network, db: sled::Db) -> anyhow::Result<Self> {
+        Ok(Self {
             network,
+            blocks: db.open_tree("blocks")?,
+            frontiers: db.open_tree("frontiers")?,
+            block_account: db.open_tree("block_account")?,
+            representative_weights: db.open_tree("representative_weights")?,
+            account_epochs: db.open_tree("account_epochs")?,
+            block_epochs: db.open_tree("block_epochs")?,
+            block_heights: db.open_tree("block_heights")?,
+            confirmation_heights: db.open_tree("confirmation_heights")?,
+            receivables: db.open_tree("receivables")?,
+            votes: db.open_tree("votes")?,
+            peers: db.open_tree("peers")?,
+            cookies: db.open_tree("cookies")?,
             db,
-            cookies,
-            peers,
-        }
+        })
     }
 }
 
 #[async_trait]
 impl State for SledDiskState {
-    async fn add_block(&mut self, _block_holder: &Block) -> anyhow::Result<()> {
-        unimplemented!()
+    async fn add_block(&mut self, block: &Block, change: &LedgerChange) -> anyhow::Result<()> {
+        let hash = block.hash().context("Add block")?;
+        let account = block.account();
+        let encoded = encode_block(block);
+        let mut frontier = hash.as_bytes().to_vec();
+        frontier.extend_from_slice(&unix_seconds().to_be_bytes());
+
+        (
+            &self.blocks,
+            &self.block_account,
+            &self.frontiers,
+            &self.receivables,
+            &self.representative_weights,
+            &self.account_epochs,
+            &self.block_epochs,
+            &self.block_heights,
+        )
+            .transaction(
+                |(
+                    blocks,
+                    block_account,
+                    frontiers,
+                    receivables,
+                    weights,
+                    epochs,
+                    block_epochs,
+                    block_heights,
+                )| {
+                    check_add(frontiers, receivables, block, hash, change)?;
+                    blocks.insert(hash.as_bytes(), encoded.as_slice())?;
+                    block_account.insert(hash.as_bytes(), account.as_bytes())?;
+                    frontiers.insert(account.as_bytes(), frontier.as_slice())?;
+                    apply_receivable_change(receivables, &change.receivable)?;
+                    apply_weight_changes(weights, &change.weights)?;
+                    apply_epoch(epochs, account, &change.epoch)?;
+                    if let Some(epoch) = epochs.get(account.as_bytes())? {
+                        block_epochs.insert(hash.as_bytes(), epoch)?;
+                    }
+                    let height = match block.previous() {
+                        Previous::Block(previous) => block_heights
+                            .get(previous.as_bytes())?
+                            .map(|height| decode_height(&height))
+                            .transpose()
+                            .map_err(|err| ConflictableTransactionError::Abort(err.to_string()))?
+                            .map(|height| height + 1),
+                        Previous::Open => Some(1),
+                    };
+                    if let Some(height) = height {
+                        block_heights.insert(hash.as_bytes(), &height.to_be_bytes())?;
+                    }
+                    Ok(())
+                },
+            )
+            .map_err(|err: TransactionError<String>| {
+                anyhow!("Adding block {:?}: {:?}", hash, err)
+            })?;
+        Ok(())
     }
 
-    async fn get_block_by_hash(&self, _hash: &BlockHash) -> anyhow::Result<Option<Block>> {
-        unimplemented!()
+    async fn remove_block(&mut self, block: &Block, change: &LedgerChange) -> anyhow::Result<()> {
+        let hash = block.hash().context("Remove block")?;
+        let account = block.account();
+        let frontier = match block.previous() {
+            Previous::Block(previous) => {
+                let mut frontier = previous.as_bytes().to_vec();
+                frontier.extend_from_slice(&unix_seconds().to_be_bytes());
+                Some(frontier)
+            }
+            Previous::Open => None,
+        };
+
+        (
+            &self.blocks,
+            &self.block_account,
+            &self.frontiers,
+            &self.receivables,
+            &self.representative_weights,
+            &self.account_epochs,
+            &self.block_epochs,
+            &self.block_heights,
+        )
+            .transaction(
+                |(
+                    blocks,
+                    block_account,
+                    frontiers,
+                    receivables,
+                    weights,
+                    epochs,
+                    block_epochs,
+                    block_heights,
+                )| {
+                    blocks.remove(hash.as_bytes())?;
+                    block_epochs.remove(hash.as_bytes())?;
+                    block_heights.remove(hash.as_bytes())?;
+                    block_account.remove(hash.as_bytes())?;
+                    match &frontier {
+                        Some(frontier) => {
+                            frontiers.insert(account.as_bytes(), frontier.as_slice())?
+                        }
+                        None => frontiers.remove(account.as_bytes())?,
+                    };
+                    apply_receivable_change(receivables, &change.receivable)?;
+                    apply_weight_changes(weights, &change.weights)?;
+                    apply_epoch(epochs, account, &change.epoch)?;
+                    Ok(())
+                },
+            )
+            .map_err(|err: TransactionError<String>| {
+                anyhow!("Removing block {:?}: {:?}", hash, err)
+            })?;
+        Ok(())
+    }
+
+    async fn receivable(
+        &self,
+        destination: &Public,
+        send_hash: &BlockHash,
+    ) -> anyhow::Result<Option<Receivable>> {
+        self.receivables
+            .get(receivable_key(destination, send_hash))?
+            .map(|v| decode_receivable(&v))
+            .transpose()
+    }
+
+    async fn receivables(
+        &self,
+        destination: &Public,
+        count: usize,
+    ) -> anyhow::Result<Vec<(BlockHash, Receivable)>> {
+        let mut entries = vec![];
+        for entry in self
+            .receivables
+            .scan_prefix(destination.as_bytes())
+            .take(count)
+        {
+            let (key, value) = entry?;
+            entries.push((
+                BlockHash::try_from(&key[Public::LEN..])?,
+                decode_receivable(&value)?,
+            ));
+        }
+        Ok(entries)
+    }
+
+    async fn get_block_by_hash(&self, hash: &BlockHash) -> anyhow::Result<Option<Block>> {
+        self.blocks
+            .get(hash.as_bytes())?
+            .map(|v| decode_block(&v).with_context(|| format!("Decoding block {:?}", hash)))
+            .transpose()
     }
 
     async fn get_latest_block_hash_for_account(
         &self,
-        _account: &Public,
+        account: &Public,
     ) -> anyhow::Result<Option<BlockHash>> {
-        unimplemented!()
+        Ok(self
+            .frontiers
+            .get(account.as_bytes())?
+            .map(|v| decode_frontier(&v).map(|(hash, _)| hash))
+            .transpose()?)
+    }
+
+    async fn frontiers(&self, start: &Public, count: usize) -> anyhow::Result<Vec<Frontier>> {
+        let mut frontiers = vec![];
+        for entry in self.frontiers.range(start.as_bytes()..).take(count) {
+            let (account, value) = entry?;
+            let (hash, modified) = decode_frontier(&value)?;
+            frontiers.push(Frontier {
+                account: Public::try_from(account.as_ref())?,
+                hash,
+                modified,
+            });
+        }
+        Ok(frontiers)
+    }
+
+    async fn block_count(&self) -> anyhow::Result<u64> {
+        Ok(self.blocks.len() as u64)
+    }
+
+    async fn account_count(&self) -> anyhow::Result<u64> {
+        Ok(self.frontiers.len() as u64)
     }
 
     async fn account_for_block_hash(
         &mut self,
-        _block_hash: &BlockHash,
+        block_hash: &BlockHash,
     ) -> Result<Option<Public>, anyhow::Error> {
-        unimplemented!()
+        Ok(self
+            .block_account
+            .get(block_hash.as_bytes())?
+            .map(|v| Public::try_from(v.as_ref()))
+            .transpose()?)
+    }
+
+    async fn representative_weight(&self, representative: &Public) -> anyhow::Result<Raw> {
+        Ok(self
+            .representative_weights
+            .get(representative.as_bytes())?
+            .map(|v| Raw::try_from(v.as_ref()))
+            .transpose()?
+            .unwrap_or_else(Raw::zero))
+    }
+
+    async fn account_epoch(&self, account: &Public) -> anyhow::Result<Epoch> {
+        Ok(match self.account_epochs.get(account.as_bytes())? {
+            Some(v) => Epoch::try_from(*v.first().context("Empty epoch")?)?,
+            None => Epoch::Epoch0,
+        })
+    }
+
+    fn network(&self) -> &Network {
+        &self.network
+    }
+
+    async fn block_epoch(&self, hash: &BlockHash) -> anyhow::Result<Epoch> {
+        Ok(match self.block_epochs.get(hash.as_bytes())? {
+            Some(v) => Epoch::try_from(*v.first().context("Empty epoch")?)?,
+            None => Epoch::Epoch0,
+        })
+    }
+
+    async fn block_height(&self, hash: &BlockHash) -> anyhow::Result<Option<u64>> {
+        self.block_heights
+            .get(hash.as_bytes())?
+            .map(|v| decode_height(&v))
+            .transpose()
+    }
+
+    async fn confirmation_height(
+        &self,
+        account: &Public,
+    ) -> anyhow::Result<Option<ConfirmationHeight>> {
+        self.confirmation_heights
+            .get(account.as_bytes())?
+            .map(|v| decode_confirmation_height(&v))
+            .transpose()
+    }
+
+    async fn set_confirmation_height(
+        &mut self,
+        account: &Public,
+        confirmation_height: &ConfirmationHeight,
+    ) -> anyhow::Result<()> {
+        let mut value = confirmation_height.height.to_be_bytes().to_vec();
+        value.extend_from_slice(confirmation_height.frontier.as_bytes());
+        self.confirmation_heights
+            .insert(account.as_bytes(), value)?;
+        Ok(())
+    }
+
+    async fn cemented_count(&self) -> anyhow::Result<u64> {
+        let mut count = 0;
+        for item in self.confirmation_heights.iter() {
+            let (_, value) = item?;
+            count += decode_confirmation_height(&value)?.height;
+        }
+        Ok(count)
     }
 
     async fn add_vote(
         &mut self,
-        _hash: &BlockHash,
-        _representative: &Public,
+        hash: &BlockHash,
+        representative: &Public,
+        timestamp: u64,
     ) -> anyhow::Result<()> {
-        unimplemented!()
+        self.votes
+            .insert(vote_key(hash, representative), &timestamp.to_be_bytes())?;
+        Ok(())
+    }
+
+    async fn vote_timestamp(
+        &self,
+        hash: &BlockHash,
+        representative: &Public,
+    ) -> anyhow::Result<Option<u64>> {
+        Ok(match self.votes.get(vote_key(hash, representative))? {
+            Some(value) => Some(u64::from_be_bytes(
+                <[u8; 8]>::try_from(&*value).context("Vote timestamp")?,
+            )),
+            None => None,
+        })
     }
 
     async fn set_cookie(&mut self, socket_addr: SocketAddr, cookie: Cookie) -> anyhow::Result<()> {
@@ -82,11 +400,625 @@ impl State for SledDiskState {
         })
     }
 
-    async fn add_peers(&mut self, _addresses: &[SocketAddr]) -> Result<(), anyhow::Error> {
-        unimplemented!()
+    async fn add_peers(&mut self, addresses: &[SocketAddr]) -> Result<(), anyhow::Error> {
+        for address in addresses {
+            self.peers.insert(format!("{}", address), &[])?;
+        }
+        Ok(())
     }
 
     async fn peers(&self) -> Result<HashSet<SocketAddr>, anyhow::Error> {
-        unimplemented!()
+        let mut peers = HashSet::new();
+        for entry in self.peers.iter() {
+            let (key, _) = entry?;
+            let s = std::str::from_utf8(&key).context("Peer key is not UTF-8")?;
+            peers.insert(SocketAddr::from_str(s).with_context(|| format!("Peer key: {}", s))?);
+        }
+        Ok(peers)
+    }
+}
+
+/// The latest block hash and modified time of a `frontiers` value.
+fn decode_frontier(value: &[u8]) -> anyhow::Result<(BlockHash, u64)> {
+    if value.len() != BlockHash::LEN + 8 {
+        return Err(anyhow!("Bad frontier length: {}", value.len()));
+    }
+    let (hash, modified) = value.split_at(BlockHash::LEN);
+    Ok((
+        BlockHash::try_from(hash)?,
+        u64::from_be_bytes(<[u8; 8]>::try_from(modified)?),
+    ))
+}
+
+fn decode_confirmation_height(value: &[u8]) -> anyhow::Result<ConfirmationHeight> {
+    if value.len() != 8 + BlockHash::LEN {
+        return Err(anyhow!("Bad confirmation height length: {}", value.len()));
+    }
+    let (height, frontier) = value.split_at(8);
+    Ok(ConfirmationHeight {
+        height: u64::from_be_bytes(<[u8; 8]>::try_from(height)?),
+        frontier: BlockHash::try_from(frontier)?,
+    })
+}
+
+fn vote_key(hash: &BlockHash, representative: &Public) -> Vec<u8> {
+    let mut key = Vec::with_capacity(BlockHash::LEN + Public::LEN);
+    key.extend_from_slice(hash.as_bytes());
+    key.extend_from_slice(representative.as_bytes());
+    key
+}
+
+/// Make sure the ledger is still how `change` was worked out: the block follows the head of
+/// its account, and anything it receives hasn't been received yet.
+fn check_add(
+    frontiers: &TransactionalTree,
+    receivables: &TransactionalTree,
+    block: &Block,
+    hash: &BlockHash,
+    change: &LedgerChange,
+) -> ConflictableTransactionResult<(), String> {
+    let head = frontiers.get(block.account().as_bytes())?;
+    let head = head.as_ref().and_then(|v| v.get(..BlockHash::LEN));
+    let previous = match block.previous() {
+        Previous::Block(previous) => Some(previous.as_bytes()),
+        Previous::Open => None,
+    };
+    if head != previous {
+        return Err(ConflictableTransactionError::Abort(format!(
+            "Block {:?} doesn't follow the head of its account",
+            hash
+        )));
+    }
+    if let ReceivableChange::Remove {
+        destination,
+        send_hash,
+    } = &change.receivable
+    {
+        if receivables
+            .get(receivable_key(destination, send_hash))?
+            .is_none()
+        {
+            return Err(ConflictableTransactionError::Abort(format!(
+                "Block {:?} receives {:?}, which isn't receivable",
+                hash, send_hash
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn apply_receivable_change(
+    receivables: &TransactionalTree,
+    change: &ReceivableChange,
+) -> Result<(), UnabortableTransactionError> {
+    match change {
+        ReceivableChange::None => {}
+        ReceivableChange::Add {
+            destination,
+            send_hash,
+            receivable,
+        } => {
+            receivables.insert(
+                receivable_key(destination, send_hash),
+                encode_receivable(receivable),
+            )?;
+        }
+        ReceivableChange::Remove {
+            destination,
+            send_hash,
+        } => {
+            receivables.remove(receivable_key(destination, send_hash))?;
+        }
+    }
+    Ok(())
+}
+
+/// Weights of zero aren't stored.
+fn apply_weight_changes(
+    weights: &TransactionalTree,
+    changes: &[WeightChange],
+) -> ConflictableTransactionResult<(), String> {
+    for change in changes {
+        let rep = change.representative();
+        let weight = match weights.get(rep.as_bytes())? {
+            Some(v) => Raw::try_from(v.as_ref())
+                .map_err(|err| ConflictableTransactionError::Abort(format!("{:?}", err)))?,
+            None => Raw::zero(),
+        };
+        let weight = change
+            .apply(&weight)
+            .map_err(|err| ConflictableTransactionError::Abort(err.to_string()))?;
+        if weight == Raw::zero() {
+            weights.remove(rep.as_bytes())?;
+        } else {
+            weights.insert(rep.as_bytes(), weight.to_vec())?;
+        }
+    }
+    Ok(())
+}
+
+/// [Epoch::Epoch0] isn't stored.
+fn apply_epoch(
+    epochs: &TransactionalTree,
+    account: &Public,
+    epoch: &Option<Epoch>,
+) -> Result<(), UnabortableTransactionError> {
+    match epoch {
+        Some(Epoch::Epoch0) => {
+            epochs.remove(account.as_bytes())?;
+        }
+        Some(epoch) => {
+            epochs.insert(account.as_bytes(), &[*epoch as u8])?;
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+fn decode_height(value: &[u8]) -> anyhow::Result<u64> {
+    Ok(u64::from_be_bytes(
+        <[u8; 8]>::try_from(value).context("Block height")?,
+    ))
+}
+
+fn receivable_key(destination: &Public, send_hash: &BlockHash) -> Vec<u8> {
+    let mut key = Vec::with_capacity(Public::LEN + BlockHash::LEN);
+    key.extend_from_slice(destination.as_bytes());
+    key.extend_from_slice(send_hash.as_bytes());
+    key
+}
+
+fn encode_receivable(receivable: &Receivable) -> Vec<u8> {
+    let mut v = receivable.amount.to_vec();
+    v.extend_from_slice(receivable.source.as_bytes());
+    v.push(receivable.epoch as u8);
+    v
+}
+
+fn decode_receivable(data: &[u8]) -> anyhow::Result<Receivable> {
+    let mut data = Bytes::new(data);
+    let amount = Raw::try_from(data.slice(Raw::LEN)?)?;
+    let source = Public::try_from(data.slice(Public::LEN)?)?;
+    let epoch = Epoch::try_from(data.u8()?)?;
+    Ok(Receivable {
+        amount,
+        source,
+        epoch,
+    })
+}
+
+const LINK_NOTHING: u8 = 0;
+const LINK_UNSURE: u8 = 1;
+const LINK_SOURCE: u8 = 2;
+const LINK_DESTINATION: u8 = 3;
+
+/// Encode a [Block] for storage:
+///
+/// `type, account, previous, representative, balance, link type, link, validation state,
+/// has signature, signature, has work, work`
+///
+/// Missing signatures and work are zero filled so that every stored block is the same length.
+fn encode_block(block: &Block) -> Vec<u8> {
+    let mut v = Vec::with_capacity(ENCODED_BLOCK_LEN);
+    v.push(block.block_type().as_u8());
+    v.extend_from_slice(block.account().as_bytes());
+    v.extend_from_slice(&block.previous().to_bytes());
+    v.extend_from_slice(block.representative().as_bytes());
+    v.extend_from_slice(&block.balance().to_vec());
+    v.push(match block.link() {
+        Link::Nothing => LINK_NOTHING,
+        Link::Unsure(_) => LINK_UNSURE,
+        Link::Source(_) => LINK_SOURCE,
+        Link::DestinationAccount(_) => LINK_DESTINATION,
+    });
+    v.extend_from_slice(block.link().as_bytes());
+    v.push(match block.validation_state() {
+        ValidationState::Published => 0,
+        ValidationState::PresumedValid => 1,
+        ValidationState::Valid => 2,
+        ValidationState::SignatureFailed => 3,
+        ValidationState::WorkFailed => 4,
+    });
+    match block.signature() {
+        Some(signature) => {
+            v.push(1);
+            v.extend_from_slice(signature.as_bytes());
+        }
+        None => {
+            v.push(0);
+            v.extend_from_slice(Signature::zero().as_bytes());
+        }
+    }
+    match block.work() {
+        Some(work) => {
+            v.push(1);
+            v.extend_from_slice(work.as_bytes());
+        }
+        None => {
+            v.push(0);
+            v.extend_from_slice(Work::zero().as_bytes());
+        }
+    }
+    v
+}
+
+const ENCODED_BLOCK_LEN: usize = 1
+    + Public::LEN
+    + BlockHash::LEN
+    + Public::LEN
+    + Raw::LEN
+    + 1
+    + Link::LEN
+    + 1
+    + 1
+    + Signature::LEN
+    + 1
+    + Work::LEN;
+
+fn decode_block(data: &[u8]) -> anyhow::Result<Block> {
+    let mut data = Bytes::new(data);
+    let block_type = BlockType::try_from(data.u8()?)?;
+    let account = Public::try_from(data.slice(Public::LEN)?)?;
+    let previous = Previous::try_from(data.slice(BlockHash::LEN)?)?;
+    let representative = Public::try_from(data.slice(Public::LEN)?)?;
+    let balance = Raw::try_from(data.slice(Raw::LEN)?)?;
+    let link_type = data.u8()?;
+    let link_data = data.slice(Link::LEN)?;
+    let link = match link_type {
+        LINK_NOTHING => Link::Nothing,
+        LINK_UNSURE => Link::Unsure(UnsureLink::try_from(link_data)?),
+        LINK_SOURCE => Link::Source(BlockHash::try_from(link_data)?),
+        LINK_DESTINATION => Link::DestinationAccount(Public::try_from(link_data)?),
+        v => return Err(anyhow!("Unknown stored link type: {}", v)),
+    };
+    let state = match data.u8()? {
+        0 => ValidationState::Published,
+        1 => ValidationState::PresumedValid,
+        2 => ValidationState::Valid,
+        3 => ValidationState::SignatureFailed,
+        4 => ValidationState::WorkFailed,
+        v => return Err(anyhow!("Unknown stored validation state: {}", v)),
+    };
+
+    let mut block = Block::new(
+        block_type,
+        account,
+        previous,
+        representative,
+        balance,
+        link,
+        state,
+    );
+    let has_signature = data.u8()? == 1;
+    let signature = Signature::try_from(data.slice(Signature::LEN)?)?;
+    if has_signature {
+        block.set_signature(signature);
+    }
+    let has_work = data.u8()? == 1;
+    let work = Work::try_from(data.slice(Work::LEN)?)?;
+    if has_work {
+        block.set_work(work);
+    }
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_encoding() {
+        let genesis = Network::Live.genesis_block();
+        let encoded = encode_block(&genesis);
+        assert_eq!(encoded.len(), ENCODED_BLOCK_LEN);
+        assert_eq!(decode_block(&encoded).unwrap(), genesis);
+    }
+
+    #[tokio::test]
+    async fn blocks() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let hash = genesis.hash().unwrap();
+        let mut state = SledDiskState::temporary(network).unwrap();
+
+        assert!(state.get_block_by_hash(hash).await.unwrap().is_none());
+        state
+            .add_block(&genesis, &LedgerChange::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            state.get_block_by_hash(hash).await.unwrap().unwrap(),
+            genesis
+        );
+        assert_eq!(
+            &state
+                .get_latest_block_hash_for_account(genesis.account())
+                .await
+                .unwrap()
+                .unwrap(),
+            hash
+        );
+        assert_eq!(
+            &state.account_for_block_hash(hash).await.unwrap().unwrap(),
+            genesis.account()
+        );
+    }
+
+    #[tokio::test]
+    async fn bad_frontiers() {
+        let network = Network::Live;
+        let hash = network.genesis_hash();
+        let account = Public::zero();
+        let state = SledDiskState::temporary(network).unwrap();
+
+        // A frontier is always a hash and a modified time.
+        for value in &[hash.as_bytes(), &hash.as_bytes()[..8]] {
+            state.frontiers.insert(account.as_bytes(), *value).unwrap();
+            assert!(state
+                .get_latest_block_hash_for_account(&account)
+                .await
+                .is_err());
+            assert!(state.frontiers(&account, 10).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn block_heights() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let hash = genesis.hash().unwrap();
+        let send = Block::new(
+            BlockType::State,
+            genesis.account().to_owned(),
+            Previous::Block(hash.to_owned()),
+            genesis.representative().to_owned(),
+            Raw::from(1),
+            Link::DestinationAccount(Public::zero()),
+            ValidationState::Valid,
+        );
+        let send_hash = send.hash().unwrap();
+        let mut state = SledDiskState::temporary(network).unwrap();
+        for block in &[&genesis, &send] {
+            state
+                .add_block(block, &LedgerChange::default())
+                .await
+                .unwrap();
+        }
+        assert_eq!(state.block_height(hash).await.unwrap(), Some(1));
+        assert_eq!(state.block_height(send_hash).await.unwrap(), Some(2));
+
+        state
+            .remove_block(&send, &LedgerChange::default())
+            .await
+            .unwrap();
+        assert_eq!(state.block_height(send_hash).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn weights_and_epochs() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let hash = genesis.hash().unwrap();
+        let account = genesis.account();
+        let rep = genesis.representative();
+        let mut state = SledDiskState::temporary(network).unwrap();
+        assert_eq!(state.representative_weight(rep).await.unwrap(), 0);
+        assert_eq!(state.account_epoch(account).await.unwrap(), Epoch::Epoch0);
+
+        let weights = WeightChange::for_block(&genesis, None);
+        let change = LedgerChange {
+            epoch: Some(Epoch::Epoch2),
+            weights: weights.to_owned(),
+            ..Default::default()
+        };
+        state.add_block(&genesis, &change).await.unwrap();
+        assert_eq!(state.representative_weight(rep).await.unwrap(), Raw::max());
+        assert_eq!(state.account_epoch(account).await.unwrap(), Epoch::Epoch2);
+        assert_eq!(state.block_epoch(hash).await.unwrap(), Epoch::Epoch2);
+
+        // Taking away more weight than a representative has leaves everything as it was.
+        let other = Public::zero();
+        let invalid = LedgerChange {
+            epoch: Some(Epoch::Epoch0),
+            weights: vec![
+                WeightChange::Add(other.to_owned(), Raw::from(1)),
+                WeightChange::Subtract(rep.to_owned(), Raw::max()),
+                WeightChange::Subtract(rep.to_owned(), Raw::from(1)),
+            ],
+            ..Default::default()
+        };
+        assert!(state.remove_block(&genesis, &invalid).await.is_err());
+        assert!(state.get_block_by_hash(hash).await.unwrap().is_some());
+        assert_eq!(state.representative_weight(rep).await.unwrap(), Raw::max());
+        assert_eq!(state.representative_weight(&other).await.unwrap(), 0);
+        assert_eq!(state.account_epoch(account).await.unwrap(), Epoch::Epoch2);
+
+        let undo = LedgerChange {
+            epoch: Some(Epoch::Epoch0),
+            weights: weights.iter().map(WeightChange::inverse).collect(),
+            ..Default::default()
+        };
+        state.remove_block(&genesis, &undo).await.unwrap();
+        assert!(state.representative_weights.is_empty());
+        assert!(state.account_epochs.is_empty());
+        assert!(state.block_epochs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn confirmation_heights() {
+        let mut state = SledDiskState::temporary(Network::Live).unwrap();
+        let account = Public::zero();
+        assert_eq!(state.confirmation_height(&account).await.unwrap(), None);
+
+        let confirmation_height = ConfirmationHeight {
+            height: 3,
+            frontier: Network::Live.genesis_hash(),
+        };
+        state
+            .set_confirmation_height(&account, &confirmation_height)
+            .await
+            .unwrap();
+        state
+            .set_confirmation_height(
+                Network::Live.genesis_block().account(),
+                &ConfirmationHeight {
+                    height: 1,
+                    frontier: Network::Live.genesis_hash(),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            state.confirmation_height(&account).await.unwrap(),
+            Some(confirmation_height)
+        );
+        assert_eq!(state.cemented_count().await.unwrap(), 4);
+    }
+
+    #[tokio::test]
+    async fn receivables() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let mut state = SledDiskState::temporary(network).unwrap();
+        let destination = Public::zero();
+        let send_hash = genesis.hash().unwrap().to_owned();
+        let receivable = Receivable {
+            amount: Raw::from(1000),
+            source: genesis.account().to_owned(),
+            epoch: Epoch::Epoch1,
+        };
+
+        state
+            .add_block(
+                &genesis,
+                &LedgerChange {
+                    receivable: ReceivableChange::Add {
+                        destination: destination.to_owned(),
+                        send_hash: send_hash.to_owned(),
+                        receivable: receivable.to_owned(),
+                    },
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(state.get_block_by_hash(&send_hash).await.unwrap().is_some());
+        assert_eq!(
+            state.receivable(&destination, &send_hash).await.unwrap(),
+            Some(receivable.to_owned())
+        );
+        assert_eq!(
+            state.receivables(&destination, 10).await.unwrap(),
+            vec![(send_hash.to_owned(), receivable.to_owned())]
+        );
+        assert!(state
+            .receivables(genesis.account(), 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let open_with_balance = |balance| {
+            Block::new(
+                BlockType::State,
+                destination.to_owned(),
+                Previous::Open,
+                genesis.representative().to_owned(),
+                Raw::from(balance),
+                Link::Source(send_hash.to_owned()),
+                ValidationState::Valid,
+            )
+        };
+        let open = open_with_balance(1000);
+        let receive = LedgerChange {
+            receivable: ReceivableChange::Remove {
+                destination: destination.to_owned(),
+                send_hash: send_hash.to_owned(),
+            },
+            ..Default::default()
+        };
+        state.add_block(&open, &receive).await.unwrap();
+        assert_eq!(
+            state.receivable(&destination, &send_hash).await.unwrap(),
+            None
+        );
+
+        // Receiving it again, or forking the account, is rejected.
+        let fork = open_with_balance(1);
+        assert!(state.add_block(&fork, &receive).await.is_err());
+        assert!(state
+            .add_block(&fork, &LedgerChange::default())
+            .await
+            .is_err());
+        assert!(state
+            .get_block_by_hash(fork.hash().unwrap())
+            .await
+            .unwrap()
+            .is_none());
+
+        // Removing the block puts back the entry it received.
+        state
+            .remove_block(
+                &open,
+                &LedgerChange {
+                    receivable: ReceivableChange::Add {
+                        destination: destination.to_owned(),
+                        send_hash: send_hash.to_owned(),
+                        receivable: receivable.to_owned(),
+                    },
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(state
+            .get_block_by_hash(open.hash().unwrap())
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            state
+                .get_latest_block_hash_for_account(&destination)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(state.account_count().await.unwrap(), 1);
+        assert_eq!(
+            state.receivable(&destination, &send_hash).await.unwrap(),
+            Some(receivable)
+        );
+    }
+
+    #[tokio::test]
+    async fn votes() {
+        let network = Network::Live;
+        let hash = network.genesis_hash();
+        let rep = Public::zero();
+        let mut state = SledDiskState::temporary(network).unwrap();
+        assert_eq!(state.vote_timestamp(&hash, &rep).await.unwrap(), None);
+
+        state.add_vote(&hash, &rep, 42).await.unwrap();
+        assert_eq!(state.vote_timestamp(&hash, &rep).await.unwrap(), Some(42));
+
+        state.votes.insert(vote_key(&hash, &rep), &[1u8]).unwrap();
+        assert!(state.vote_timestamp(&hash, &rep).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn peers() {
+        let mut state = SledDiskState::temporary(Network::Live).unwrap();
+        let addrs = vec![
+            SocketAddr::from_str("127.0.0.1:7075").unwrap(),
+            SocketAddr::from_str("[::ffff:1.2.3.4]:7075").unwrap(),
+        ];
+        state.add_peers(&addrs).await.unwrap();
+        state.add_peers(&addrs[0..1]).await.unwrap();
+
+        let peers = state.peers().await.unwrap();
+        assert_eq!(peers.len(), 2);
+        for addr in addrs {
+            assert!(peers.contains(&addr));
+        }
     }
 }
@@ -8,6 +8,7 @@ pub struct Timestamp(u64);
 
 impl Timestamp {
     pub const LEN: usize = 8;
+    const SEQUENCE_MAX: u64 = 1_577_836_800_000;
 
     pub fn now() -> Self {
         let start = SystemTime::now();
@@ -21,10 +22,26 @@ impl Timestamp {
         Self(s)
     }
 
-    fn to_u64(&self) -> u64 {
+    pub fn to_u64(&self) -> u64 {
         self.0
     }
 
+    /// The timestamp of a final vote.
+    pub fn final_vote() -> Self {
+        Self(u64::MAX)
+    }
+
+    /// A final vote, which can't be replaced by a later one.
+    pub fn is_final(&self) -> bool {
+        self.0 == u64::MAX
+    }
+
+    /// Older nodes send an incrementing sequence number instead of the time. Anything before
+    /// 2020 in milliseconds is taken to be a sequence.
+    pub fn is_sequence(&self) -> bool {
+        self.0 < Self::SEQUENCE_MAX
+    }
+
     pub fn to_bytes(&self) -> [u8; Self::LEN] {
         self.0.to_le_bytes()
     }
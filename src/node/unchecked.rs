@@ -0,0 +1,195 @@
+//! Blocks that arrived before a block they depend on, waiting until it's in our ledger.
+//!
+//! A block is kept under the hash of its missing previous or source block. When that block is
+//! added, the waiting blocks are taken out and handled again. Blocks that wait too long are
+//! forgotten, since their dependency may never arrive.
+use crate::blocks::{BlockHash, BlockHolder};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+pub type ArcUnchecked = Arc<Mutex<Unchecked>>;
+
+/// How long a block waits for its dependency.
+pub const UNCHECKED_TIMEOUT: Duration = Duration::from_secs(4 * 60 * 60);
+
+/// The most blocks waiting at once. The oldest is dropped to make room.
+pub const UNCHECKED_SIZE: usize = 65_536;
+
+#[derive(Debug)]
+struct Waiting {
+    hash: BlockHash,
+    holder: BlockHolder,
+    serial: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Unchecked {
+    /// Waiting blocks by the hash of the block they need.
+    waiting: HashMap<BlockHash, Vec<Waiting>>,
+
+    /// When each block arrived, with its dependency and serial, oldest first. [Unchecked::take]
+    /// leaves its blocks here, so an entry only counts while a block with its serial waits.
+    order: VecDeque<(Instant, BlockHash, u64)>,
+
+    len: usize,
+
+    /// Tells a block apart from earlier arrivals of the same block.
+    serial: u64,
+}
+
+impl Unchecked {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep `holder` until `dependency` arrives. Returns false if it's already waiting.
+    pub fn insert(&mut self, dependency: BlockHash, holder: BlockHolder, now: Instant) -> bool {
+        self.expire(now);
+
+        let hash = holder.hash();
+        let waiting = self.waiting.entry(dependency.to_owned()).or_default();
+        if waiting.iter().any(|w| w.hash == hash) {
+            return false;
+        }
+        waiting.push(Waiting {
+            hash: hash.to_owned(),
+            holder,
+            serial: self.serial,
+        });
+        self.order.push_back((now, dependency, self.serial));
+        self.serial += 1;
+        self.len += 1;
+
+        while self.len > UNCHECKED_SIZE {
+            match self.order.pop_front() {
+                Some((_, dependency, serial)) => self.remove(&dependency, serial),
+                None => break,
+            }
+        }
+        if self.order.len() > 2 * UNCHECKED_SIZE {
+            let waiting = &self.waiting;
+            self.order
+                .retain(|(_, dependency, serial)| is_waiting(waiting, dependency, *serial));
+        }
+        true
+    }
+
+    /// The blocks that were waiting for `dependency`, which has now arrived.
+    pub fn take(&mut self, dependency: &BlockHash) -> Vec<BlockHolder> {
+        let waiting = self.waiting.remove(dependency).unwrap_or_default();
+        self.len -= waiting.len();
+        waiting.into_iter().map(|w| w.holder).collect()
+    }
+
+    /// Forget blocks that have waited longer than [UNCHECKED_TIMEOUT].
+    pub fn expire(&mut self, now: Instant) {
+        while let Some((arrived, _, _)) = self.order.front() {
+            if now.saturating_duration_since(*arrived) < UNCHECKED_TIMEOUT {
+                break;
+            }
+            if let Some((_, dependency, serial)) = self.order.pop_front() {
+                self.remove(&dependency, serial);
+            }
+        }
+    }
+
+    /// The number of waiting blocks.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Forget a block, unless it was taken and has arrived again since.
+    fn remove(&mut self, dependency: &BlockHash, serial: u64) {
+        let waiting = match self.waiting.get_mut(dependency) {
+            Some(waiting) => waiting,
+            None => return,
+        };
+        if let Some(index) = waiting.iter().position(|w| w.serial == serial) {
+            waiting.remove(index);
+            self.len -= 1;
+        }
+        if waiting.is_empty() {
+            self.waiting.remove(dependency);
+        }
+    }
+}
+
+fn is_waiting(
+    waiting: &HashMap<BlockHash, Vec<Waiting>>,
+    dependency: &BlockHash,
+    serial: u64,
+) -> bool {
+    waiting
+        .get(dependency)
+        .map(|waiting| waiting.iter().any(|w| w.serial == serial))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::ChangeBlock;
+    use crate::Public;
+    use std::str::FromStr;
+
+    fn hash(n: u8) -> BlockHash {
+        BlockHash::from_str(&format!("{:064X}", n)).unwrap()
+    }
+
+    fn change(previous: u8) -> BlockHolder {
+        BlockHolder::Change(ChangeBlock::new(hash(previous), Public::zero()))
+    }
+
+    #[test]
+    fn waits_for_dependency() {
+        let now = Instant::now();
+        let mut unchecked = Unchecked::new();
+        assert!(unchecked.insert(hash(1), change(1), now));
+        assert!(!unchecked.insert(hash(1), change(1), now));
+        assert!(unchecked.insert(hash(2), change(2), now));
+        assert_eq!(unchecked.len(), 2);
+
+        assert_eq!(unchecked.take(&hash(1)), vec![change(1)]);
+        assert!(unchecked.take(&hash(1)).is_empty());
+        assert_eq!(unchecked.len(), 1);
+
+        // The taken block doesn't disturb expiry of the rest.
+        unchecked.expire(now + UNCHECKED_TIMEOUT);
+        assert!(unchecked.is_empty());
+        assert!(unchecked.take(&hash(2)).is_empty());
+    }
+
+    #[test]
+    fn taken_blocks_arriving_again() {
+        let now = Instant::now();
+        let later = now + UNCHECKED_TIMEOUT / 2;
+        let mut unchecked = Unchecked::new();
+        assert!(unchecked.insert(hash(1), change(1), now));
+        assert_eq!(unchecked.take(&hash(1)), vec![change(1)]);
+        assert!(unchecked.insert(hash(1), change(1), later));
+
+        // The first arrival's timeout doesn't apply to the second.
+        unchecked.expire(now + UNCHECKED_TIMEOUT);
+        assert_eq!(unchecked.len(), 1);
+        unchecked.expire(later + UNCHECKED_TIMEOUT);
+        assert!(unchecked.is_empty());
+    }
+
+    #[test]
+    fn stale_order_is_compacted() {
+        let now = Instant::now();
+        let mut unchecked = Unchecked::new();
+        for _ in 0..=2 * UNCHECKED_SIZE {
+            unchecked.insert(hash(1), change(1), now);
+            unchecked.take(&hash(1));
+        }
+        assert!(unchecked.is_empty());
+        assert!(unchecked.order.len() <= 2 * UNCHECKED_SIZE);
+    }
+}
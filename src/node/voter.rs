@@ -0,0 +1,163 @@
+//! Voting as a representative, when the node has been given a representative's private key.
+//!
+//! Other nodes ask for our votes with confirm requests. We answer with a vote for the block we
+//! think should win each root. Once a block is confirmed we switch to a final vote for it, and
+//! from then on only ever vote for that block for its root, even if asked about a fork.
+use crate::blocks::BlockHash;
+use crate::node::elections::Root;
+use crate::node::messages::confirm_ack::{Confirm, ConfirmAck};
+use crate::node::timestamp::Timestamp;
+use crate::{Private, Public, Signature};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+pub type ArcVoter = Arc<Mutex<Voter>>;
+
+/// The most hashes we put in one vote. The header has room for 15.
+pub const MAX_VOTE_HASHES: usize = 12;
+
+/// How many roots we remember final votes for.
+pub const FINAL_VOTES_SIZE: usize = 65_536;
+
+/// A block we've decided to vote for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub root: Root,
+    pub hash: BlockHash,
+
+    /// The block is confirmed, so the vote should be final.
+    pub confirmed: bool,
+}
+
+#[derive(Debug)]
+pub struct Voter {
+    key: Private,
+    account: Public,
+
+    /// The block we gave a final vote to for each root, oldest first.
+    final_votes: HashMap<Root, BlockHash>,
+    final_order: VecDeque<Root>,
+}
+
+impl Voter {
+    pub fn new(key: Private) -> anyhow::Result<Self> {
+        let account = key.to_public()?;
+        Ok(Self {
+            key,
+            account,
+            final_votes: HashMap::new(),
+            final_order: VecDeque::new(),
+        })
+    }
+
+    /// The representative account we vote as.
+    pub fn account(&self) -> &Public {
+        &self.account
+    }
+
+    /// Which block to vote for, and whether the vote is final. A final vote can't be taken back,
+    /// so a root that already has one keeps it.
+    pub fn decide(&mut self, candidate: Candidate) -> (BlockHash, bool) {
+        if let Some(hash) = self.final_votes.get(&candidate.root) {
+            return (hash.to_owned(), true);
+        }
+        if !candidate.confirmed {
+            return (candidate.hash, false);
+        }
+
+        self.final_votes
+            .insert(candidate.root.clone(), candidate.hash.clone());
+        self.final_order.push_back(candidate.root);
+        if self.final_order.len() > FINAL_VOTES_SIZE {
+            if let Some(root) = self.final_order.pop_front() {
+                self.final_votes.remove(&root);
+            }
+        }
+        (candidate.hash, true)
+    }
+
+    /// Signed votes for the candidates, final and non-final ones separately, with at most
+    /// [MAX_VOTE_HASHES] hashes each.
+    pub fn votes(&mut self, candidates: Vec<Candidate>) -> anyhow::Result<Vec<ConfirmAck>> {
+        let mut normal = vec![];
+        let mut finals = vec![];
+        for candidate in candidates {
+            let (hash, is_final) = self.decide(candidate);
+            let hashes = if is_final { &mut finals } else { &mut normal };
+            if !hashes.contains(&hash) {
+                hashes.push(hash);
+            }
+        }
+
+        let mut votes = vec![];
+        for (hashes, timestamp) in &[
+            (normal, Timestamp::now()),
+            (finals, Timestamp::final_vote()),
+        ] {
+            for chunk in hashes.chunks(MAX_VOTE_HASHES) {
+                let mut vote = ConfirmAck::new(
+                    self.account.to_owned(),
+                    Signature::zero(),
+                    timestamp.to_owned(),
+                    Confirm::VoteByHash(chunk.to_vec()),
+                );
+                vote.sign(&self.key)?;
+                votes.push(vote);
+            }
+        }
+        Ok(votes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn hash(n: u8) -> BlockHash {
+        BlockHash::from_str(&format!("{:064X}", n)).unwrap()
+    }
+
+    fn candidate(root: u8, block: u8, confirmed: bool) -> Candidate {
+        Candidate {
+            root: Root::Previous(hash(root)),
+            hash: hash(block),
+            confirmed,
+        }
+    }
+
+    #[test]
+    fn votes() {
+        let key = Private::random();
+        let mut voter = Voter::new(key.to_owned()).unwrap();
+        let candidates = (0..20).map(|i| candidate(i, 100 + i, false)).collect();
+        let votes = voter.votes(candidates).unwrap();
+        assert_eq!(votes.len(), 2);
+        assert_eq!(votes[0].confirm.hashes().len(), MAX_VOTE_HASHES);
+        assert_eq!(votes[1].confirm.hashes().len(), 20 - MAX_VOTE_HASHES);
+        for vote in &votes {
+            assert_eq!(vote.account, key.to_public().unwrap());
+            assert!(vote.verify_signature().is_ok());
+            assert!(!vote.timestamp.is_final());
+        }
+    }
+
+    #[test]
+    fn final_votes_stick() {
+        let mut voter = Voter::new(Private::random()).unwrap();
+        assert_eq!(voter.decide(candidate(1, 2, false)), (hash(2), false));
+        assert_eq!(voter.decide(candidate(1, 2, true)), (hash(2), true));
+
+        // Asked about a fork of the same root.
+        assert_eq!(voter.decide(candidate(1, 3, true)), (hash(2), true));
+        let votes = voter
+            .votes(vec![candidate(1, 3, false), candidate(4, 5, false)])
+            .unwrap();
+        assert_eq!(votes.len(), 2);
+        assert_eq!(votes[0].confirm.hashes(), vec![hash(5)]);
+        assert_eq!(votes[1].confirm.hashes(), vec![hash(2)]);
+        assert!(votes[1].timestamp.is_final());
+        assert!(votes[1].verify_signature().is_ok());
+    }
+}
@@ -1,5 +1,5 @@
 //! Filesystem tools for finding out OS independent directory names etc.
-use crate::Network;
+use crate::{DevNetwork, Network};
 use clap::Clap;
 use directories::BaseDirs;
 use std::fs::create_dir_all;
@@ -8,16 +8,34 @@ use std::path::{Path, PathBuf};
 /// CLI options for [Paths].
 #[derive(Clap)]
 pub(crate) struct PathsOpts {
+    /// The network to use: live, beta or test.
     #[clap(short = 'n', long, default_value = "live")]
     network: Network,
 
+    /// Use a private dev network instead, from a config made by `feeless devnet genesis`.
+    #[clap(long, conflicts_with = "network")]
+    devnet: Option<PathBuf>,
+
+    /// Directory for the ledger, wallets and node ID. Defaults to the OS's local data directory.
     #[clap(long, env = "FEELESS_DATA_DIR")]
     data_dir: Option<PathBuf>,
 }
 
 impl PathsOpts {
+    /// The network from `--network`, or the one in the `--devnet` config.
+    pub fn network(&self) -> anyhow::Result<Network> {
+        Ok(match &self.devnet {
+            Some(path) => DevNetwork::load(path)?.into_network(),
+            None => self.network,
+        })
+    }
+
+    pub fn paths(&self, network: Network) -> Paths {
+        Paths::new_maybe_custom(network, self.data_dir.clone())
+    }
+
     pub fn wallet_path(&self) -> anyhow::Result<PathBuf> {
-        let p = Paths::new_maybe_custom(self.network.clone(), self.data_dir.clone());
+        let p = self.paths(self.network()?);
         p.ensure_data_path()?;
         Ok(p.wallet_path())
     }
@@ -31,6 +49,7 @@ impl PathsOpts {
 /// For example:
 /// * /home/gak/.local/share/feeless/live/wallet.dat
 /// * C:\Users\gak\App Data\Local\feeless\live\wallet.dat
+/// * /home/gak/.local/share/feeless/dev-1b2c3d4e5f607182/wallet.dat for a dev network
 pub(crate) struct Paths {
     pub data: PathBuf,
 }
@@ -62,8 +81,13 @@ impl Paths {
         }
     }
 
+    /// Dev networks are told apart by their genesis block, so each gets its own ledger.
     fn network_path(network: Network) -> PathBuf {
-        network.to_string().to_ascii_lowercase().into()
+        match network {
+            Network::Dev(_) => format!("dev-{}", &network.genesis_hash().as_hex_lower()[..16]),
+            network => network.to_string().to_ascii_lowercase(),
+        }
+        .into()
     }
 
     /// Join the data path to the specified path. This will be OS dependant,
@@ -77,9 +101,53 @@ impl Paths {
         self.data_path(Path::new("wallet"))
     }
 
+    /// Return the path to the node's ledger database.
+    pub fn db_path(&self) -> PathBuf {
+        self.data_path(Path::new("db"))
+    }
+
+    /// Return the path to the private key of our node ID.
+    pub fn node_key_path(&self) -> PathBuf {
+        self.data_path(Path::new("node_id_private.key"))
+    }
+
     /// Make sure the data path exists.
     pub fn ensure_data_path(&self) -> anyhow::Result<()> {
         create_dir_all(&self.data)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Difficulty, Private};
+
+    #[test]
+    fn dev_networks_have_their_own_directory() {
+        let data = PathBuf::from("data");
+        let dev = || {
+            DevNetwork::generate(
+                &Private::random(),
+                DevNetwork::DEFAULT_MAGIC,
+                Difficulty::new(DevNetwork::DEFAULT_WORK_THRESHOLD),
+            )
+            .unwrap()
+            .into_network()
+        };
+        let (first, second) = (dev(), dev());
+        let path = Paths::new_custom(first, data.clone()).data;
+        assert_eq!(
+            path,
+            data.join(format!(
+                "dev-{}",
+                &first.genesis_hash().as_hex_lower()[..16]
+            ))
+        );
+        assert_ne!(path, Paths::new_custom(second, data.clone()).data);
+        assert_eq!(
+            Paths::new_custom(Network::Live, data.clone()).data,
+            data.join("live")
+        );
+    }
+}
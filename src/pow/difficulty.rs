@@ -1,4 +1,6 @@
+use crate::blocks::{Epoch, Subtype};
 use crate::encoding::{deserialize_from_str, expect_len, to_hex};
+use crate::network::Network;
 use crate::{Error, Result};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::cmp::Ordering;
@@ -17,12 +19,18 @@ impl Difficulty {
         Self(v)
     }
 
-    pub fn receive() -> Self {
-        Self::from_str("FFFFFE0000000000").unwrap()
+    /// The live network's threshold for receives and opens of an account at `epoch`.
+    pub fn receive(epoch: &Epoch) -> Self {
+        Network::Live
+            .work_thresholds()
+            .threshold(epoch, &Subtype::Receive)
     }
 
-    pub fn normal() -> Self {
-        Self::from_str("FFFFFFF800000000").unwrap()
+    /// The live network's threshold for sends and changes of an account at `epoch`.
+    pub fn normal(epoch: &Epoch) -> Self {
+        Network::Live
+            .work_thresholds()
+            .threshold(epoch, &Subtype::Send)
     }
 
     pub fn from_fixed_slice(s: &[u8; Self::LEN]) -> Result<Self> {
@@ -105,9 +113,22 @@ mod tests {
     }
 
     #[test]
-    fn dont_panic() {
-        // These have unwraps in them and so this is a sanity check to make sure it doesn't panic.
-        Difficulty::receive();
-        Difficulty::normal();
+    fn epochs() {
+        assert_eq!(
+            Difficulty::normal(&Epoch::Epoch2),
+            Difficulty::from_str("FFFFFFF800000000").unwrap()
+        );
+        assert_eq!(
+            Difficulty::receive(&Epoch::Epoch2),
+            Difficulty::from_str("FFFFFE0000000000").unwrap()
+        );
+        assert_eq!(
+            Difficulty::normal(&Epoch::Epoch1),
+            Difficulty::from_str("FFFFFFC000000000").unwrap()
+        );
+        assert_eq!(
+            Difficulty::receive(&Epoch::Epoch0),
+            Difficulty::normal(&Epoch::Epoch1)
+        );
     }
 }
@@ -1,3 +1,9 @@
+#[cfg(feature = "node")]
+use crate::node::ArcState;
+
+#[cfg(feature = "node")]
+use crate::rpc::StateHandler;
+
 use crate::rpc::client::{RPCClient, RPCRequest};
 use crate::{Address, Raw, Result};
 use async_trait::async_trait;
@@ -14,7 +20,7 @@ impl RPCRequest for &AccountWeightRequest {
     type Response = AccountWeightResponse;
 
     fn action(&self) -> &str {
-        "account_block_count"
+        "account_weight"
     }
 
     async fn call(&self, client: &RPCClient) -> Result<AccountWeightResponse> {
@@ -22,6 +28,22 @@ impl RPCRequest for &AccountWeightRequest {
     }
 }
 
+#[cfg(feature = "node")]
+#[async_trait]
+impl StateHandler for &AccountWeightRequest {
+    type Response = AccountWeightResponse;
+
+    async fn handle(&self, state: ArcState) -> Result<AccountWeightResponse> {
+        let weight = state
+            .lock()
+            .await
+            .representative_weight(&self.account.to_public())
+            .await
+            .map_err(|err| crate::Error::RPCError(format!("{:?}", err)))?;
+        Ok(AccountWeightResponse { weight })
+    }
+}
+
 impl AccountWeightRequest {
     pub fn new(account: Address) -> Self {
         Self { account }
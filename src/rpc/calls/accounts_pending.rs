@@ -1,3 +1,9 @@
+#[cfg(feature = "node")]
+use crate::node::ArcState;
+
+#[cfg(feature = "node")]
+use crate::rpc::StateHandler;
+
 use crate::blocks::BlockHash;
 use crate::rpc::client::{RPCClient, RPCRequest};
 use crate::{Address, Raw, Result};
@@ -44,6 +50,90 @@ impl RPCRequest for &AccountsPendingRequest {
     }
 }
 
+#[cfg(feature = "node")]
+#[async_trait]
+impl StateHandler for &AccountsPendingRequest {
+    type Response = AccountsPendingResponse;
+
+    // TODO: include_active needs access to elections.
+    async fn handle(&self, state: ArcState) -> Result<AccountsPendingResponse> {
+        let state = state.lock().await;
+        let mut all = HashMap::new();
+        for account in &self.accounts {
+            let mut entries = state
+                .receivables(&account.to_public(), usize::MAX)
+                .await
+                .map_err(|err| crate::Error::RPCError(format!("{:?}", err)))?;
+            if let Some(threshold) = &self.threshold {
+                entries.retain(|(_, receivable)| &receivable.amount >= threshold);
+            }
+            if self.include_only_confirmed {
+                let mut confirmed = vec![];
+                for (hash, receivable) in entries {
+                    let send = state
+                        .get_block_by_hash(&hash)
+                        .await
+                        .map_err(|err| crate::Error::RPCError(format!("{:?}", err)))?;
+                    let is_cemented = match &send {
+                        Some(send) => state
+                            .is_cemented(send)
+                            .await
+                            .map_err(|err| crate::Error::RPCError(format!("{:?}", err)))?,
+                        None => false,
+                    };
+                    if is_cemented {
+                        confirmed.push((hash, receivable));
+                    }
+                }
+                entries = confirmed;
+            }
+            if self.sorting {
+                entries.sort_by_key(|(_, r)| std::cmp::Reverse(r.amount.to_u128()));
+            }
+            entries.truncate(self.count as usize);
+            // Accounts without anything receivable are left out, like the reference node does.
+            if !entries.is_empty() {
+                all.insert(account.to_owned(), entries);
+            }
+        }
+
+        let blocks = all.into_iter();
+        Ok(if self.source {
+            AccountsPendingResponse::Source {
+                blocks: blocks
+                    .map(|(account, entries)| {
+                        let entries = entries.into_iter().map(|(hash, receivable)| {
+                            let entry = BlockEntry {
+                                amount: receivable.amount,
+                                source: receivable.source.to_address(),
+                            };
+                            (hash, entry)
+                        });
+                        (account, entries.collect())
+                    })
+                    .collect(),
+            }
+        } else if self.threshold.is_some() {
+            AccountsPendingResponse::Threshold {
+                blocks: blocks
+                    .map(|(account, entries)| {
+                        let entries = entries.into_iter().map(|(hash, r)| (hash, r.amount));
+                        (account, entries.collect())
+                    })
+                    .collect(),
+            }
+        } else {
+            AccountsPendingResponse::OnlyBlockHash {
+                blocks: blocks
+                    .map(|(account, entries)| {
+                        (account, entries.into_iter().map(|(hash, _)| hash).collect())
+                    })
+                    .collect(),
+            }
+        })
+    }
+}
+
 impl AccountsPendingRequest {
     pub fn new(accounts: Vec<Address>, count: u64) -> Self {
         Self {
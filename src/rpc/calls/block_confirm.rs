@@ -1,3 +1,9 @@
+#[cfg(feature = "node")]
+use crate::node::{NodeCommand, NodeCommandSender};
+
+#[cfg(feature = "node")]
+use crate::rpc::NodeHandler;
+
 use crate::blocks::BlockHash;
 use crate::rpc::calls::{as_str, from_str};
 use crate::rpc::client::{RPCClient, RPCRequest};
@@ -24,6 +30,29 @@ impl RPCRequest for &BlockConfirmRequest {
     }
 }
 
+#[cfg(feature = "node")]
+#[async_trait]
+impl NodeHandler for &BlockConfirmRequest {
+    type Response = BlockConfirmResponse;
+
+    async fn handle(&self, node_tx: NodeCommandSender) -> Result<BlockConfirmResponse> {
+        use tokio::sync::oneshot;
+        let (tx, rx) = oneshot::channel();
+        node_tx
+            .send(NodeCommand::BlockConfirm(self.hash.to_owned(), tx))
+            .await
+            .expect("TODO");
+        let found = rx
+            .await
+            .expect("TODO")
+            .map_err(|err| crate::Error::RPCError(format!("{:?}", err)))?;
+        if !found {
+            return Err(crate::Error::RPCError("Block not found".into()));
+        }
+        Ok(BlockConfirmResponse { started: 1 })
+    }
+}
+
 impl BlockConfirmRequest {
     pub fn new(hash: BlockHash) -> Self {
         Self { hash }
@@ -1,3 +1,16 @@
+#[cfg(feature = "node")]
+use crate::node::ArcState;
+
+#[cfg(feature = "node")]
+use crate::rpc::StateHandler;
+
+#[cfg(feature = "node")]
+use crate::blocks::{BlockType, Previous};
+#[cfg(feature = "node")]
+use crate::node::state_subtype;
+#[cfg(feature = "node")]
+use chrono::TimeZone;
+
 use crate::blocks::{BlockHash, BlockHolder, Subtype};
 use crate::rpc::calls::{as_str, from_str};
 use crate::rpc::client::{RPCClient, RPCRequest};
@@ -31,6 +44,61 @@ impl RPCRequest for &BlockInfoRequest {
     }
 }
 
+#[cfg(feature = "node")]
+#[async_trait]
+impl StateHandler for &BlockInfoRequest {
+    type Response = BlockInfoResponse;
+
+    async fn handle(&self, state: ArcState) -> Result<BlockInfoResponse> {
+        let rpc_error = |err: anyhow::Error| crate::Error::RPCError(format!("{:?}", err));
+        let state = state.lock().await;
+        let block = state
+            .get_block_by_hash(&self.hash)
+            .await
+            .map_err(rpc_error)?
+            .ok_or_else(|| crate::Error::RPCError("Block not found".into()))?;
+        let previous_balance = match block.previous() {
+            Previous::Block(previous) => state
+                .get_block_by_hash(previous)
+                .await
+                .map_err(rpc_error)?
+                .map(|p| p.balance().to_owned())
+                .ok_or_else(|| crate::Error::RPCError("Previous block not found".into()))?,
+            Previous::Open => Raw::zero(),
+        };
+        let balance = block.balance().to_owned();
+        let amount = balance
+            .checked_sub(&previous_balance)
+            .or_else(|| previous_balance.checked_sub(&balance))
+            .unwrap_or_else(Raw::zero);
+        let height = state
+            .block_height(&self.hash)
+            .await
+            .map_err(rpc_error)?
+            .unwrap_or(0);
+        let confirmed = state.is_cemented(&block).await.map_err(rpc_error)?;
+        let subtype = match block.block_type() {
+            BlockType::State => {
+                let is_epoch_link = state.network().epoch_for_link(block.link()).is_some();
+                Some(state_subtype(&block, &previous_balance, is_epoch_link))
+            }
+            _ => None,
+        };
+
+        Ok(BlockInfoResponse {
+            block_account: block.account().to_address(),
+            amount,
+            balance,
+            height,
+            // We don't keep track of when blocks arrived.
+            local_timestamp: Utc.timestamp(0, 0),
+            confirmed,
+            subtype,
+            contents: block.to_holder().map_err(rpc_error)?,
+        })
+    }
+}
+
 impl BlockInfoRequest {
     pub fn new(hash: BlockHash) -> Self {
         Self {
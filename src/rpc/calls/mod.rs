@@ -16,12 +16,13 @@ mod block_confirm;
 mod block_count;
 mod block_create;
 mod block_info;
+mod node_id;
 mod peers;
 mod process;
 mod work_validate;
 
 #[cfg(feature = "node")]
-use crate::node::NodeCommandSender;
+use crate::node::{ArcState, NodeCommandSender};
 
 #[cfg(feature = "node")]
 use crate::Result;
@@ -48,7 +49,8 @@ pub use block_count::{BlockCountRequest, BlockCountResponse};
 pub use block_create::{BlockCreateRequest, BlockCreateResponse};
 pub use block_info::{BlockInfoRequest, BlockInfoResponse};
 use clap::Clap;
-pub use peers::{DetailedPeerInfo, Peers, PeersRequest, PeersResponse};
+pub use node_id::{NodeIdRequest, NodeIdResponse};
+pub use peers::{DetailedPeerInfo, NetType, Peers, PeersRequest, PeersResponse};
 pub use process::{ProcessRequest, ProcessResponse};
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt::Display;
@@ -64,6 +66,15 @@ pub trait NodeHandler {
     async fn handle(&self, node_tx: NodeCommandSender) -> Result<Self::Response>;
 }
 
+/// For calls that only need to read the ledger.
+#[cfg(feature = "node")]
+#[async_trait]
+pub trait StateHandler {
+    type Response: Serialize;
+
+    async fn handle(&self, state: ArcState) -> Result<Self::Response>;
+}
+
 #[derive(Debug, Clap, Deserialize)]
 #[serde(tag = "action", rename_all = "snake_case")]
 pub enum RpcCommand {
@@ -85,6 +96,7 @@ pub enum RpcCommand {
     BlockCreate(BlockCreateRequest),
     BlockInfo(BlockInfoRequest),
     BlockConfirm(BlockConfirmRequest),
+    NodeId(NodeIdRequest),
     Peers(PeersRequest),
     Process(ProcessRequest),
     WorkValidate(WorkValidateRequest),
@@ -0,0 +1,79 @@
+#[cfg(feature = "node")]
+use crate::node::{node_id::node_id_string, NodeCommand, NodeCommandSender};
+
+#[cfg(feature = "node")]
+use crate::rpc::NodeHandler;
+
+use crate::rpc::client::{RPCClient, RPCRequest};
+use crate::{Address, Public, Result};
+use async_trait::async_trait;
+use clap::Clap;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clap)]
+pub struct NodeIdRequest {}
+
+#[async_trait]
+impl RPCRequest for &NodeIdRequest {
+    type Response = NodeIdResponse;
+
+    fn action(&self) -> &str {
+        "node_id"
+    }
+
+    async fn call(&self, client: &RPCClient) -> Result<NodeIdResponse> {
+        client.rpc(self).await
+    }
+}
+
+#[cfg(feature = "node")]
+#[async_trait]
+impl NodeHandler for &NodeIdRequest {
+    type Response = NodeIdResponse;
+
+    async fn handle(&self, node_tx: NodeCommandSender) -> Result<NodeIdResponse> {
+        use tokio::sync::oneshot;
+        let (tx, rx) = oneshot::channel();
+        node_tx.send(NodeCommand::NodeId(tx)).await.expect("TODO");
+        let public = rx
+            .await
+            .expect("TODO")
+            .map_err(|err| crate::Error::RPCError(format!("{:?}", err)))?;
+        Ok(NodeIdResponse {
+            as_account: public.to_address(),
+            node_id: node_id_string(&public),
+            public,
+        })
+    }
+}
+
+/// The private key is deliberately not included, unlike older versions of the reference node.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct NodeIdResponse {
+    public: Public,
+    as_account: Address,
+    node_id: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn decode() {
+        let s = r#" {
+            "public": "2A8B4F5F6B0F8A0A1F7E1E5D87EEB9D4FD2ADD09A2D5CA94B2C3C8A1BA5D12E7",
+            "as_account": "nano_1cndbxhpp5wc3ahqw9kxizqdmo9x7dgimaposccd7iyan8x7t6q93fo3z9pf",
+            "node_id": "node_1cndbxhpp5wc3ahqw9kxizqdmo9x7dgimaposccd7iyan8x7t6q93fo3z9pf"
+        }
+        "#;
+
+        let r = serde_json::from_str::<NodeIdResponse>(s).unwrap();
+        let public =
+            Public::from_str("2A8B4F5F6B0F8A0A1F7E1E5D87EEB9D4FD2ADD09A2D5CA94B2C3C8A1BA5D12E7")
+                .unwrap();
+        assert_eq!(r.public, public);
+        assert_eq!(r.as_account, public.to_address());
+    }
+}
@@ -43,7 +43,11 @@ impl NodeHandler for &PeersRequest {
     async fn handle(&self, node_tx: NodeCommandSender) -> Result<PeersResponse> {
         use tokio::sync::oneshot;
         let (tx, rx) = oneshot::channel();
-        node_tx.send(NodeCommand::PeerInfo(tx)).await.expect("TODO");
+        let details = self.peer_details.unwrap_or(false);
+        node_tx
+            .send(NodeCommand::PeerInfo(details, tx))
+            .await
+            .expect("TODO");
         Ok(PeersResponse {
             peers: rx.await.expect("TODO"),
         })
@@ -74,6 +78,16 @@ pub struct DetailedPeerInfo {
     net_type: NetType,
 }
 
+impl DetailedPeerInfo {
+    pub fn new(protocol_version: Version, node_id: String, net_type: NetType) -> Self {
+        Self {
+            protocol_version,
+            node_id,
+            net_type,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum NetType {
@@ -45,6 +45,7 @@ impl RPCClientOpts {
             RpcCommand::BlockCount(c) => self.show(c).await?,
             RpcCommand::BlockCreate(c) => self.show(c).await?,
             RpcCommand::BlockInfo(c) => self.show(c).await?,
+            RpcCommand::NodeId(c) => self.show(c).await?,
             RpcCommand::Peers(c) => self.show(c).await?,
             RpcCommand::Process(c) => self.show(c).await?,
             RpcCommand::WorkValidate(c) => self.show(c).await?,
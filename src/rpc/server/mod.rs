@@ -1,6 +1,6 @@
 use crate::node::{ArcState, NodeCommandReceiver, NodeCommandSender};
 use crate::rpc::client::RPCError;
-use crate::rpc::{NodeHandler, RpcCommand};
+use crate::rpc::{NodeHandler, RpcCommand, StateHandler};
 use crate::Result;
 use serde::Serialize;
 use tokio::sync::mpsc;
@@ -11,16 +11,23 @@ use warp::Filter;
 pub struct RPCServer {
     state: ArcState,
     node_cmd_tx: NodeCommandSender,
+
+    /// Listened to on localhost only.
+    port: u16,
 }
 
 impl RPCServer {
-    pub fn new_with_channel(state: ArcState) -> (Self, NodeCommandReceiver) {
-        let (tx, rx) = mpsc::channel(100);
-        let s = Self {
-            node_cmd_tx: tx,
+    pub fn new(state: ArcState, node_cmd_tx: NodeCommandSender, port: u16) -> Self {
+        Self {
             state,
-        };
-        (s, rx)
+            node_cmd_tx,
+            port,
+        }
+    }
+
+    pub fn new_with_channel(state: ArcState, port: u16) -> (Self, NodeCommandReceiver) {
+        let (tx, rx) = mpsc::channel(100);
+        (Self::new(state, tx, port), rx)
     }
 
     pub async fn run(self) -> anyhow::Result<()> {
@@ -32,13 +39,12 @@ impl RPCServer {
             .and(warp::body::json())
             .and_then(Self::handle);
 
-        // TODO: Configurable
-        warp::serve(rpc).run(([127, 0, 0, 1], 7076)).await;
+        warp::serve(rpc).run(([127, 0, 0, 1], self.port)).await;
         Ok(())
     }
 
     async fn handle(
-        _state: ArcState,
+        state: ArcState,
         node_tx: NodeCommandSender,
         cmd: RpcCommand,
     ) -> std::result::Result<Box<dyn warp::Reply>, warp::Rejection> {
@@ -53,6 +59,11 @@ impl RPCServer {
             //     network_receive_minimum: Difficulty::new(4),
             // }),
             // RpcCommand::Peers(c) => json_result(handle_peers(state, tx, c).await),
+            RpcCommand::AccountsPending(c) => json_result(c.handle(state).await),
+            RpcCommand::AccountWeight(c) => json_result(c.handle(state).await),
+            RpcCommand::BlockConfirm(c) => json_result(c.handle(node_tx).await),
+            RpcCommand::BlockInfo(c) => json_result(c.handle(state).await),
+            RpcCommand::NodeId(c) => json_result(c.handle(node_tx).await),
             RpcCommand::Peers(c) => json_result(c.handle(node_tx).await),
             // RpcCommand::Process(c) => json_result(handle_process(state, tx, c).await),
             action => json_result(Ok(RPCError {
@@ -1,18 +1,34 @@
 use crate::Error;
 use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 
 /// Network version of a node.
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub enum Version {
     V18 = 18,
     V19 = 19,
+
+    /// Handshake responses can include a salt and the genesis hash (handshake v2).
+    V20 = 20,
+}
+
+impl Version {
+    /// The oldest version we can talk to.
+    pub const MIN: Version = Version::V18;
+
+    /// The newest version we understand, which we use when the peer also supports it.
+    pub const MAX: Version = Version::V20;
+
+    pub fn supports_handshake_v2(&self) -> bool {
+        *self >= Version::V20
+    }
 }
 
 impl Display for Version {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.to_string()[1..].to_owned())
+        write!(f, "{}", *self as u8)
     }
 }
 
@@ -23,7 +39,16 @@ impl FromStr for Version {
         Ok(match s {
             "18" => Version::V18,
             "19" => Version::V19,
+            "20" => Version::V20,
             v => return Err(Error::InvalidVersion(v.into())),
         })
     }
 }
+
+impl TryFrom<u8> for Version {
+    type Error = Error;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        Self::from_str(&v.to_string())
+    }
+}